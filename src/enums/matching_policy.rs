@@ -0,0 +1,17 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum MatchingPolicy {
+    #[default]
+    PriceTime,                  // Strict FIFO within a price level
+    ProRata { min_lot: u64 }    // Allocate proportionally to resting size, in multiples of min_lot
+}
+
+impl Display for MatchingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PriceTime => write!(f, "Price Time"),
+            Self::ProRata { min_lot } => write!(f, "Pro Rata (min lot {min_lot})")
+        }
+    }
+}
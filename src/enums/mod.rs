@@ -1,5 +1,10 @@
+pub mod matching_policy;
 pub mod order_book_errors;
+pub mod order_book_event;
+pub mod order_outcome;
 pub mod order_side;
 pub mod order_status;
 pub mod order_type;
-pub mod symbol;
\ No newline at end of file
+pub mod symbol;
+pub mod time_in_force;
+pub mod trigger_direction;
\ No newline at end of file
@@ -1,16 +1,30 @@
-use std::fmt::{Display, Debug};
+use std::{error::Error, fmt::{Display, Debug}};
 
-use crate::enums::symbol::Symbol;
+use crate::models::order_id::OrderId;
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub enum OrderBookError {
     InvalidTick(u32),
     PriceOutOfRange,
     OrderNotFound,
-    SymbolNotFound(Symbol),
+    SymbolNotFound(String),
     NonLimitOrderRestAttempt,
     CannotFillCompletely,
-    InsufficientLiquidity,
+    NoLiquidity,
+    MissingStopPrice,
+    DuplicateOrderId(OrderId),
+    InvalidQuantity,
+    OrderSizeOutOfRange { min: u64, max: u64, got: u64 },
+    InvalidLotSize(u64),
+    InvalidQuantityScale(u32),
+    PriceBandViolation { price: u32, max_deviation_bps: u32 },
+    InvalidModification,
+    LevelOrderLimitReached(usize),
+    BookFull(usize),
+    ReduceOnlyViolation { position: i64, got: u64 },
+    InvalidConfigData(String),
+    CorruptBook(String),
+    QuantityUnderflow,
     Other(String)
 }
 
@@ -21,9 +35,23 @@ impl Display for OrderBookError {
             Self::PriceOutOfRange => write!(f, "The specified price was outside of the valid range."),
             Self::OrderNotFound => write!(f, "The specified order was not found."),
             Self::SymbolNotFound(symbol) => write!(f, "The symbol '{symbol}' does not yet exist in the order book manager."),
-            Self::NonLimitOrderRestAttempt => write!(f, "An attempt was made to rest a non-limit order. Limit orders are the only supported order that can be resting."),
+            Self::NonLimitOrderRestAttempt => write!(f, "An attempt was made to rest a non-limit order. Limit and All or None orders are the only supported orders that can be resting."),
             Self::CannotFillCompletely => write!(f, "A Fill or Kill order could not be completely filled. The order has been cancelled."),
-            Self::InsufficientLiquidity => write!(f, "There is insufficient liquidity in the specified security to entirely fill this order."),
+            Self::NoLiquidity => write!(f, "There is no liquidity on the opposite side of the book to fill this order."),
+            Self::MissingStopPrice => write!(f, "A Stop or Stop Limit order was submitted without a stop price."),
+            Self::DuplicateOrderId(order_id) => write!(f, "An order with the id '{order_id}' is already live in the order book."),
+            Self::InvalidQuantity => write!(f, "Order quantity must be greater than zero."),
+            Self::OrderSizeOutOfRange { min, max, got } => write!(f, "An order quantity of {got} is outside the allowed range of {min} to {max}."),
+            Self::InvalidLotSize(lot_size) => write!(f, "Order quantity must be a multiple of the lot size {lot_size}."),
+            Self::InvalidQuantityScale(qty_scale) => write!(f, "Order quantity has more than the {qty_scale} decimal places the book trades in."),
+            Self::PriceBandViolation { price, max_deviation_bps } => write!(f, "A price of {price} is more than {max_deviation_bps} basis points from the current mid."),
+            Self::InvalidModification => write!(f, "An amend may not change the order's side or type."),
+            Self::LevelOrderLimitReached(limit) => write!(f, "The price level already holds the maximum of {limit} resting orders."),
+            Self::BookFull(limit) => write!(f, "The order book already holds the maximum of {limit} resting orders."),
+            Self::ReduceOnlyViolation { position, got } => write!(f, "A reduce-only order of quantity {got} would not reduce the current position of {position}."),
+            Self::InvalidConfigData(reason) => write!(f, "The order book config is invalid: {reason}."),
+            Self::CorruptBook(reason) => write!(f, "The order book state is inconsistent: {reason}."),
+            Self::QuantityUnderflow => write!(f, "A fill would have taken more quantity than an order had left."),
             Self::Other(msg) => write!(f, "{msg}")
         }
     }
@@ -36,10 +64,47 @@ impl Debug for OrderBookError {
             Self::PriceOutOfRange => write!(f, "The specified price was outside of the valid range."),
             Self::OrderNotFound => write!(f, "The specified order was not found."),
             Self::SymbolNotFound(symbol) => write!(f, "The symbol '{symbol}' does not yet exist in the order book manager."),
-            Self::NonLimitOrderRestAttempt => write!(f, "An attempt was made to rest a non-limit order. Limit orders are the only supported order that can be resting."),
+            Self::NonLimitOrderRestAttempt => write!(f, "An attempt was made to rest a non-limit order. Limit and All or None orders are the only supported orders that can be resting."),
             Self::CannotFillCompletely => write!(f, "A Fill or Kill order could not be completely filled. The order has been cancelled."),
-            Self::InsufficientLiquidity => write!(f, "There is insufficient liquidity in the specified security to entirely fill this order."),
+            Self::NoLiquidity => write!(f, "There is no liquidity on the opposite side of the book to fill this order."),
+            Self::MissingStopPrice => write!(f, "A Stop or Stop Limit order was submitted without a stop price."),
+            Self::DuplicateOrderId(order_id) => write!(f, "An order with the id '{order_id}' is already live in the order book."),
+            Self::InvalidQuantity => write!(f, "Order quantity must be greater than zero."),
+            Self::OrderSizeOutOfRange { min, max, got } => write!(f, "An order quantity of {got} is outside the allowed range of {min} to {max}."),
+            Self::InvalidLotSize(lot_size) => write!(f, "Order quantity must be a multiple of the lot size {lot_size}."),
+            Self::InvalidQuantityScale(qty_scale) => write!(f, "Order quantity has more than the {qty_scale} decimal places the book trades in."),
+            Self::PriceBandViolation { price, max_deviation_bps } => write!(f, "A price of {price} is more than {max_deviation_bps} basis points from the current mid."),
+            Self::InvalidModification => write!(f, "An amend may not change the order's side or type."),
+            Self::LevelOrderLimitReached(limit) => write!(f, "The price level already holds the maximum of {limit} resting orders."),
+            Self::BookFull(limit) => write!(f, "The order book already holds the maximum of {limit} resting orders."),
+            Self::ReduceOnlyViolation { position, got } => write!(f, "A reduce-only order of quantity {got} would not reduce the current position of {position}."),
+            Self::InvalidConfigData(reason) => write!(f, "The order book config is invalid: {reason}."),
+            Self::CorruptBook(reason) => write!(f, "The order book state is inconsistent: {reason}."),
+            Self::QuantityUnderflow => write!(f, "A fill would have taken more quantity than an order had left."),
             Self::Other(msg) => write!(f, "{msg}"),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Error for OrderBookError {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn into_boxed_error(result: Result<(), OrderBookError>) -> Result<(), Box<dyn Error>> {
+        result?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_book_error_correctly_converts_into_boxed_error() {
+        let err = OrderBookError::Other("feed disconnected".to_string());
+
+        let boxed_err = into_boxed_error(Err(err.clone())).unwrap_err();
+
+        assert_eq!(boxed_err.to_string(), err.to_string());
+        assert!(into_boxed_error(Ok(())).is_ok());
+    }
+}
@@ -0,0 +1,15 @@
+use crate::{enums::order_side::OrderSide, models::{order::Order, order_id::OrderId, user_id::UserId}};
+
+// Every state change the book makes, in the order it made them. Quantities on fill events are the traded amount
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderBookEvent {
+    OrderAccepted { order: Order, timestamp: u128 },
+    OrderRested { order_id: OrderId, order_side: OrderSide, price: u32, quantity: u64, timestamp: u128 },
+    OrderFilled { order_id: OrderId, price: u32, quantity: u64, timestamp: u128 },
+    OrderPartiallyFilled { order_id: OrderId, price: u32, quantity: u64, remaining_quantity: u64, timestamp: u128 },
+    OrderModified { order_id: OrderId, quantity: u64, timestamp: u128 },     // In-place size reduction
+    OrderCanceled { order_id: OrderId, remaining_quantity: u64, timestamp: u128 },
+    OrderRejected { order_id: OrderId, reason: String, timestamp: u128 },
+    OrderExpired { order_id: OrderId, remaining_quantity: u64, timestamp: u128 },
+    PositionSet { user_id: UserId, position: i64, timestamp: u128 }        // Caller-owned position that reduce-only orders are checked against
+}
@@ -0,0 +1,22 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderOutcome {
+    Rested,                     // Nothing traded, the whole order is resting (or parked, for stop orders)
+    PartiallyFilledResting,     // Some quantity traded and the remainder is resting
+    FullyFilled,                // Everything traded on arrival
+    Canceled,                   // Left the book with quantity unfilled, e.g. an IOC remainder or a market order that ran out of book
+    Rejected                    // Never accepted, add_order returned an error
+}
+
+impl Display for OrderOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rested => write!(f, "Rested"),
+            Self::PartiallyFilledResting => write!(f, "Partially Filled Resting"),
+            Self::FullyFilled => write!(f, "Fully Filled"),
+            Self::Canceled => write!(f, "Canceled"),
+            Self::Rejected => write!(f, "Rejected")
+        }
+    }
+}
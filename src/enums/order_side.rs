@@ -6,6 +6,24 @@ pub enum OrderSide {
     Sell
 }
 
+// Wire tags used by the binary codec
+impl OrderSide {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Buy => 0,
+            Self::Sell => 1
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Buy),
+            1 => Some(Self::Sell),
+            _ => None
+        }
+    }
+}
+
 impl Display for OrderSide {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -11,6 +11,34 @@ pub enum OrderStatus {
     Expired             // Time limit reached
 }
 
+// Wire tags used by the binary codec
+impl OrderStatus {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::PendingNew => 0,
+            Self::Active => 1,
+            Self::PartiallyFilled => 2,
+            Self::Filled => 3,
+            Self::Canceled => 4,
+            Self::Rejected => 5,
+            Self::Expired => 6
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::PendingNew),
+            1 => Some(Self::Active),
+            2 => Some(Self::PartiallyFilled),
+            3 => Some(Self::Filled),
+            4 => Some(Self::Canceled),
+            5 => Some(Self::Rejected),
+            6 => Some(Self::Expired),
+            _ => None
+        }
+    }
+}
+
 impl Display for OrderStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
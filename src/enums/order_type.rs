@@ -1,11 +1,51 @@
 use std::fmt::Display;
 
+use rust_decimal::Decimal;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OrderType {
     Limit,
     Market,
-    ImmediateOrCancel,
-    FillOrKill
+    Stop,
+    StopLimit,
+    AllOrNone,
+    MarketNotional(Decimal),    // Spends up to this much cash instead of trading a fixed quantity
+    MidpointPeg,                // Trades only against other midpoint orders, at the mid of the displayed BBO
+    MarketIfTouched,            // Becomes a Market order once the price reaches its trigger from the favorable side
+    LimitIfTouched              // Becomes a Limit order once the price reaches its trigger from the favorable side
+}
+
+// Wire tags used by the binary codec. The MarketNotional amount travels separately. Tags 2 and 3 were Immediate or
+// Cancel and Fill or Kill before those moved to TimeInForce, and stay retired
+impl OrderType {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Limit => 0,
+            Self::Market => 1,
+            Self::Stop => 4,
+            Self::StopLimit => 5,
+            Self::AllOrNone => 6,
+            Self::MarketNotional(_) => 7,
+            Self::MidpointPeg => 8,
+            Self::MarketIfTouched => 9,
+            Self::LimitIfTouched => 10
+        }
+    }
+
+    pub fn from_tag(tag: u8, notional: Decimal) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Limit),
+            1 => Some(Self::Market),
+            4 => Some(Self::Stop),
+            5 => Some(Self::StopLimit),
+            6 => Some(Self::AllOrNone),
+            7 => Some(Self::MarketNotional(notional)),
+            8 => Some(Self::MidpointPeg),
+            9 => Some(Self::MarketIfTouched),
+            10 => Some(Self::LimitIfTouched),
+            _ => None
+        }
+    }
 }
 
 impl Display for OrderType {
@@ -13,8 +53,13 @@ impl Display for OrderType {
         match self {
             Self::Limit => write!(f, "Limit"),
             Self::Market => write!(f, "Market"),
-            Self::ImmediateOrCancel => write!(f, "Immediate or Cancel"),
-            Self::FillOrKill => write!(f, "Fill or Kill")
+            Self::Stop => write!(f, "Stop"),
+            Self::StopLimit => write!(f, "Stop Limit"),
+            Self::AllOrNone => write!(f, "All or None"),
+            Self::MarketNotional(notional) => write!(f, "Market Notional ({notional})"),
+            Self::MidpointPeg => write!(f, "Midpoint Peg"),
+            Self::MarketIfTouched => write!(f, "Market If Touched"),
+            Self::LimitIfTouched => write!(f, "Limit If Touched")
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    Gtc,            // Rests until filled or canceled
+    Day,            // Rests until the session ends
+    Gtd(u128),      // Rests until this timestamp
+    Ioc,            // Trades what it can on arrival, the rest is canceled
+    Fok             // Trades in full on arrival or not at all
+}
+
+// Wire tags used by the binary codec. The Gtd expiry travels separately
+impl TimeInForce {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Gtc => 0,
+            Self::Day => 1,
+            Self::Gtd(_) => 2,
+            Self::Ioc => 3,
+            Self::Fok => 4
+        }
+    }
+
+    pub fn from_tag(tag: u8, expiry: u128) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Gtc),
+            1 => Some(Self::Day),
+            2 => Some(Self::Gtd(expiry)),
+            3 => Some(Self::Ioc),
+            4 => Some(Self::Fok),
+            _ => None
+        }
+    }
+
+    // Whether an unfilled remainder may rest in the book
+    pub fn rests(&self) -> bool {
+        matches!(self, Self::Gtc | Self::Day | Self::Gtd(_))
+    }
+}
+
+impl Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gtc => write!(f, "Good Till Canceled"),
+            Self::Day => write!(f, "Day"),
+            Self::Gtd(expiry) => write!(f, "Good Till Date ({expiry})"),
+            Self::Ioc => write!(f, "Immediate or Cancel"),
+            Self::Fok => write!(f, "Fill or Kill")
+        }
+    }
+}
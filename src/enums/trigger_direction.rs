@@ -0,0 +1,25 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerDirection {
+    Rising,     // Activates once a trade prints at or above the trigger price
+    Falling     // Activates once a trade prints at or below the trigger price
+}
+
+impl TriggerDirection {
+    pub fn is_triggered(&self, trigger_price: u32, trade_price: u32) -> bool {
+        match self {
+            Self::Rising => trade_price >= trigger_price,
+            Self::Falling => trade_price <= trigger_price
+        }
+    }
+}
+
+impl Display for TriggerDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rising => write!(f, "Rising"),
+            Self::Falling => write!(f, "Falling")
+        }
+    }
+}
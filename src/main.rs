@@ -1,9 +1,9 @@
-use std::{collections::HashSet, time::Instant};
+use std::{collections::HashSet, env, time::Instant};
 
 use rand::{Rng, SeedableRng, rngs::StdRng};
 use rand_distr::{Normal, Distribution};
 
-use crate::{enums::{order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, symbol::Symbol}, models::{order::Order, order_book_config::OrderBookConfig}, order_book::OrderBook, order_book_manager::OrderBookManager};
+use crate::{enums::{order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, symbol::Symbol, time_in_force::TimeInForce}, models::{bench_stats::{BenchStats, LatencySummary}, order::Order, order_book_config::OrderBookConfig, order_id::OrderId, user_id::UserId}, order_book::OrderBook, order_book_manager::SymbolOrderBookManager};
 
 pub mod enums;
 pub mod models;
@@ -12,16 +12,20 @@ pub mod order_book;
 pub mod utils;
 
 fn main() {
-    check_order_book_latencies();
-    //check_order_book_manager_latencies();
+    match env::args().nth(1).as_deref() {
+        Some("manager") => check_order_book_manager_latencies(),
+        Some("partial-fills") => check_partial_fill_latencies(),
+        _ => check_order_book_latencies()
+    }
 }
 
 fn check_order_book_latencies() {
     let config = OrderBookConfig {
         min_price: 0,           // $0
-        max_price: 10_000_00,   // $10,000
+        max_price: 1_000_000,   // $10,000
         tick_size: 1,
         queue_size: 100,
+        ..Default::default()
     };
 
     let mut order_book = OrderBook::new(config);
@@ -59,28 +63,34 @@ fn check_order_book_latencies() {
         tick_set.insert(price_ticks);
 
         orders.push(Order {
-            order_id: i as u64,
+            order_id: OrderId(i as u64),
             order_type: OrderType::Limit,
             order_status: OrderStatus::PendingNew,
             order_side: side,
-            user_id: rng.random_range(0..1000),
+            user_id: UserId(rng.random_range(0..1000)),
             price,
             quantity: qty,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc,
         });
     }
 
     println!("Price tick range: {} → {}", min_tick, max_tick);
     println!("Total distinct price levels: {}", tick_set.len());
     println!(
-        "Approx. price range: ${:.2} → ${:.2}",
-        min_tick as f32 * 0.01,
-        max_tick as f32 * 0.01
+        "Price range: ${} → ${}",
+        order_book.config.format_price(min_tick as u32),
+        order_book.config.format_price(max_tick as u32)
     );
 
     // -------------------------------------------------
     // Benchmark with latency collection
     // -------------------------------------------------
-    let mut latencies = Vec::with_capacity(num_orders);
+    let mut bench_stats = BenchStats::default();
+    bench_stats.add_order.reserve(num_orders);
 
     let total_start = Instant::now();
 
@@ -88,24 +98,87 @@ fn check_order_book_latencies() {
         let start = Instant::now();
         order_book.add_order(order).unwrap();
         let end = Instant::now();
-        latencies.push((end - start).as_nanos() as u64);
+        bench_stats.add_order.push((end - start).as_nanos() as u64);
     }
 
     let total_end = Instant::now();
 
-    // -------------------------------------------------
-    // Compute percentiles
-    // -------------------------------------------------
-    latencies.sort_unstable();
+    println!("Latency statistics:");
+    println!("{}", LatencySummary::from_samples(&bench_stats.add_order));
+    println!("Total time elapsed: {}ms", (total_end - total_start).as_millis());
+}
+
+// Small aggressors against a few deep resting orders, so nearly every match partially fills the front of a level
+fn check_partial_fill_latencies() {
+    let config = OrderBookConfig {
+        min_price: 0,
+        max_price: 1_000_000,
+        tick_size: 1,
+        queue_size: 100,
+        ..Default::default()
+    };
+
+    let mut order_book = OrderBook::new(config);
+
+    let num_orders = 1_000_000;
+    let base_ticks = 5000;
+    let resting_levels = 10;
+
+    for i in 0..resting_levels {
+        order_book.add_order(Order {
+            order_id: OrderId(i),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: base_ticks + i as u32,
+            quantity: u64::MAX / (2 * resting_levels),
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc,
+        }).unwrap();
+    }
+
+    let mut rng = StdRng::seed_from_u64(12345);
 
-    let n = latencies.len();
-    let p50 = latencies[n * 50 / 100];
-    let p90 = latencies[n * 90 / 100];
-    let p99 = latencies[n * 99 / 100];
-    let avg = latencies.iter().sum::<u64>() / n as u64;
+    let mut orders = Vec::with_capacity(num_orders);
 
+    for i in 0..num_orders {
+        orders.push(Order {
+            order_id: OrderId(resting_levels + i as u64),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(rng.random_range(1..1000)),
+            price: base_ticks + resting_levels as u32 - 1,
+            quantity: rng.random_range(1..1000),
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc,
+        });
+    }
+
+    let mut bench_stats = BenchStats::default();
+    bench_stats.add_order.reserve(num_orders);
+
+    let total_start = Instant::now();
+
+    for order in orders {
+        let start = Instant::now();
+        order_book.add_order(order).unwrap();
+        let end = Instant::now();
+        bench_stats.add_order.push((end - start).as_nanos() as u64);
+    }
+
+    let total_end = Instant::now();
+
+    println!("Trades: {}", order_book.trade_history.len());
     println!("Latency statistics:");
-    println!("p50: {p50}ns\tp90: {p90}ns\tp99: {p99}ns\tavg: {avg}ns\tsamples: {n}");
+    println!("{}", LatencySummary::from_samples(&bench_stats.add_order));
     println!("Total time elapsed: {}ms", (total_end - total_start).as_millis());
 }
 
@@ -113,12 +186,13 @@ fn check_order_book_manager_latencies() {
     // Configuration - same for all symbols for simplicity
     let config = OrderBookConfig {
         min_price: 0,
-        max_price: 10_000_00,
+        max_price: 1_000_000,
         tick_size: 1,
         queue_size: 100,
+        ..Default::default()
     };
 
-    let mut manager = OrderBookManager::new();
+    let mut manager = SymbolOrderBookManager::new();
     
     // Define symbols to benchmark
     let symbols = vec![
@@ -182,22 +256,27 @@ fn check_order_book_manager_latencies() {
         tick_set.insert(price_ticks);
 
         orders.push((symbol, Order {
-            order_id: i as u64,
+            order_id: OrderId(i as u64),
             order_type: OrderType::Limit,
             order_status: OrderStatus::PendingNew,
             order_side: side,
-            user_id: rng.random_range(0..1000),
+            user_id: UserId(rng.random_range(0..1000)),
             price,
             quantity: qty,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc,
         }));
     }
 
     println!("Price tick range: {} → {}", min_tick, max_tick);
     println!("Total distinct price levels: {}", tick_set.len());
     println!(
-        "Approx. price range: ${:.2} → ${:.2}",
-        min_tick as f32 * 0.01,
-        max_tick as f32 * 0.01
+        "Price range: ${} → ${}",
+        config.format_price(min_tick as u32),
+        config.format_price(max_tick as u32)
     );
     
     println!("\nOrders per symbol:");
@@ -209,26 +288,20 @@ fn check_order_book_manager_latencies() {
         );
     }
 
-    let mut latencies = Vec::with_capacity(num_orders);
+    let mut bench_stats = BenchStats::default();
+    bench_stats.add_order.reserve(num_orders);
     let total_start = Instant::now();
 
     for (symbol, order) in orders {
         let start = Instant::now();
         manager.add_order(symbol, order).unwrap();
         let end = Instant::now();
-        latencies.push((end - start).as_nanos() as u64);
+        bench_stats.add_order.push((end - start).as_nanos() as u64);
     }
 
     let total_end = Instant::now();
-    latencies.sort_unstable();
-
-    let n = latencies.len();
-    let p50 = latencies[n * 50 / 100];
-    let p90 = latencies[n * 90 / 100];
-    let p99 = latencies[n * 99 / 100];
-    let avg = latencies.iter().sum::<u64>() / n as u64;
 
     println!("\nLatency Statistics:");
-    println!("p50: {p50}ns\tp90: {p90}ns\tp99: {p99}ns\tavg: {avg}ns\tsamples: {n}");
+    println!("{}", LatencySummary::from_samples(&bench_stats.add_order));
     println!("Total time elapsed: {}ms", (total_end - total_start).as_millis());
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bar {
+    pub start_ts: u128,
+    pub open: u32,
+    pub high: u32,
+    pub low: u32,
+    pub close: u32,
+    pub volume: u64
+}
@@ -0,0 +1,7 @@
+// The displayed best prices as of a BBO change. None means that side of the book had no displayed orders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BboTick {
+    pub timestamp: u128,
+    pub best_bid: Option<u32>,
+    pub best_ask: Option<u32>
+}
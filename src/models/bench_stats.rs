@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::fmt::Display;
+
+#[derive(Debug, Default)]
 pub struct BenchStats {
     pub fill_order: Vec<u64>,
     pub add_order: Vec<u64>,
@@ -12,19 +14,142 @@ pub struct BenchStats {
     pub can_fill_completely: Vec<u64>,
 }
 
-impl Default for BenchStats {
-    fn default() -> Self {
-        BenchStats { 
-            fill_order: vec![],
-            add_order: vec![], 
-            execute_fill_by_order_type: vec![], 
-            fill_limit_order: vec![], 
-            fill_market_order: vec![], 
-            fill_immediate_or_cancel_order: vec![],
-            fill_fill_or_kill_order: vec![], 
-            match_order_against_book: vec![], 
-            rest_remaining_limit_order: vec![], 
-            can_fill_completely: vec![]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LatencySummary {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub avg: u64,
+    pub max: u64,
+    pub count: usize
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchSummary {
+    pub functions: Vec<(&'static str, LatencySummary)>
+}
+
+impl BenchStats {
+    // Nearest-rank percentile (index n * p / 100 of the sorted samples), p in 0..=100
+    pub fn percentile(&self, which: &[u64], p: f64) -> u64 {
+        let mut samples = which.to_vec();
+        samples.sort_unstable();
+
+        LatencySummary::percentile_of_sorted(&samples, p)
+    }
+
+    // Hands back every sample recorded so far and starts a fresh window, without reallocating the book
+    pub fn drain(&mut self) -> BenchStats {
+        std::mem::take(self)
+    }
+
+    pub fn summary(&self) -> BenchSummary {
+        let functions = [
+            ("fill_order", &self.fill_order),
+            ("add_order", &self.add_order),
+            ("execute_fill_by_order_type", &self.execute_fill_by_order_type),
+            ("fill_limit_order", &self.fill_limit_order),
+            ("fill_market_order", &self.fill_market_order),
+            ("fill_immediate_or_cancel_order", &self.fill_immediate_or_cancel_order),
+            ("fill_fill_or_kill_order", &self.fill_fill_or_kill_order),
+            ("match_order_against_book", &self.match_order_against_book),
+            ("rest_remaining_limit_order", &self.rest_remaining_limit_order),
+            ("can_fill_completely", &self.can_fill_completely)
+        ];
+
+        BenchSummary {
+            functions: functions.into_iter()
+                .map(|(name, samples)| (name, LatencySummary::from_samples(samples)))
+                .collect()
         }
     }
-}
\ No newline at end of file
+}
+
+impl LatencySummary {
+    pub fn from_samples(samples: &[u64]) -> Self {
+        if samples.is_empty() {
+            return LatencySummary::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+
+        LatencySummary {
+            p50: Self::percentile_of_sorted(&sorted, 50.0),
+            p90: Self::percentile_of_sorted(&sorted, 90.0),
+            p99: Self::percentile_of_sorted(&sorted, 99.0),
+            avg: sorted.iter().sum::<u64>() / sorted.len() as u64,
+            max: sorted[sorted.len() - 1],
+            count: sorted.len()
+        }
+    }
+
+    fn percentile_of_sorted(sorted: &[u64], p: f64) -> u64 {
+        if sorted.is_empty() {
+            return 0;
+        }
+
+        let index = (sorted.len() as f64 * p.clamp(0.0, 100.0) / 100.0) as usize;
+        sorted[index.min(sorted.len() - 1)]
+    }
+}
+
+impl Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "p50: {}ns\tp90: {}ns\tp99: {}ns\tavg: {}ns\tmax: {}ns\tsamples: {}", self.p50, self.p90, self.p99, self.avg, self.max, self.count)
+    }
+}
+
+impl Display for BenchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<32}{:>10}{:>10}{:>10}{:>10}{:>10}{:>12}", "function", "p50", "p90", "p99", "avg", "max", "samples")?;
+
+        for (name, summary) in &self.functions {
+            writeln!(f, "{:<32}{:>10}{:>10}{:>10}{:>10}{:>10}{:>12}", name, summary.p50, summary.p90, summary.p99, summary.avg, summary.max, summary.count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for BenchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_percentile_correctly_computes_nearest_rank_percentiles() {
+        let bench_stats = BenchStats::default();
+        let samples: Vec<u64> = (1..=100).rev().collect();
+
+        assert_eq!(bench_stats.percentile(&samples, 50.0), 51);
+        assert_eq!(bench_stats.percentile(&samples, 90.0), 91);
+        assert_eq!(bench_stats.percentile(&samples, 99.0), 100);
+        assert_eq!(bench_stats.percentile(&samples, 100.0), 100);
+        assert_eq!(bench_stats.percentile(&samples, 0.0), 1);
+        assert_eq!(bench_stats.percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn test_summary_correctly_summarizes_each_tracked_function() {
+        let bench_stats = BenchStats {
+            add_order: vec![400, 100, 300, 200],
+            fill_order: vec![10],
+            ..Default::default()
+        };
+
+        let summary = bench_stats.summary();
+
+        assert_eq!(summary.functions.len(), 10);
+        assert_eq!(summary.functions[1], ("add_order", LatencySummary { p50: 300, p90: 400, p99: 400, avg: 250, max: 400, count: 4 }));
+        assert_eq!(summary.functions[0], ("fill_order", LatencySummary { p50: 10, p90: 10, p99: 10, avg: 10, max: 10, count: 1 }));
+        assert_eq!(summary.functions[2].1, LatencySummary::default());
+        assert!(bench_stats.to_string().contains("add_order"));
+    }
+}
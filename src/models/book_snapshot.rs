@@ -0,0 +1,14 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthLevel {
+    pub price: u32,
+    pub quantity: u64,
+    pub order_count: usize
+}
+
+pub type BestQuote = DepthLevel;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BookSnapshot {
+    pub bids: Vec<DepthLevel>,      // Best (highest) price first
+    pub asks: Vec<DepthLevel>       // Best (lowest) price first
+}
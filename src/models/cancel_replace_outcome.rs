@@ -0,0 +1,9 @@
+use crate::models::{order_fill::OrderFill, order_id::OrderId};
+
+// Result of a successful cancel_replace. fills are what the replacement traded on arrival
+#[derive(Debug, Clone)]
+pub struct CancelReplaceOutcome {
+    pub canceled_id: OrderId,
+    pub new_id: OrderId,
+    pub fills: Vec<OrderFill>
+}
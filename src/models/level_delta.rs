@@ -0,0 +1,10 @@
+use crate::enums::order_side::OrderSide;
+
+// The displayed state of a price level after it changed. A zero order count means the level is now empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelDelta {
+    pub side: OrderSide,
+    pub price: u32,
+    pub new_total_quantity: u64,
+    pub new_order_count: usize
+}
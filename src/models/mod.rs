@@ -1,4 +1,15 @@
+pub mod bar;
+pub mod bbo_tick;
 pub mod bench_stats;
+pub mod book_snapshot;
+pub mod cancel_replace_outcome;
+pub mod level_delta;
 pub mod order_book_config;
+pub mod order_book_stats;
+pub mod order_builder;
 pub mod order_fill;
-pub mod order;
\ No newline at end of file
+pub mod order_id;
+pub mod order;
+pub mod price_level;
+pub mod queue_position;
+pub mod user_id;
\ No newline at end of file
@@ -1,12 +1,222 @@
-use crate::enums::{order_side::OrderSide, order_status::OrderStatus, order_type::OrderType};
+use rust_decimal::Decimal;
+
+use crate::{enums::{order_book_errors::OrderBookError, order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, time_in_force::TimeInForce, trigger_direction::TriggerDirection}, models::{order_builder::OrderBuilder, order_id::OrderId, user_id::UserId}, utils::read_field};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Order {
-    pub order_id: u64,
+    pub order_id: OrderId,
     pub order_type: OrderType,
     pub order_status: OrderStatus,
     pub order_side: OrderSide,
-    pub user_id: u32,
+    pub user_id: UserId,
     pub price: u32,
-    pub quantity: i32
-}
\ No newline at end of file
+    pub quantity: u64,              // Remaining quantity
+    pub stop_price: Option<u32>,    // Trigger price for Stop / StopLimit / MarketIfTouched / LimitIfTouched orders
+    pub reduce_only: bool,          // Never rests, and may only shrink the user's position set via set_position
+    pub filled_quantity: u64,       // Cumulative quantity traded, kept across amends
+    pub hidden: bool,               // Rests and matches behind visible orders at its level, but is never displayed
+    pub time_in_force: TimeInForce  // How long any unfilled quantity stays live, independent of order_type
+}
+
+impl Order {
+    pub fn builder(order_id: OrderId, order_side: OrderSide) -> OrderBuilder {
+        OrderBuilder::new(order_id, order_side)
+    }
+
+    pub fn limit_buy(order_id: OrderId, user_id: UserId, price: u32, quantity: u64) -> Order {
+        OrderBuilder::new(order_id, OrderSide::Buy).user_id(user_id).price(price).quantity(quantity).build()
+    }
+
+    pub fn limit_sell(order_id: OrderId, user_id: UserId, price: u32, quantity: u64) -> Order {
+        OrderBuilder::new(order_id, OrderSide::Sell).user_id(user_id).price(price).quantity(quantity).build()
+    }
+
+    pub fn market_buy(order_id: OrderId, user_id: UserId, quantity: u64) -> Order {
+        OrderBuilder::new(order_id, OrderSide::Buy).order_type(OrderType::Market).user_id(user_id).quantity(quantity).build()
+    }
+
+    pub fn market_sell(order_id: OrderId, user_id: UserId, quantity: u64) -> Order {
+        OrderBuilder::new(order_id, OrderSide::Sell).order_type(OrderType::Market).user_id(user_id).quantity(quantity).build()
+    }
+
+    // Which way the price has to move to reach stop_price. Stops chase the move, buying as it rises and selling as
+    // it falls, while if-touched orders wait for a better price. None for orders that aren't held until triggered
+    pub fn trigger_direction(&self) -> Option<TriggerDirection> {
+        match (&self.order_type, &self.order_side) {
+            (OrderType::Stop | OrderType::StopLimit, OrderSide::Buy) => Some(TriggerDirection::Rising),
+            (OrderType::Stop | OrderType::StopLimit, OrderSide::Sell) => Some(TriggerDirection::Falling),
+            (OrderType::MarketIfTouched | OrderType::LimitIfTouched, OrderSide::Buy) => Some(TriggerDirection::Falling),
+            (OrderType::MarketIfTouched | OrderType::LimitIfTouched, OrderSide::Sell) => Some(TriggerDirection::Rising),
+            _ => None
+        }
+    }
+}
+
+const REDUCE_ONLY_FLAG: u8 = 1;
+const HIDDEN_FLAG: u8 = 1 << 1;
+const STOP_PRICE_FLAG: u8 = 1 << 2;
+
+// Fixed little-endian wire layout:
+// order_id u64 | type u8 | status u8 | side u8 | flags u8 | user_id u32 | price u32 | quantity u64 | stop_price u32 |
+// filled_quantity u64 | notional 16 bytes (only set for MarketNotional) | time_in_force u8 | expiry u128 (only set for Gtd)
+impl Order {
+    pub const ENCODED_LEN: usize = 73;
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+
+        let mut flags = 0;
+        if self.reduce_only {
+            flags |= REDUCE_ONLY_FLAG;
+        }
+        if self.hidden {
+            flags |= HIDDEN_FLAG;
+        }
+        if self.stop_price.is_some() {
+            flags |= STOP_PRICE_FLAG;
+        }
+
+        let notional = match self.order_type {
+            OrderType::MarketNotional(notional) => notional,
+            _ => Decimal::ZERO
+        };
+
+        let expiry = match self.time_in_force {
+            TimeInForce::Gtd(expiry) => expiry,
+            _ => 0
+        };
+
+        bytes[0..8].copy_from_slice(&self.order_id.0.to_le_bytes());
+        bytes[8] = self.order_type.tag();
+        bytes[9] = self.order_status.tag();
+        bytes[10] = self.order_side.tag();
+        bytes[11] = flags;
+        bytes[12..16].copy_from_slice(&self.user_id.0.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.price.to_le_bytes());
+        bytes[20..28].copy_from_slice(&self.quantity.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.stop_price.unwrap_or(0).to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.filled_quantity.to_le_bytes());
+        bytes[40..56].copy_from_slice(&notional.serialize());
+        bytes[56] = self.time_in_force.tag();
+        bytes[57..73].copy_from_slice(&expiry.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Order, OrderBookError> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(OrderBookError::Other(format!("An encoded order must be {} bytes, got {}.", Self::ENCODED_LEN, bytes.len())));
+        }
+
+        let flags = bytes[11];
+        if flags & !(REDUCE_ONLY_FLAG | HIDDEN_FLAG | STOP_PRICE_FLAG) != 0 {
+            return Err(OrderBookError::Other(format!("Unknown order flags {flags:#04x}.")));
+        }
+
+        // Scale lives in bits 16..24 of the first word and only the sign bit may be set alongside it
+        let notional_flags = u32::from_le_bytes(read_field(bytes, 40));
+        if notional_flags & 0x7F00_FFFF != 0 || (notional_flags >> 16) & 0xFF > 28 {
+            return Err(OrderBookError::Other("Malformed order notional.".to_string()));
+        }
+
+        let notional = Decimal::deserialize(read_field(bytes, 40));
+
+        let order_type = OrderType::from_tag(bytes[8], notional)
+            .ok_or_else(|| OrderBookError::Other(format!("Unknown order type tag {}.", bytes[8])))?;
+        let order_status = OrderStatus::from_tag(bytes[9])
+            .ok_or_else(|| OrderBookError::Other(format!("Unknown order status tag {}.", bytes[9])))?;
+        let order_side = OrderSide::from_tag(bytes[10])
+            .ok_or_else(|| OrderBookError::Other(format!("Unknown order side tag {}.", bytes[10])))?;
+        let time_in_force = TimeInForce::from_tag(bytes[56], u128::from_le_bytes(read_field(bytes, 57)))
+            .ok_or_else(|| OrderBookError::Other(format!("Unknown time in force tag {}.", bytes[56])))?;
+
+        let stop_price = u32::from_le_bytes(read_field(bytes, 28));
+
+        Ok(Order {
+            order_id: OrderId(u64::from_le_bytes(read_field(bytes, 0))),
+            order_type,
+            order_status,
+            order_side,
+            user_id: UserId(u32::from_le_bytes(read_field(bytes, 12))),
+            price: u32::from_le_bytes(read_field(bytes, 16)),
+            quantity: u64::from_le_bytes(read_field(bytes, 20)),
+            stop_price: (flags & STOP_PRICE_FLAG != 0).then_some(stop_price),
+            reduce_only: flags & REDUCE_ONLY_FLAG != 0,
+            filled_quantity: u64::from_le_bytes(read_field(bytes, 32)),
+            hidden: flags & HIDDEN_FLAG != 0,
+            time_in_force
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_decode_correctly_round_trips_encoded_orders() {
+        let orders = [
+            Order {
+                order_id: OrderId(u64::MAX),
+                order_type: OrderType::StopLimit,
+                order_status: OrderStatus::PartiallyFilled,
+                order_side: OrderSide::Sell,
+                user_id: UserId(42),
+                price: 5025,
+                quantity: 300,
+                stop_price: Some(5000),
+                reduce_only: true,
+                filled_quantity: 200,
+                hidden: true,
+                time_in_force: TimeInForce::Gtd(1_700_000_000_000_000_000)
+            },
+            Order {
+                order_id: OrderId(7),
+                order_type: OrderType::MarketNotional(Decimal::new(-12345, 2)),
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 0,
+                quantity: 0,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            }
+        ];
+
+        for order in orders {
+            assert_eq!(Order::decode(&order.encode()), Ok(order));
+        }
+    }
+
+    #[test]
+    fn test_decode_errors_other_for_malformed_buffers() {
+        let order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+        let bytes = order.encode();
+
+        assert!(matches!(Order::decode(&bytes[..Order::ENCODED_LEN - 1]), Err(OrderBookError::Other(_))));
+
+        for (offset, value) in [(8, 2), (8, 11), (9, 7), (10, 2), (11, 1 << 3), (42, 29), (56, 5)] {
+            let mut malformed = bytes;
+            malformed[offset] = value;
+
+            assert!(matches!(Order::decode(&malformed), Err(OrderBookError::Other(_))));
+        }
+    }
+}
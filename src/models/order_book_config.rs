@@ -1,8 +1,290 @@
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+
+use crate::{enums::{matching_policy::MatchingPolicy, order_book_errors::OrderBookError}, models::price_level::PriceLevel};
+
+// Upper bound on queue slots reserved per side, price levels times queue_size, so a bad config can't allocate an
+// absurd amount up front. The default config reserves about half of it
+pub const MAX_QUEUE_SLOTS: u64 = 200_000_000;
+
+// Largest price_scale whose unit multiplier still fits in a u64
+pub const MAX_PRICE_SCALE: u32 = 19;
+
+// Largest qty_scale whose unit multiplier still fits in a u64
+pub const MAX_QTY_SCALE: u32 = 19;
 
 #[derive(Clone)]
 pub struct OrderBookConfig {
     pub min_price: u32,
     pub max_price: u32,
     pub tick_size: u32,
-    pub queue_size: usize
-}
\ No newline at end of file
+    pub queue_size: usize,
+    pub price_scale: u32,       // Decimal places represented by one unit of price, e.g. 2 for cents
+    pub qty_scale: u32,         // Decimal places represented by one unit of quantity, e.g. 3 for 0.001 BTC
+    pub matching_policy: MatchingPolicy,
+    pub min_order_qty: u64,
+    pub max_order_qty: u64,
+    pub lot_size: u64,              // Order quantities must be a whole number of lots
+    pub event_log_capacity: usize,  // Most recent events kept by the book, 0 disables the event log
+    pub track_level_deltas: bool,   // Record changed levels for drain_level_deltas
+    pub max_orders_per_level: usize,    // Resting orders allowed at one price on one side
+    pub max_resting_orders: usize,      // Resting orders allowed across the whole book
+    pub bbo_history_capacity: usize,    // Most recent BBO changes kept by the book, 0 disables the history
+    pub max_price_deviation_bps: u32,   // How far a priced order may sit from the mid, u32::MAX disables the band
+    pub max_slippage_ticks: u32         // How far past the initial best a market order may sweep, u32::MAX disables the limit
+}
+
+impl Default for OrderBookConfig {
+    fn default() -> Self {
+        OrderBookConfig {
+            min_price: 0,
+            max_price: 1_000_000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            qty_scale: 0,
+            matching_policy: MatchingPolicy::PriceTime,
+            min_order_qty: 1,
+            max_order_qty: u64::MAX,
+            lot_size: 1,
+            event_log_capacity: 0,
+            track_level_deltas: false,
+            max_orders_per_level: usize::MAX,
+            max_resting_orders: usize::MAX,
+            bbo_history_capacity: 0,
+            max_price_deviation_bps: u32::MAX,
+            max_slippage_ticks: u32::MAX
+        }
+    }
+}
+
+impl OrderBookConfig {
+    pub fn validate(&self) -> Result<(), OrderBookError> {
+        if self.min_price >= self.max_price {
+            return Err(OrderBookError::InvalidConfigData(format!("min_price ({}) must be less than max_price ({})", self.min_price, self.max_price)));
+        }
+
+        if self.tick_size == 0 {
+            return Err(OrderBookError::InvalidConfigData("tick_size must be greater than 0".to_string()));
+        }
+
+        if !(self.max_price - self.min_price).is_multiple_of(self.tick_size) {
+            return Err(OrderBookError::InvalidConfigData(format!("price range {}..={} is not a whole number of {} ticks", self.min_price, self.max_price, self.tick_size)));
+        }
+
+        // Every level costs memory even with a queue_size of 0
+        let price_levels = ((self.max_price - self.min_price) / self.tick_size) as u64 + 1;
+        if price_levels.saturating_mul(self.queue_size.max(1) as u64) > MAX_QUEUE_SLOTS {
+            return Err(OrderBookError::InvalidConfigData(format!("{price_levels} price levels of {} queue slots each exceeds {MAX_QUEUE_SLOTS} slots per side", self.queue_size)));
+        }
+
+        if self.price_scale > MAX_PRICE_SCALE {
+            return Err(OrderBookError::InvalidConfigData(format!("price_scale must be at most {MAX_PRICE_SCALE}")));
+        }
+
+        if self.qty_scale > MAX_QTY_SCALE {
+            return Err(OrderBookError::InvalidConfigData(format!("qty_scale must be at most {MAX_QTY_SCALE}")));
+        }
+
+        if self.min_order_qty > self.max_order_qty {
+            return Err(OrderBookError::InvalidConfigData(format!("min_order_qty ({}) must not exceed max_order_qty ({})", self.min_order_qty, self.max_order_qty)));
+        }
+
+        if self.lot_size == 0 {
+            return Err(OrderBookError::InvalidConfigData("lot_size must be greater than 0".to_string()));
+        }
+
+        if self.max_orders_per_level == 0 {
+            return Err(OrderBookError::InvalidConfigData("max_orders_per_level must be greater than 0".to_string()));
+        }
+
+        if self.max_resting_orders == 0 {
+            return Err(OrderBookError::InvalidConfigData("max_resting_orders must be greater than 0".to_string()));
+        }
+
+        Ok(())
+    }
+
+    // Converts a real price (e.g. 50.25) into its tick index, counted in tick_size steps up from min_price
+    pub fn price_to_tick(&self, price: Decimal) -> Result<u32, OrderBookError> {
+        let scaled_price = price.checked_mul(Decimal::from(10u64.pow(self.price_scale)))
+            .ok_or(OrderBookError::PriceOutOfRange)?;
+
+        if !scaled_price.fract().is_zero() {
+            return Err(OrderBookError::InvalidTick(self.tick_size));
+        }
+
+        let price = scaled_price.to_u32()
+            .ok_or(OrderBookError::PriceOutOfRange)?;
+
+        if price < self.min_price || price > self.max_price {
+            return Err(OrderBookError::PriceOutOfRange);
+        }
+
+        if !(price - self.min_price).is_multiple_of(self.tick_size) {
+            return Err(OrderBookError::InvalidTick(self.tick_size));
+        }
+
+        Ok((price - self.min_price) / self.tick_size)
+    }
+
+    // The integer price used by `Order.price` at a tick index
+    pub fn tick_price(&self, tick: u32) -> u32 {
+        self.min_price + tick * self.tick_size
+    }
+
+    pub fn tick_to_price(&self, tick: u32) -> Decimal {
+        self.scale_price(self.tick_price(tick))
+    }
+
+    // An integer price as used by `Order.price`, or a difference between two, as a real price
+    pub fn scale_price(&self, price: u32) -> Decimal {
+        Decimal::new(price as i64, self.price_scale)
+    }
+
+    // Converts a real quantity (e.g. 0.015) into the integer units used by `Order.quantity` and `OrderFill.quantity`,
+    // so fill arithmetic stays exact integer math
+    pub fn qty_to_units(&self, quantity: Decimal) -> Result<u64, OrderBookError> {
+        let scaled_quantity = quantity.checked_mul(Decimal::from(10u64.pow(self.qty_scale)))
+            .ok_or(OrderBookError::InvalidQuantity)?;
+
+        if !scaled_quantity.fract().is_zero() {
+            return Err(OrderBookError::InvalidQuantityScale(self.qty_scale));
+        }
+
+        scaled_quantity.to_u64()
+            .ok_or(OrderBookError::InvalidQuantity)
+    }
+
+    pub fn units_to_qty(&self, units: u64) -> Decimal {
+        Decimal::from_i128_with_scale(units as i128, self.qty_scale)
+    }
+
+    // Cash value of quantity units traded at an integer price
+    pub fn notional(&self, price: u32, units: u64) -> Decimal {
+        self.scale_price(price) * self.units_to_qty(units)
+    }
+
+    pub fn price_level(&self, tick: u32) -> PriceLevel {
+        PriceLevel { tick, price: self.tick_to_price(tick) }
+    }
+
+    // Renders a tick index as its real price at full scale, e.g. tick 5000 as "50.00" from a min_price of 0 and a
+    // tick_size of 1 cent
+    pub fn format_price(&self, tick: u32) -> String {
+        self.price_level(tick).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_price_to_tick_correctly_converts_prices_at_min_and_max_boundaries() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(config.price_to_tick(Decimal::new(100, 2)), Ok(0));
+        assert_eq!(config.price_to_tick(Decimal::new(10000, 2)), Ok(1980));
+        assert_eq!(config.price_to_tick(Decimal::new(5025, 2)), Ok(985));
+    }
+
+    #[test]
+    fn test_price_to_tick_errors_price_out_of_range() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(config.price_to_tick(Decimal::new(95, 2)), Err(OrderBookError::PriceOutOfRange));
+        assert_eq!(config.price_to_tick(Decimal::new(10005, 2)), Err(OrderBookError::PriceOutOfRange));
+        assert_eq!(config.price_to_tick(Decimal::new(-100, 2)), Err(OrderBookError::PriceOutOfRange));
+    }
+
+    #[test]
+    fn test_price_to_tick_errors_invalid_tick_for_misaligned_prices() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(config.price_to_tick(Decimal::new(5027, 2)), Err(OrderBookError::InvalidTick(5)));
+        assert_eq!(config.price_to_tick(Decimal::new(50255, 3)), Err(OrderBookError::InvalidTick(5)));
+    }
+
+    #[test]
+    fn test_tick_to_price_correctly_round_trips_price_to_tick() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(config.tick_to_price(985), Decimal::new(5025, 2));
+        assert_eq!(config.tick_to_price(0), Decimal::new(100, 2));
+        assert_eq!(config.tick_price(985), 5025);
+        assert_eq!(config.price_to_tick(config.tick_to_price(1979)), Ok(1979));
+        assert_eq!(config.format_price(1980), "100.00");
+    }
+
+    #[test]
+    fn test_format_price_correctly_renders_cent_ticks_exactly() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(config.format_price(5000), "50.00");
+        assert_eq!(config.format_price(5001), "50.01");
+        assert_eq!(config.format_price(7), "0.07");
+        assert_eq!(config.price_level(999_999).to_string(), "9999.99");
+    }
+
+    #[test]
+    fn test_qty_to_units_correctly_round_trips_fractional_quantities() {
+        let config = OrderBookConfig {
+            qty_scale: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(config.qty_to_units(Decimal::new(1, 3)), Ok(1));
+        assert_eq!(config.qty_to_units(Decimal::new(15, 1)), Ok(1500));
+        assert_eq!(config.qty_to_units(Decimal::from(2)), Ok(2000));
+        assert_eq!(config.units_to_qty(1500), Decimal::new(15, 1));
+        assert_eq!(config.notional(5000, 20), Decimal::new(1, 0));
+    }
+
+    #[test]
+    fn test_qty_to_units_errors_for_excess_precision_and_negative_quantities() {
+        let config = OrderBookConfig {
+            qty_scale: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(config.qty_to_units(Decimal::new(1, 4)), Err(OrderBookError::InvalidQuantityScale(3)));
+        assert_eq!(config.qty_to_units(Decimal::new(-1, 3)), Err(OrderBookError::InvalidQuantity));
+        assert!(matches!(OrderBookConfig { qty_scale: MAX_QTY_SCALE + 1, ..Default::default() }.validate(), Err(OrderBookError::InvalidConfigData(_))));
+    }
+}
@@ -0,0 +1,27 @@
+// Lifetime order counters for a book. A modify that loses time priority counts as a cancel plus a new order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderBookStats {
+    pub orders_received: u64,
+    pub orders_filled: u64,             // Fully executed, whether as aggressor or resting
+    pub orders_partially_filled: u64,   // Aggressors that traded on arrival but had quantity left over
+    pub orders_canceled: u64,
+    pub orders_rejected: u64
+}
+
+impl OrderBookStats {
+    pub fn fill_ratio(&self) -> f64 {
+        self.ratio_of_received(self.orders_filled)
+    }
+
+    pub fn cancel_ratio(&self) -> f64 {
+        self.ratio_of_received(self.orders_canceled)
+    }
+
+    fn ratio_of_received(&self, count: u64) -> f64 {
+        if self.orders_received == 0 {
+            return 0.0;
+        }
+
+        count as f64 / self.orders_received as f64
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{enums::{order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, time_in_force::TimeInForce}, models::{order::Order, order_id::OrderId, user_id::UserId}};
+
+// Starts from a new Gtc limit order with nothing filled, so only the fields that differ need setting. Quantity
+// defaults to 0, which add_order rejects, so it always has to be given
+pub struct OrderBuilder {
+    order: Order
+}
+
+impl OrderBuilder {
+    pub fn new(order_id: OrderId, order_side: OrderSide) -> Self {
+        OrderBuilder {
+            order: Order {
+                order_id,
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price: 0,
+                quantity: 0,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            }
+        }
+    }
+
+    pub fn order_type(mut self, order_type: OrderType) -> Self {
+        self.order.order_type = order_type;
+        self
+    }
+
+    pub fn user_id(mut self, user_id: UserId) -> Self {
+        self.order.user_id = user_id;
+        self
+    }
+
+    pub fn price(mut self, price: u32) -> Self {
+        self.order.price = price;
+        self
+    }
+
+    pub fn quantity(mut self, quantity: u64) -> Self {
+        self.order.quantity = quantity;
+        self
+    }
+
+    pub fn stop_price(mut self, stop_price: u32) -> Self {
+        self.order.stop_price = Some(stop_price);
+        self
+    }
+
+    pub fn reduce_only(mut self, reduce_only: bool) -> Self {
+        self.order.reduce_only = reduce_only;
+        self
+    }
+
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.order.hidden = hidden;
+        self
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.order.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn build(self) -> Order {
+        self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_build_correctly_defaults_to_new_gtc_limit_order() {
+        let order = OrderBuilder::new(OrderId(1), OrderSide::Sell).build();
+
+        assert_eq!(order, Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 0,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        });
+    }
+
+    #[test]
+    fn test_build_correctly_applies_every_setter() {
+        let order = OrderBuilder::new(OrderId(2), OrderSide::Buy)
+            .order_type(OrderType::StopLimit)
+            .user_id(UserId(7))
+            .price(5010)
+            .quantity(300)
+            .stop_price(5000)
+            .reduce_only(true)
+            .hidden(true)
+            .time_in_force(TimeInForce::Day)
+            .build();
+
+        assert_eq!(order, Order {
+            order_id: OrderId(2),
+            order_type: OrderType::StopLimit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(7),
+            price: 5010,
+            quantity: 300,
+            stop_price: Some(5000),
+            reduce_only: true,
+            filled_quantity: 0,
+            hidden: true,
+            time_in_force: TimeInForce::Day
+        });
+    }
+
+    #[test]
+    fn test_limit_and_market_constructors_correctly_match_builder() {
+        assert_eq!(Order::limit_buy(OrderId(3), UserId(1), 5000, 100), OrderBuilder::new(OrderId(3), OrderSide::Buy).user_id(UserId(1)).price(5000).quantity(100).build());
+        assert_eq!(Order::limit_sell(OrderId(4), UserId(1), 5000, 100), OrderBuilder::new(OrderId(4), OrderSide::Sell).user_id(UserId(1)).price(5000).quantity(100).build());
+        assert_eq!(Order::market_buy(OrderId(5), UserId(1), 100), OrderBuilder::new(OrderId(5), OrderSide::Buy).order_type(OrderType::Market).user_id(UserId(1)).quantity(100).build());
+        assert_eq!(Order::market_sell(OrderId(6), UserId(1), 100).order_type, OrderType::Market);
+    }
+}
@@ -1,8 +1,108 @@
+use crate::{enums::{order_book_errors::OrderBookError, order_side::OrderSide}, models::{order_id::OrderId, user_id::UserId}, utils::read_field};
+
 #[derive(Debug, Clone)]
 pub struct OrderFill {
-    pub aggressive_order_id: u64,
-    pub resting_order_id: u64,
+    pub aggressive_order_id: OrderId,
+    pub resting_order_id: OrderId,     // The resting order is always the maker
+    pub aggressor_side: OrderSide,
+    pub aggressive_user_id: UserId,
+    pub resting_user_id: UserId,
     pub price: u32,
-    pub quantity: u32,
+    pub quantity: u64,
     pub timestamp: u128
-}
\ No newline at end of file
+}
+
+// Fixed little-endian wire layout:
+// aggressive_order_id u64 | resting_order_id u64 | aggressor_side u8 | aggressive_user_id u32 | resting_user_id u32 |
+// price u32 | quantity u64 | timestamp u128
+impl OrderFill {
+    pub const ENCODED_LEN: usize = 53;
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+
+        bytes[0..8].copy_from_slice(&self.aggressive_order_id.0.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.resting_order_id.0.to_le_bytes());
+        bytes[16] = self.aggressor_side.tag();
+        bytes[17..21].copy_from_slice(&self.aggressive_user_id.0.to_le_bytes());
+        bytes[21..25].copy_from_slice(&self.resting_user_id.0.to_le_bytes());
+        bytes[25..29].copy_from_slice(&self.price.to_le_bytes());
+        bytes[29..37].copy_from_slice(&self.quantity.to_le_bytes());
+        bytes[37..53].copy_from_slice(&self.timestamp.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<OrderFill, OrderBookError> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(OrderBookError::Other(format!("An encoded fill must be {} bytes, got {}.", Self::ENCODED_LEN, bytes.len())));
+        }
+
+        let aggressor_side = OrderSide::from_tag(bytes[16])
+            .ok_or_else(|| OrderBookError::Other(format!("Unknown order side tag {}.", bytes[16])))?;
+
+        Ok(OrderFill {
+            aggressive_order_id: OrderId(u64::from_le_bytes(read_field(bytes, 0))),
+            resting_order_id: OrderId(u64::from_le_bytes(read_field(bytes, 8))),
+            aggressor_side,
+            aggressive_user_id: UserId(u32::from_le_bytes(read_field(bytes, 17))),
+            resting_user_id: UserId(u32::from_le_bytes(read_field(bytes, 21))),
+            price: u32::from_le_bytes(read_field(bytes, 25)),
+            quantity: u64::from_le_bytes(read_field(bytes, 29)),
+            timestamp: u128::from_le_bytes(read_field(bytes, 37))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_decode_correctly_round_trips_encoded_fill() {
+        let fill = OrderFill {
+            aggressive_order_id: OrderId(9),
+            resting_order_id: OrderId(u64::MAX),
+            aggressor_side: OrderSide::Sell,
+            aggressive_user_id: UserId(3),
+            resting_user_id: UserId(u32::MAX),
+            price: 5025,
+            quantity: 150,
+            timestamp: 1_700_000_000_000_000_123
+        };
+
+        let decoded_fill = OrderFill::decode(&fill.encode()).unwrap();
+
+        assert_eq!(decoded_fill.aggressive_order_id, fill.aggressive_order_id);
+        assert_eq!(decoded_fill.resting_order_id, fill.resting_order_id);
+        assert_eq!(decoded_fill.aggressor_side, fill.aggressor_side);
+        assert_eq!(decoded_fill.aggressive_user_id, fill.aggressive_user_id);
+        assert_eq!(decoded_fill.resting_user_id, fill.resting_user_id);
+        assert_eq!(decoded_fill.price, fill.price);
+        assert_eq!(decoded_fill.quantity, fill.quantity);
+        assert_eq!(decoded_fill.timestamp, fill.timestamp);
+    }
+
+    #[test]
+    fn test_decode_errors_other_for_malformed_fill_buffers() {
+        let fill = OrderFill {
+            aggressive_order_id: OrderId(9),
+            resting_order_id: OrderId(1),
+            aggressor_side: OrderSide::Buy,
+            aggressive_user_id: UserId(3),
+            resting_user_id: UserId(4),
+            price: 5025,
+            quantity: 150,
+            timestamp: 0
+        };
+        let mut bytes = fill.encode();
+
+        assert!(matches!(OrderFill::decode(&[]), Err(OrderBookError::Other(_))));
+        assert!(matches!(OrderFill::decode(&[bytes.as_slice(), &[0]].concat()), Err(OrderBookError::Other(_))));
+
+        bytes[16] = 2;
+
+        assert!(matches!(OrderFill::decode(&bytes), Err(OrderBookError::Other(_))));
+    }
+}
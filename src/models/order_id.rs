@@ -0,0 +1,16 @@
+use std::fmt::Display;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct OrderId(pub u64);
+
+impl From<u64> for OrderId {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl Display for OrderId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,16 @@
+use std::fmt::Display;
+
+use rust_decimal::Decimal;
+
+// A tick index paired with the real price it stands for, e.g. for log lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub tick: u32,
+    pub price: Decimal
+}
+
+impl Display for PriceLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.price)
+    }
+}
@@ -0,0 +1,5 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub orders_ahead: usize,
+    pub quantity_ahead: u64
+}
@@ -0,0 +1,16 @@
+use std::fmt::Display;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UserId(pub u32);
+
+impl From<u32> for UserId {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -1,24 +1,61 @@
-use std::{collections::{HashMap, VecDeque}, vec};
+use std::{collections::{BTreeSet, HashMap, HashSet, VecDeque}, vec};
 
+use rust_decimal::{Decimal, prelude::ToPrimitive};
 use slab::Slab;
 
-use crate::{enums::{order_book_errors::OrderBookError, order_side::OrderSide, order_status::OrderStatus, order_type::OrderType}, models::{bench_stats::BenchStats, order::Order, order_book_config::{OrderBookConfig}, order_fill::OrderFill}, utils::get_timestamp};
+use crate::{enums::{matching_policy::MatchingPolicy, order_book_errors::OrderBookError, order_book_event::OrderBookEvent, order_outcome::OrderOutcome, order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, time_in_force::TimeInForce}, models::{bar::Bar, bbo_tick::BboTick, bench_stats::BenchStats, book_snapshot::{BookSnapshot, DepthLevel}, cancel_replace_outcome::CancelReplaceOutcome, level_delta::LevelDelta, order::Order, order_book_config::{OrderBookConfig}, order_book_stats::OrderBookStats, order_fill::OrderFill, order_id::OrderId, queue_position::QueuePosition, user_id::UserId}, utils::{Clock, SystemClock, fnv1a}};
+
+// Levels per side covered by checksum
+pub const CHECKSUM_LEVELS: usize = 10;
 
 pub struct OrderBook {
     pub config: OrderBookConfig,
-    pub bids: Vec<VecDeque<usize>>,         // Stores an index of order_ledger
+    pub bids: Vec<VecDeque<usize>>,         // One level per tick from min_price, each storing indices of order_ledger
     pub asks: Vec<VecDeque<usize>>,         // ""
     pub order_ledger: Slab<Order>,
-    pub index_mappings: HashMap<u64, usize>,       // <order_id, ledger_index>
+    pub index_mappings: HashMap<OrderId, usize>,   // <order_id, ledger_index>
+    pub user_orders: HashMap<UserId, HashSet<OrderId>>,   // <user_id, resting order_ids>
+    pub positions: HashMap<UserId, i64>,    // Caller-provided net positions, long positive, consulted by reduce-only orders
     pub trade_history: Vec<OrderFill>,
-    pub best_bid_index: Option<usize>,
-    pub best_ask_index: Option<usize>,
+    pub trade_history_index: HashMap<OrderId, Vec<usize>>,   // <order_id, trade_history indices it took part in>
+    pub bid_quantities: Vec<u64>,           // Running resting quantity per price level
+    pub ask_quantities: Vec<u64>,           // ""
+    pub bid_hidden_quantities: Vec<u64>,    // The part of each level's quantity held by hidden orders
+    pub ask_hidden_quantities: Vec<u64>,    // ""
+    pub total_bid_quantity: u64,
+    pub total_ask_quantity: u64,
+    pub occupied_bid_levels: BTreeSet<usize>,   // Price indices holding resting quantity. Level counts and best prices come from here, and matching skips the rest
+    pub occupied_ask_levels: BTreeSet<usize>,   // ""
+    pub stop_orders: Vec<Order>,            // Pending conditional orders (stops and if-touched), in arrival order
+    pub midpoint_orders: Vec<Order>,        // Resting MidpointPeg orders, in arrival order. Never on the lit levels
+    pub last_trade_price: Option<u32>,
+    pub next_order_id: u64,                 // Next id handed out by submit_auto_id
+    pub stats: OrderBookStats,
+    pub events: VecDeque<OrderBookEvent>,   // Bounded to config.event_log_capacity, oldest dropped first
+    pub bbo_history: Vec<BboTick>,          // Up to twice config.bbo_history_capacity, trimmed in halves so it stays one slice
+    pub changed_levels: Vec<(OrderSide, u32)>,  // Displayed levels touched since the last drain_level_deltas
+    pub clock: Box<dyn Clock>,              // Source of every fill and event timestamp
     pub bench_stats: BenchStats
 }
 
 impl OrderBook {
     pub fn new(config: OrderBookConfig) -> Self {
+        Self::try_new(config).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_new(config: OrderBookConfig) -> Result<Self, OrderBookError> {
+        Self::try_with_clock(config, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(config: OrderBookConfig, clock: Box<dyn Clock>) -> Self {
+        Self::try_with_clock(config, clock).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_with_clock(config: OrderBookConfig, clock: Box<dyn Clock>) -> Result<Self, OrderBookError> {
+        config.validate()?;
+
         let vec_capacity = ((config.max_price - config.min_price) / config.tick_size) as usize;
+        let event_log_capacity = config.event_log_capacity;
 
         let mut bids = vec![];
         for _ in 0..(vec_capacity + 1) {
@@ -34,1354 +71,10470 @@ impl OrderBook {
             asks.push(queue);
         }
 
-        OrderBook {
+        Ok(OrderBook {
             config,
             bids,
             asks,
             order_ledger: Slab::new(),
             index_mappings: HashMap::new(),
+            user_orders: HashMap::new(),
+            positions: HashMap::new(),
             trade_history: vec![],
-            best_bid_index: None,
-            best_ask_index: None,
+            trade_history_index: HashMap::new(),
+            bid_quantities: vec![0; vec_capacity + 1],
+            ask_quantities: vec![0; vec_capacity + 1],
+            bid_hidden_quantities: vec![0; vec_capacity + 1],
+            ask_hidden_quantities: vec![0; vec_capacity + 1],
+            total_bid_quantity: 0,
+            total_ask_quantity: 0,
+            occupied_bid_levels: BTreeSet::new(),
+            occupied_ask_levels: BTreeSet::new(),
+            stop_orders: vec![],
+            midpoint_orders: vec![],
+            last_trade_price: None,
+            next_order_id: 0,
+            stats: Default::default(),
+            events: VecDeque::with_capacity(event_log_capacity),
+            bbo_history: vec![],
+            changed_levels: vec![],
+            clock,
             bench_stats: Default::default()
-        }
+        })
     }
-    
-    #[inline(never)]
-    pub fn fill_order(&mut self, queue: &mut VecDeque<usize>, aggressive_order: &mut Order, resting_order_index: usize, fills: &mut Vec<OrderFill>) -> Result<bool, OrderBookError> {
-        let mut remove_resting_order = false;
-        let mut filled_order = false;
 
-        {
-            let resting_order = self.order_ledger.get_mut(resting_order_index)
-                .ok_or(OrderBookError::OrderNotFound)?;
+    // Rebuilds a book from a recorded event log, starting empty. Matching is deterministic, so only the inputs are
+    // re-applied (accepted orders, cancels, expiries, in-place amends and positions) and the fills, rests and
+    // rejections they caused happen again along the way. next_order_id resumes past the highest id replayed, so
+    // submit_auto_id never hands out one the log already used.
+    // What the log can't reproduce:
+    // - anything dropped by event_log_capacity, so the log must be complete from an empty book
+    // - on_trade_price calls made from outside the book, which aren't recorded
+    // - orders rejected before they were accepted, which only left an OrderRejected, so stats differ
+    // - timestamps, which come from the replaying book's clock
+    pub fn replay(config: OrderBookConfig, events: &[OrderBookEvent]) -> Result<Self, OrderBookError> {
+        let mut order_book = Self::try_new(config)?;
+        let mut next_order_id = 0;
+
+        for event in events {
+            match event {
+                // An accepted order that then failed fails the same way again, and its rejection is already in the log
+                OrderBookEvent::OrderAccepted { order, .. } => {
+                    next_order_id = next_order_id.max(order.order_id.0.saturating_add(1));
+                    let _ = order_book.add_order(order.clone());
+                },
+                OrderBookEvent::PositionSet { user_id, position, .. } => order_book.set_position(*user_id, *position),
+                OrderBookEvent::OrderCanceled { order_id, .. } => order_book.cancel_order(*order_id)?,
+                OrderBookEvent::OrderExpired { order_id, .. } => {
+                    order_book.remove_order(*order_id)?;
+
+                    if order_book.events_enabled() {
+                        order_book.record_event(event.clone());
+                    }
 
-            if resting_order.quantity == aggressive_order.quantity {
-                let fill = OrderFill {
-                    aggressive_order_id: aggressive_order.order_id,
-                    resting_order_id: resting_order.order_id,
-                    price: resting_order.price,
-                    quantity: resting_order.quantity as u32,
-                    timestamp: get_timestamp()
-                };
-                fills.push(fill);
-                remove_resting_order = true;
-                aggressive_order.quantity -= resting_order.quantity;
-                filled_order = true;
-            }
-            else if resting_order.quantity > aggressive_order.quantity {
-                let fill = OrderFill {
-                    aggressive_order_id: aggressive_order.order_id,
-                    resting_order_id: resting_order.order_id,
-                    price: resting_order.price,
-                    quantity: aggressive_order.quantity as u32,
-                    timestamp: get_timestamp()
-                };
-                fills.push(fill);
-                resting_order.quantity -= aggressive_order.quantity;
-                queue.push_front(resting_order_index);
-                aggressive_order.quantity = 0;
-                filled_order = true;
-            }
-            else {
-                let fill = OrderFill {
-                    aggressive_order_id: aggressive_order.order_id,
-                    resting_order_id: resting_order.order_id,
-                    price: resting_order.price,
-                    quantity: resting_order.quantity as u32,
-                    timestamp: get_timestamp()
-                };
-                fills.push(fill);
-                aggressive_order.quantity -= resting_order.quantity; 
-                remove_resting_order = true;
+                    order_book.record_bbo_change();
+                },
+                OrderBookEvent::OrderModified { order_id, quantity, .. } => {
+                    let &ledger_index = order_book.index_mappings.get(order_id).ok_or(OrderBookError::OrderNotFound)?;
+                    let order = Order { quantity: *quantity, ..order_book.order_ledger[ledger_index].clone() };
+
+                    order_book.modify_order(*order_id, order)?;
+                },
+                OrderBookEvent::OrderRested { .. }
+                | OrderBookEvent::OrderFilled { .. }
+                | OrderBookEvent::OrderPartiallyFilled { .. }
+                | OrderBookEvent::OrderRejected { .. } => {}
             }
         }
 
-        if remove_resting_order {
-            self.order_ledger.remove(resting_order_index);  
-        }
+        order_book.next_order_id = next_order_id;
 
-        Ok(filled_order)
+        Ok(order_book)
     }
 
-    #[inline(never)]
-    pub fn add_order(&mut self, order: Order) -> Result<(), OrderBookError> {
-        if order.price as usize >= self.bids.len() {
-            return Err(OrderBookError::PriceOutOfRange);
-        }
+    // Resets the book to empty while keeping every price level's reserved queue capacity
+    pub fn clear(&mut self) {
+        self.bids.iter_mut().for_each(VecDeque::clear);
+        self.asks.iter_mut().for_each(VecDeque::clear);
+        self.order_ledger.clear();
+        self.index_mappings.clear();
+        self.user_orders.clear();
+        self.trade_history.clear();
+        self.trade_history_index.clear();
+        self.changed_levels.clear();
+        self.bid_quantities.fill(0);
+        self.ask_quantities.fill(0);
+        self.bid_hidden_quantities.fill(0);
+        self.ask_hidden_quantities.fill(0);
+        self.total_bid_quantity = 0;
+        self.total_ask_quantity = 0;
+        self.occupied_bid_levels.clear();
+        self.occupied_ask_levels.clear();
+        self.stop_orders.clear();
+        self.midpoint_orders.clear();
+        self.last_trade_price = None;
+        self.stats = Default::default();
+        self.events.clear();
+        self.bbo_history.clear();
+        self.bench_stats = Default::default();
+    }
 
-        self.execute_fill_by_order_type(order)?;
+    // Fills with start <= timestamp < end. Relies on fills being appended in timestamp order
+    pub fn trades_between(&self, start: u128, end: u128) -> &[OrderFill] {
+        let start_index = self.trade_history.partition_point(|fill| fill.timestamp < start);
+        let end_index = self.trade_history.partition_point(|fill| fill.timestamp < end);
 
-        Ok(())
+        &self.trade_history[start_index..end_index.max(start_index)]
     }
 
-    pub fn cancel_order(&mut self, order_id: u64) -> Result<(), OrderBookError> {
-        if !self.order_ledger.iter().any(|(_, order)| order.order_id == order_id) {
-            return Err(OrderBookError::OrderNotFound);
-        }
+    // Fills the order took part in, as either the aggressor or the resting side
+    pub fn fills_for_order(&self, order_id: OrderId) -> Vec<&OrderFill> {
+        self.trade_history_index.get(&order_id)
+            .map(|indices| indices.iter().map(|&i| &self.trade_history[i]).collect())
+            .unwrap_or_default()
+    }
 
-        let ledger_index = self.index_mappings[&order_id];
+    fn record_trades(&mut self, fills: &[OrderFill]) {
+        for fill in fills {
+            let trade_index = self.trade_history.len();
 
-        let order = &self.order_ledger[ledger_index];
-        if order.price as usize >= self.bids.len() {
-            return Err(OrderBookError::PriceOutOfRange);
+            self.trade_history_index.entry(fill.aggressive_order_id).or_default().push(trade_index);
+            self.trade_history_index.entry(fill.resting_order_id).or_default().push(trade_index);
+            self.trade_history.push(fill.clone());
         }
+    }
 
-        match order.order_side {
-            OrderSide::Buy => {
-                if let Some(queue) = self.bids.get_mut(order.price as usize) {
-                    queue.retain(|&idx| idx != ledger_index);
-                    self.order_ledger.remove(ledger_index);
-                }
-                else {
-                    return Err(OrderBookError::OrderNotFound);
-                }
-            },
-            OrderSide::Sell => {
-                if let Some(queue) = self.asks.get_mut(order.price as usize) {
-                    queue.retain(|&idx| idx != ledger_index);
-                    self.order_ledger.remove(ledger_index);
-                }
-                else {
-                    return Err(OrderBookError::OrderNotFound);
+    // Orders held in the ledger, i.e. resting on the lit levels
+    pub fn resting_order_count(&self) -> usize {
+        self.order_ledger.len()
+    }
+
+    // Every live order contains_order reports, so pending stop orders and resting midpoint orders as well as those on
+    // the lit levels
+    pub fn order_count(&self) -> usize {
+        self.order_ledger.len() + self.stop_orders.len() + self.midpoint_orders.len()
+    }
+
+    pub fn take_bench_stats(&mut self) -> BenchStats {
+        self.bench_stats.drain()
+    }
+
+    pub fn stats(&self) -> &OrderBookStats {
+        &self.stats
+    }
+
+    pub fn events(&self) -> &VecDeque<OrderBookEvent> {
+        &self.events
+    }
+
+    pub fn drain_events(&mut self) -> Vec<OrderBookEvent> {
+        self.events.drain(..).collect()
+    }
+
+    // The current displayed state of every level changed since the last drain, bids then asks, each by price.
+    // Applying these to a mirror built from depth() keeps it in sync
+    pub fn drain_level_deltas(&mut self) -> Vec<LevelDelta> {
+        let mut changed_levels = std::mem::take(&mut self.changed_levels);
+        changed_levels.sort_by_key(|(side, price)| (side.tag(), *price));
+        changed_levels.dedup();
+
+        changed_levels.into_iter()
+            .map(|(side, price)| {
+                let level = self.displayed_level(&side, self.level_index(price), false);
+
+                LevelDelta {
+                    side,
+                    price,
+                    new_total_quantity: level.quantity,
+                    new_order_count: level.order_count
                 }
-            }
+            })
+            .collect()
+    }
+
+    fn events_enabled(&self) -> bool {
+        self.config.event_log_capacity > 0
+    }
+
+    fn record_event(&mut self, event: OrderBookEvent) {
+        if !self.events_enabled() {
+            return;
         }
 
-        Ok(())
+        if self.events.len() == self.config.event_log_capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
     }
 
-    pub fn modify_order(&mut self, order_id: u64, order: Order) -> Result<(), OrderBookError> {
-        self.cancel_order(order_id)?;
-        self.add_order(order)
+    // The most recent BBO changes, oldest first, bounded to config.bbo_history_capacity
+    pub fn bbo_history(&self) -> &[BboTick] {
+        let start_index = self.bbo_history.len().saturating_sub(self.config.bbo_history_capacity);
+        &self.bbo_history[start_index..]
     }
 
-    #[inline(never)]
-    fn execute_fill_by_order_type(&mut self, mut order: Order) -> Result<(), OrderBookError> {
-        match order.order_type {
-            OrderType::Limit => {
-                let fills = self.fill_limit_order(&mut order)?;
+    // Called once at the end of each public mutation, so the intermediate states of e.g. an amend aren't recorded
+    fn record_bbo_change(&mut self) {
+        if self.config.bbo_history_capacity == 0 {
+            return;
+        }
 
-                let partially_filled = fills.len() > 0;
+        let (best_bid, best_ask) = (self.best_bid(), self.best_ask());
 
-                if order.quantity > 0 {
-                    self.rest_remaining_limit_order(order, partially_filled)?;
-                }
-            },
-            OrderType::Market => {
-                self.fill_market_order(&mut order)?;
+        let last_bbo = self.bbo_history.last().map_or((None, None), |tick| (tick.best_bid, tick.best_ask));
+        if (best_bid, best_ask) == last_bbo {
+            return;
+        }
 
-                if order.quantity > 0 {
-                    return Err(OrderBookError::InsufficientLiquidity);
-                }
-            },
-            OrderType::ImmediateOrCancel => {
-                self.fill_immediate_or_cancel_order(&mut order)?;
-            },
-            OrderType::FillOrKill => {
-                self.fill_fill_or_kill_order(&mut order)?;
-            }
+        if self.bbo_history.len() == 2 * self.config.bbo_history_capacity {
+            self.bbo_history.drain(..self.config.bbo_history_capacity);
         }
-    
-        Ok(())
+
+        self.bbo_history.push(BboTick { timestamp: self.clock.now(), best_bid, best_ask });
     }
 
-    #[inline(never)]
-    fn fill_limit_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
-        let fills = match order.order_side {
-            OrderSide::Buy => {
-                self.match_order_against_book(order, 0, order.price as usize)?
-            }
-            OrderSide::Sell => {
-                self.match_order_against_book(order, order.price as usize, self.bids.len() - 1)?
+    pub fn last_trade(&self) -> Option<&OrderFill> {
+        self.trade_history.last()
+    }
+
+    // Buckets the trade tape into bar_ns wide windows aligned to multiples of bar_ns. Windows without trades are skipped
+    pub fn ohlcv(&self, bar_ns: u128) -> Vec<Bar> {
+        let mut bars: Vec<Bar> = vec![];
+
+        if bar_ns == 0 {
+            return bars;
+        }
+
+        for fill in &self.trade_history {
+            let start_ts = fill.timestamp - fill.timestamp % bar_ns;
+
+            match bars.last_mut() {
+                Some(bar) if bar.start_ts == start_ts => {
+                    bar.high = bar.high.max(fill.price);
+                    bar.low = bar.low.min(fill.price);
+                    bar.close = fill.price;
+                    bar.volume += fill.quantity;
+                },
+                _ => bars.push(Bar {
+                    start_ts,
+                    open: fill.price,
+                    high: fill.price,
+                    low: fill.price,
+                    close: fill.price,
+                    volume: fill.quantity
+                })
             }
-        };
+        }
 
-        self.trade_history.append(&mut fills.clone());
+        bars
+    }
 
-        Ok(fills)
+    // Displayed best prices. Levels holding only hidden orders are passed over, though they still match first
+    pub fn best_bid(&self) -> Option<u32> {
+        self.displayed_best_excluding(&OrderSide::Buy, None)
     }
 
-    #[inline(never)]
-    fn fill_market_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
-        let mut fills = match order.order_side {
-            OrderSide::Buy => {
-                self.match_order_against_book(order, 0, self.asks.len() - 1)?
+    pub fn best_ask(&self) -> Option<u32> {
+        self.displayed_best_excluding(&OrderSide::Sell, None)
+    }
+
+    // The displayed best price on the side as it would read with excluded_order gone from its level
+    fn displayed_best_excluding(&self, side: &OrderSide, excluded_order: Option<&Order>) -> Option<u32> {
+        let (levels, level_quantities, hidden_quantities) = match side {
+            OrderSide::Buy => (&self.bids, &self.bid_quantities, &self.bid_hidden_quantities),
+            OrderSide::Sell => (&self.asks, &self.ask_quantities, &self.ask_hidden_quantities)
+        };
+
+        let is_displayed = |i: usize| match excluded_order {
+            Some(order) if order.order_side == *side && self.level_index(order.price) == i => {
+                let hidden_quantity = hidden_quantities[i] - if order.hidden { order.quantity } else { 0 };
+                levels[i].len() > 1 && (hidden_quantity == 0 || level_quantities[i] - order.quantity > hidden_quantity)
             },
-            OrderSide::Sell => {
-                self.match_order_against_book(order, 0, self.bids.len() - 1)?
-            }
+            _ => self.is_displayed_level(&levels[i], level_quantities[i], hidden_quantities[i])
         };
 
-        self.trade_history.append(&mut fills);
+        match side {
+            OrderSide::Buy => self.occupied_bid_levels.iter().rev().copied().find(|&i| is_displayed(i)),
+            OrderSide::Sell => self.occupied_ask_levels.iter().copied().find(|&i| is_displayed(i))
+        }.map(|i| self.level_price(i))
+    }
 
-        Ok(fills)
+    fn is_displayed_level(&self, queue: &VecDeque<usize>, quantity: u64, hidden_quantity: u64) -> bool {
+        !queue.is_empty() && (hidden_quantity == 0 || quantity > hidden_quantity)
     }
 
-    #[inline(never)]
-    fn fill_immediate_or_cancel_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
-        let fills = self.fill_limit_order(order)?;
-        
-        Ok(fills)
+    // None if either side is empty, or if resting All or None orders leave the book crossed
+    pub fn spread(&self) -> Option<u32> {
+        self.best_ask()?.checked_sub(self.best_bid()?)
     }
 
-    #[inline(never)]
-    fn fill_fill_or_kill_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
-        if !self.can_fill_completely(&order)? {
-            return Err(OrderBookError::CannotFillCompletely);
-        }
+    pub fn spread_decimal(&self) -> Option<Decimal> {
+        self.spread().map(|spread| self.config.scale_price(spread))
+    }
 
-        let fills = self.fill_limit_order(order)?;
+    // Matching keeps the book uncrossed, so outside of resting All or None orders either of these holding points
+    // at a corrupted book
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some(best_bid), Some(best_ask)) if best_bid > best_ask)
+    }
 
-        Ok(fills)
+    pub fn is_locked(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some(best_bid), Some(best_ask)) if best_bid == best_ask)
     }
 
-    #[inline(never)]
-    fn match_order_against_book(&mut self, aggressive_order: &mut Order, start_index: usize, end_index: usize) -> Result<Vec<OrderFill>, OrderBookError> {
-        let mut fills = Vec::new();
+    // Mid of the displayed BBO. Fills are priced in whole ticks, so a mid falling between two valid prices (e.g. a
+    // one tick spread) gives None and midpoint orders wait for the spread to change
+    pub fn mid_price(&self) -> Option<u32> {
+        let (best_bid, best_ask) = (self.best_bid()?, self.best_ask()?);
 
-        let match_side = if aggressive_order.order_side == OrderSide::Buy {
-            OrderSide::Sell
+        if best_bid > best_ask || !(best_bid + best_ask).is_multiple_of(2) {
+            return None;
         }
-        else {
-            OrderSide::Buy
-        };
 
-        match match_side {
-            OrderSide::Buy => {
-                let end_index = self.best_bid_index.unwrap_or(end_index);
-                for i in (start_index..=end_index).rev() {
-                    if aggressive_order.quantity == 0 {
-                        break;
-                    }
+        let mid_price = (best_bid + best_ask) / 2;
 
-                    let queue_option = self.bids.get_mut(i);
-                    if queue_option.is_none() {
-                        continue;
-                    }
-                    let mut queue = std::mem::take(queue_option.unwrap());
+        (mid_price - self.config.min_price).is_multiple_of(self.config.tick_size).then_some(mid_price)
+    }
 
-                    while aggressive_order.quantity > 0 && !queue.is_empty() {
-                        let resting_order_index = queue.pop_front().unwrap();
-                        let _filled = self.fill_order(&mut queue, aggressive_order, resting_order_index, &mut fills)?;
-                    }
+    // Level indices rather than prices, like every index into the level arrays. The occupied sets are the one
+    // record of which levels hold orders, so the best levels are just their ends
+    pub fn best_bid_index(&self) -> Option<usize> {
+        self.occupied_bid_levels.last().copied()
+    }
 
-                    self.bids[i] = queue;
-                }
-            },
-            OrderSide::Sell => {
-                let start_index = self.best_ask_index.unwrap_or(start_index);
-                for i in start_index..=end_index {
-                    if aggressive_order.quantity == 0 {
-                        break;
-                    }
+    pub fn best_ask_index(&self) -> Option<usize> {
+        self.occupied_ask_levels.first().copied()
+    }
 
-                    let queue_option = self.asks.get_mut(i);
-                    if queue_option.is_none() {
-                        continue;
-                    }
+    // The oldest order at the best level, i.e. the next to match
+    pub fn best_bid_order(&self) -> Option<&Order> {
+        let &front_index = self.bids.get(self.best_bid_index()?)?.front()?;
+        self.order_ledger.get(front_index)
+    }
+
+    pub fn best_ask_order(&self) -> Option<&Order> {
+        let &front_index = self.asks.get(self.best_ask_index()?)?.front()?;
+        self.order_ledger.get(front_index)
+    }
 
-                    let mut queue = std::mem::take(queue_option.unwrap());
+    // FNV-1a over the top CHECKSUM_LEVELS displayed levels per side, bids then asks, best price first. Each level
+    // contributes its price, quantity and order count little-endian, and each side starts with its wire tag
+    pub fn checksum(&self) -> u64 {
+        let mut bytes = vec![];
 
-                    while aggressive_order.quantity > 0 && !queue.is_empty() {
-                        let resting_order = queue.pop_front().unwrap();
-                        let _filled = self.fill_order(&mut queue, aggressive_order, resting_order, &mut fills)?;
-                    }
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            bytes.push(side.tag());
 
-                    self.asks[i] = queue;
-                }
+            for level in self.levels_from_best(side, false).take(CHECKSUM_LEVELS) {
+                bytes.extend(level.price.to_le_bytes());
+                bytes.extend(level.quantity.to_le_bytes());
+                bytes.extend((level.order_count as u64).to_le_bytes());
             }
         }
 
-        Ok(fills)
+        fnv1a(bytes)
     }
 
-    #[inline(never)]
-    fn rest_remaining_limit_order(&mut self, mut order: Order, partially_filled: bool) -> Result<(), OrderBookError> {
-        if order.order_type != OrderType::Limit {
-            return Err(OrderBookError::NonLimitOrderRestAttempt);
+    // Aggregates up to `levels` non-empty price levels per side, best price first
+    pub fn depth(&self, levels: usize) -> BookSnapshot {
+        BookSnapshot {
+            bids: self.levels_from_best(OrderSide::Buy, false).take(levels).collect(),
+            asks: self.levels_from_best(OrderSide::Sell, false).take(levels).collect()
         }
+    }
 
-        order.order_status = if partially_filled {
-            OrderStatus::PartiallyFilled
+    // Non-empty resting levels on one side, walking away from the best price. Without include_hidden, levels show
+    // only their displayed orders and levels holding nothing but hidden orders are skipped
+    fn levels_from_best(&self, side: OrderSide, include_hidden: bool) -> impl Iterator<Item = DepthLevel> + '_ {
+        let indices: Box<dyn Iterator<Item = &usize>> = match side {
+            OrderSide::Buy => Box::new(self.occupied_bid_levels.iter().rev()),
+            OrderSide::Sell => Box::new(self.occupied_ask_levels.iter())
+        };
+
+        indices
+            .map(move |&i| self.displayed_level(&side, i, include_hidden))
+            .filter(|level| level.order_count > 0)
+    }
+
+    fn displayed_level(&self, side: &OrderSide, index: usize, include_hidden: bool) -> DepthLevel {
+        let (levels, level_quantities, hidden_quantities) = match side {
+            OrderSide::Buy => (&self.bids, &self.bid_quantities, &self.bid_hidden_quantities),
+            OrderSide::Sell => (&self.asks, &self.ask_quantities, &self.ask_hidden_quantities)
+        };
+
+        let price = self.level_price(index);
+
+        if include_hidden || hidden_quantities[index] == 0 {
+            return DepthLevel { price, quantity: level_quantities[index], order_count: levels[index].len() };
         }
-        else {
-            OrderStatus::Active
+
+        DepthLevel {
+            price,
+            quantity: level_quantities[index] - hidden_quantities[index],
+            order_count: levels[index].iter().filter(|&&idx| !self.order_ledger[idx].hidden).count()
+        }
+    }
+
+    // How much a cash amount buys (or sells into) walking the opposing levels from the best price, and the
+    // notional actually spent. Only whole units are taken, so some of the cash may be left over
+    pub fn fillable_quantity_for_notional(&self, side: OrderSide, notional: Decimal) -> (u64, Decimal) {
+        let resting_side = match side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy
         };
 
-        match order.order_side {
-            OrderSide::Buy => {
-                self.recalculate_best_bid(order.price)?;
-                if let Some(queue) = self.bids.get_mut(order.price as usize) {
-                    let order_id = order.order_id;
-                    let order_index = self.order_ledger.insert(order);
-                    queue.push_back(order_index);
-                    self.index_mappings.insert(order_id, order_index);
-                }
-                else {
-                    let order_id = order.order_id;
-                    let order_price = order.price;
-                    let order_index = self.order_ledger.insert(order);
-                    let mut queue = VecDeque::new();
-                    queue.push_back(order_index);
-                    self.bids.insert(order_price as usize, queue);
-                    self.index_mappings.insert(order_id, order_index);
-                }
-            },
-            OrderSide::Sell => {
-                self.recalculate_best_ask(order.price)?;
-                if let Some(queue) = self.asks.get_mut(order.price as usize) {
-                    let order_id = order.order_id;
-                    let order_index = self.order_ledger.insert(order);
-                    queue.push_back(order_index);
-                    self.index_mappings.insert(order_id, order_index);
-                }
-                else {
-                    let order_id = order.order_id;
-                    let order_price = order.price;
-                    let order_index = self.order_ledger.insert(order);
-                    let mut queue = VecDeque::new();
-                    queue.push_back(order_index);
-                    self.asks.insert(order_price as usize, queue);
-                    self.index_mappings.insert(order_id, order_index);
-                }
+        let mut quantity = 0;
+        let mut consumed_notional = Decimal::ZERO;
+
+        if notional <= Decimal::ZERO {
+            return (quantity, consumed_notional);
+        }
+
+        for level in self.levels_from_best(resting_side, true) {
+            let unit_price = self.config.notional(level.price, 1);
+            let remaining_notional = notional - consumed_notional;
+
+            let level_fill = if unit_price.is_zero() {
+                level.quantity
+            }
+            else {
+                (remaining_notional / unit_price).floor().to_u64().unwrap_or(u64::MAX).min(level.quantity)
+            };
+
+            quantity += level_fill;
+            consumed_notional += self.config.notional(level.price, level_fill);
+
+            if level_fill < level.quantity {
+                break;
             }
         }
 
-        Ok(())
+        (quantity, consumed_notional)
     }
 
-    fn recalculate_best_bid(&mut self, order_price: u32) -> Result<(), OrderBookError> {
-        if let Some(current_best) = self.best_bid_index {
-            if order_price as usize > current_best {
-                self.best_bid_index = Some(order_price as usize);
-            }
+    // (bid - ask) / (bid + ask) over the top levels of each side, None if either side is empty
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let snapshot = self.depth(levels);
+
+        if snapshot.bids.is_empty() || snapshot.asks.is_empty() {
+            return None;
         }
-        else {
-            self.best_bid_index = Some(order_price as usize);
+
+        let bid_quantity: u64 = snapshot.bids.iter().map(|level| level.quantity).sum();
+        let ask_quantity: u64 = snapshot.asks.iter().map(|level| level.quantity).sum();
+
+        Some((bid_quantity as f64 - ask_quantity as f64) / (bid_quantity + ask_quantity) as f64)
+    }
+
+    // Best bid and ask weighted by the displayed quantity on the opposite side, so it leans towards the thinner side.
+    // In real prices like spread_decimal, None if either side is empty
+    pub fn micro_price(&self) -> Option<Decimal> {
+        let snapshot = self.depth(1);
+        let (best_bid, best_ask) = (snapshot.bids.first()?, snapshot.asks.first()?);
+
+        let bid_price = self.config.scale_price(best_bid.price);
+        let ask_price = self.config.scale_price(best_ask.price);
+        let (bid_quantity, ask_quantity) = (Decimal::from(best_bid.quantity), Decimal::from(best_ask.quantity));
+
+        Some((bid_price * ask_quantity + ask_price * bid_quantity) / (bid_quantity + ask_quantity))
+    }
+
+    // Resting quantity on the side that an aggressor limited to limit_price could reach, hidden orders included.
+    // An aggressor with no size limit can take any All or None order whole, so those all count
+    pub fn cumulative_quantity(&self, side: OrderSide, limit_price: u32) -> u64 {
+        self.reachable_quantity(&side, limit_price, u64::MAX)
+    }
+
+    // How much of aggressive_quantity would trade against the side up to limit_price, walking it best price first
+    // and splitting each level the way match_level does. All or None orders the aggressor can't take whole are
+    // passed over, so they don't count
+    fn reachable_quantity(&self, resting_side: &OrderSide, limit_price: u32, aggressive_quantity: u64) -> u64 {
+        let levels = self.reachable_levels(resting_side, limit_price);
+        let levels_from_best: Box<dyn Iterator<Item = &VecDeque<usize>>> = match resting_side {
+            OrderSide::Sell => Box::new(levels.iter()),
+            OrderSide::Buy => Box::new(levels.iter().rev())
+        };
+
+        let mut remaining_quantity = aggressive_quantity;
+
+        for queue in levels_from_best {
+            if remaining_quantity == 0 {
+                break;
+            }
+
+            if !queue.is_empty() {
+                remaining_quantity -= self.level_allocations(queue, remaining_quantity).iter().sum::<u64>();
+            }
         }
 
-        Ok(())
-    }
+        aggressive_quantity - remaining_quantity
+    }
+
+    // How much of the order would trade on arrival before any remainder rests, without touching the book. All or
+    // None and Fill or Kill orders trade everything or nothing. Stop orders are left pending and midpoint orders
+    // never reach the lit levels, so both report 0
+    pub fn marketable_quantity(&self, order: &Order) -> u64 {
+        let resting_side = match order.order_side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy
+        };
+
+        let (limit_price, quantity) = match &order.order_type {
+            OrderType::Limit | OrderType::AllOrNone => (order.price, order.quantity),
+            OrderType::Market => (self.market_limit_price(&order.order_side), order.quantity),
+            // Sized the way notional_order_quantity sizes it on arrival
+            OrderType::MarketNotional(notional) => {
+                let (quantity, _) = self.fillable_quantity_for_notional(order.order_side.clone(), *notional);
+                (self.market_limit_price(&order.order_side), quantity - quantity % self.config.lot_size)
+            },
+            OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched | OrderType::MidpointPeg => return 0
+        };
+
+        let marketable_quantity = self.reachable_quantity(&resting_side, limit_price, quantity);
+
+        let all_or_nothing = order.order_type == OrderType::AllOrNone || order.time_in_force == TimeInForce::Fok;
+        if all_or_nothing && marketable_quantity < quantity {
+            return 0;
+        }
+
+        marketable_quantity
+    }
+
+    pub fn total_quantity(&self, side: OrderSide) -> u64 {
+        match side {
+            OrderSide::Buy => self.total_bid_quantity,
+            OrderSide::Sell => self.total_ask_quantity
+        }
+    }
+
+    // Displayed quantity at the level, excluding hidden orders
+    pub fn total_quantity_at(&self, side: OrderSide, price: u32) -> u64 {
+        let (level_quantities, hidden_quantities) = match side {
+            OrderSide::Buy => (&self.bid_quantities, &self.bid_hidden_quantities),
+            OrderSide::Sell => (&self.ask_quantities, &self.ask_hidden_quantities)
+        };
+
+        self.checked_level_index(price)
+            .map_or(0, |index| level_quantities[index] - hidden_quantities[index])
+    }
+
+    // What sits ahead of a resting order in its price level's FIFO queue
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition> {
+        let &ledger_index = self.index_mappings.get(&order_id)?;
+        let order = self.order_ledger.get(ledger_index)?;
+
+        let index = self.checked_level_index(order.price)?;
+        let queue = match order.order_side {
+            OrderSide::Buy => &self.bids[index],
+            OrderSide::Sell => &self.asks[index]
+        };
+
+        let orders_ahead = queue.iter().position(|&idx| idx == ledger_index)?;
+        let quantity_ahead = queue.iter()
+            .take(orders_ahead)
+            .map(|&idx| self.order_ledger[idx].quantity)
+            .sum();
+
+        Some(QueuePosition { orders_ahead, quantity_ahead })
+    }
+
+    // Bids best to worst, then asks best to worst, each level in queue order
+    pub fn iter_orders(&self) -> impl Iterator<Item = &Order> {
+        let bid_levels = &self.bids[..self.best_bid_index().map_or(0, |i| i + 1)];
+        let ask_levels = &self.asks[self.best_ask_index().unwrap_or(self.asks.len())..];
+
+        bid_levels.iter().rev()
+            .chain(ask_levels)
+            .flatten()
+            .map(|&idx| &self.order_ledger[idx])
+    }
+
+    pub fn iter_level(&self, side: OrderSide, price: u32) -> impl Iterator<Item = &Order> {
+        let levels = match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks
+        };
+
+        self.checked_level_index(price)
+            .map(|index| &levels[index])
+            .into_iter()
+            .flatten()
+            .map(|&idx| &self.order_ledger[idx])
+    }
+
+    pub fn level_count(&self, side: OrderSide) -> usize {
+        match side {
+            OrderSide::Buy => self.occupied_bid_levels.len(),
+            OrderSide::Sell => self.occupied_ask_levels.len()
+        }
+    }
+
+    pub fn has_bids(&self) -> bool {
+        !self.occupied_bid_levels.is_empty()
+    }
+
+    pub fn has_asks(&self) -> bool {
+        !self.occupied_ask_levels.is_empty()
+    }
+
+    // No resting liquidity on either side. Pending stop orders and resting midpoint orders never sit on the levels,
+    // so they don't count
+    pub fn is_empty(&self) -> bool {
+        !self.has_bids() && !self.has_asks()
+    }
+
+    pub fn capacity_levels(&self) -> usize {
+        self.bids.len()
+    }
+    
+    #[inline(never)]
+    // The resting order is the front of queue and stays there, updated in place, if the aggressor only partially
+    // consumes it. It's popped only once fully filled.
+    // Each branch only subtracts the smaller quantity from the larger, as its condition guarantees. The subtractions
+    // are still checked so a broken invariant surfaces as QuantityUnderflow before anything changes, rather than
+    // wrapping silently in a release build
+    pub fn fill_order(&mut self, queue: &mut VecDeque<usize>, aggressive_order: &mut Order, resting_order_index: usize, fills: &mut Vec<OrderFill>) -> Result<bool, OrderBookError> {
+        debug_assert_eq!(queue.front(), Some(&resting_order_index));
+
+        let mut remove_resting_order = false;
+        let mut filled_order = false;
+
+        let (resting_side, resting_price, resting_hidden, fill_quantity) = {
+            let resting_order = self.order_ledger.get_mut(resting_order_index)
+                .ok_or(OrderBookError::OrderNotFound)?;
+
+            if resting_order.quantity == aggressive_order.quantity {
+                let fill = OrderFill {
+                    aggressive_order_id: aggressive_order.order_id,
+                    resting_order_id: resting_order.order_id,
+                    aggressor_side: aggressive_order.order_side.clone(),
+                    aggressive_user_id: aggressive_order.user_id,
+                    resting_user_id: resting_order.user_id,
+                    price: resting_order.price,
+                    quantity: resting_order.quantity,
+                    timestamp: self.clock.now()
+                };
+                aggressive_order.quantity = aggressive_order.quantity.checked_sub(resting_order.quantity)
+                    .ok_or(OrderBookError::QuantityUnderflow)?;
+                fills.push(fill);
+                remove_resting_order = true;
+                filled_order = true;
+            }
+            else if resting_order.quantity > aggressive_order.quantity {
+                let fill = OrderFill {
+                    aggressive_order_id: aggressive_order.order_id,
+                    resting_order_id: resting_order.order_id,
+                    aggressor_side: aggressive_order.order_side.clone(),
+                    aggressive_user_id: aggressive_order.user_id,
+                    resting_user_id: resting_order.user_id,
+                    price: resting_order.price,
+                    quantity: aggressive_order.quantity,
+                    timestamp: self.clock.now()
+                };
+                resting_order.quantity = resting_order.quantity.checked_sub(aggressive_order.quantity)
+                    .ok_or(OrderBookError::QuantityUnderflow)?;
+                fills.push(fill);
+                aggressive_order.quantity = 0;
+                filled_order = true;
+            }
+            else {
+                let fill = OrderFill {
+                    aggressive_order_id: aggressive_order.order_id,
+                    resting_order_id: resting_order.order_id,
+                    aggressor_side: aggressive_order.order_side.clone(),
+                    aggressive_user_id: aggressive_order.user_id,
+                    resting_user_id: resting_order.user_id,
+                    price: resting_order.price,
+                    quantity: resting_order.quantity,
+                    timestamp: self.clock.now()
+                };
+                aggressive_order.quantity = aggressive_order.quantity.checked_sub(resting_order.quantity)
+                    .ok_or(OrderBookError::QuantityUnderflow)?;
+                fills.push(fill);
+                remove_resting_order = true;
+            }
+
+            let fill_quantity = fills[fills.len() - 1].quantity;
+
+            resting_order.filled_quantity += fill_quantity;
+            aggressive_order.filled_quantity += fill_quantity;
+
+            if !remove_resting_order {
+                resting_order.order_status = OrderStatus::PartiallyFilled;
+            }
+
+            (resting_order.order_side.clone(), resting_order.price, resting_order.hidden, fill_quantity)
+        };
+
+        self.remove_level_quantity(&resting_side, resting_price, fill_quantity, resting_hidden);
+        self.last_trade_price = Some(resting_price);
+
+        if remove_resting_order {
+            queue.pop_front();
+            let resting_order = self.order_ledger.remove(resting_order_index);
+            self.index_mappings.remove(&resting_order.order_id);
+            self.remove_user_order(resting_order.user_id, resting_order.order_id);
+            self.stats.orders_filled += 1;
+        }
+
+        Ok(filled_order)
+    }
+
+    #[inline(never)]
+    pub fn add_order(&mut self, order: Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        self.stats.orders_received += 1;
+
+        let order_id = order.order_id;
+        let result = self.try_add_order(order);
+
+        self.record_bbo_change();
+
+        if let Err(err) = &result {
+            self.stats.orders_rejected += 1;
+
+            if self.events_enabled() {
+                self.record_event(OrderBookEvent::OrderRejected { order_id, reason: err.to_string(), timestamp: self.clock.now() });
+            }
+        }
+
+        result
+    }
+
+    fn try_add_order(&mut self, order: Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        self.validate_order(&order, None)?;
+
+        self.check_order_id_available(order.order_id)?;
+
+        if self.events_enabled() {
+            self.record_event(OrderBookEvent::OrderAccepted { order: order.clone(), timestamp: self.clock.now() });
+        }
+
+        let previous_trade_price = self.last_trade_price;
+
+        let result = self.execute_fill_by_order_type(order);
+
+        // Resting midpoint orders on both sides means there was no mid when the later one arrived
+        if self.midpoint_orders.iter().any(|order| order.order_side == OrderSide::Buy)
+            && self.midpoint_orders.iter().any(|order| order.order_side == OrderSide::Sell) {
+            self.match_resting_midpoint_orders();
+        }
+
+        if let Some(trade_price) = self.last_trade_price
+            && self.last_trade_price != previous_trade_price
+            && !self.stop_orders.is_empty() {
+            self.on_trade_price(trade_price);
+        }
+
+        self.debug_assert_book_not_crossed();
+
+        result
+    }
+
+    // Best prices stay maintained per order rather than once per batch, since each order matches against the book
+    // the previous ones left behind
+    pub fn add_orders(&mut self, orders: Vec<Order>) -> Vec<Result<Vec<OrderFill>, OrderBookError>> {
+        let mut results = Vec::with_capacity(orders.len());
+
+        for order in orders {
+            results.push(self.add_order(order));
+        }
+
+        results
+    }
+
+    // Same as add_order, but also classifies what became of the order so callers don't have to work it out from
+    // the fills and the book
+    pub fn add_order_with_outcome(&mut self, order: Order) -> (OrderOutcome, Result<Vec<OrderFill>, OrderBookError>) {
+        let order_id = order.order_id;
+        let result = self.add_order(order.clone());
+
+        let outcome = match &result {
+            Err(_) => OrderOutcome::Rejected,
+            Ok(fills) => {
+                let filled: u64 = fills.iter().map(|fill| fill.quantity).sum();
+
+                if self.contains_order(order_id) {
+                    if filled > 0 { OrderOutcome::PartiallyFilledResting } else { OrderOutcome::Rested }
+                } else {
+                    let fully_filled = match self.unfilled_notional(&order, fills) {
+                        Some(unfilled) => unfilled.is_zero(),
+                        None => self.unfilled_quantity(&order, fills) == Some(0)
+                    };
+
+                    if fully_filled { OrderOutcome::FullyFilled } else { OrderOutcome::Canceled }
+                }
+            }
+        };
+
+        (outcome, result)
+    }
+
+    // Assigns the book's next sequence number in place of the supplied id. Ids are never reused, and any a client
+    // supplied that are still live are skipped over
+    pub fn submit_auto_id(&mut self, mut order: Order) -> (OrderId, Result<Vec<OrderFill>, OrderBookError>) {
+        let mut order_id = OrderId(self.next_order_id);
+
+        while self.check_order_id_available(order_id).is_err() {
+            order_id = OrderId(order_id.0 + 1);
+        }
+
+        self.next_order_id = order_id.0 + 1;
+        order.order_id = order_id;
+
+        (order_id, self.add_order(order))
+    }
+
+    fn validate_order(&self, order: &Order, replaced_order_id: Option<OrderId>) -> Result<(), OrderBookError> {
+        // Notional orders work out their quantity from the book when they execute, which is when it's checked, and
+        // carry no meaningful price
+        if let OrderType::MarketNotional(notional) = order.order_type {
+            if notional <= Decimal::ZERO {
+                return Err(OrderBookError::InvalidQuantity);
+            }
+
+            return Ok(());
+        }
+
+        self.check_order_quantity(order.quantity)?;
+
+        if order.reduce_only {
+            self.check_reduce_only(order)?;
+        }
+
+        // Market orders sweep the whole opposing side and midpoint orders trade at the mid, so their price field
+        // carries no meaning
+        if matches!(order.order_type, OrderType::Market | OrderType::MidpointPeg) {
+            return Ok(());
+        }
+
+        // A conditional order's trigger has to sit on the book's grid or it could never fire as intended
+        if matches!(order.order_type, OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched) {
+            self.check_price_on_grid(order.stop_price.ok_or(OrderBookError::MissingStopPrice)?)?;
+        }
+
+        // Stop and market-if-touched orders trade as market orders once triggered, so only their trigger matters
+        if matches!(order.order_type, OrderType::Stop | OrderType::MarketIfTouched) {
+            return Ok(());
+        }
+
+        self.check_price_on_grid(order.price)?;
+
+        if matches!(order.order_type, OrderType::Limit | OrderType::AllOrNone) {
+            self.check_price_band(order.price, replaced_order_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_order_quantity(&self, quantity: u64) -> Result<(), OrderBookError> {
+        if quantity == 0 {
+            return Err(OrderBookError::InvalidQuantity);
+        }
+
+        if quantity < self.config.min_order_qty || quantity > self.config.max_order_qty {
+            return Err(OrderBookError::OrderSizeOutOfRange { min: self.config.min_order_qty, max: self.config.max_order_qty, got: quantity });
+        }
+
+        if !quantity.is_multiple_of(self.config.lot_size) {
+            return Err(OrderBookError::InvalidLotSize(self.config.lot_size));
+        }
+
+        Ok(())
+    }
+
+    fn check_price_on_grid(&self, price: u32) -> Result<(), OrderBookError> {
+        if price > self.config.max_price || price < self.config.min_price {
+            return Err(OrderBookError::PriceOutOfRange);
+        }
+
+        if !(price - self.config.min_price).is_multiple_of(self.config.tick_size) {
+            return Err(OrderBookError::InvalidTick(self.config.tick_size));
+        }
+
+        Ok(())
+    }
+
+    // The reference is the mid of the displayed BBO, or the one displayed best price if the other side is empty.
+    // An amend is checked against the BBO without the order it replaces, as add_order will see it once the original
+    // is canceled. Worked in doubled prices so a mid between two ticks stays exact
+    fn check_price_band(&self, price: u32, replaced_order_id: Option<OrderId>) -> Result<(), OrderBookError> {
+        if self.config.max_price_deviation_bps == u32::MAX {
+            return Ok(());
+        }
+
+        let replaced_order = replaced_order_id
+            .and_then(|order_id| self.index_mappings.get(&order_id))
+            .map(|&ledger_index| &self.order_ledger[ledger_index]);
+
+        let best_bid = self.displayed_best_excluding(&OrderSide::Buy, replaced_order);
+        let best_ask = self.displayed_best_excluding(&OrderSide::Sell, replaced_order);
+
+        let doubled_reference = match (best_bid, best_ask) {
+            (Some(best_bid), Some(best_ask)) => best_bid as u64 + best_ask as u64,
+            (Some(best_price), None) | (None, Some(best_price)) => 2 * best_price as u64,
+            (None, None) => return Ok(())
+        };
+
+        let doubled_deviation = (2 * price as u64).abs_diff(doubled_reference);
+
+        if doubled_deviation * 10_000 > self.config.max_price_deviation_bps as u64 * doubled_reference {
+            return Err(OrderBookError::PriceBandViolation { price, max_deviation_bps: self.config.max_price_deviation_bps });
+        }
+
+        Ok(())
+    }
+
+    // A reduce-only order must trade against the user's position and can't be larger than it, or it would flip
+    // the position's direction
+    fn check_reduce_only(&self, order: &Order) -> Result<(), OrderBookError> {
+        let position = self.positions.get(&order.user_id).copied().unwrap_or(0);
+
+        let reducible_quantity = match order.order_side {
+            OrderSide::Buy if position < 0 => position.unsigned_abs(),
+            OrderSide::Sell if position > 0 => position.unsigned_abs(),
+            _ => 0
+        };
+
+        if order.quantity > reducible_quantity {
+            return Err(OrderBookError::ReduceOnlyViolation { position, got: order.quantity });
+        }
+
+        Ok(())
+    }
+
+    // Positions are owned by the caller; the book only reads them to police reduce-only orders. They're still logged,
+    // since replaying reduce-only orders depends on them
+    pub fn set_position(&mut self, user_id: UserId, position: i64) {
+        if position == 0 {
+            self.positions.remove(&user_id);
+        }
+        else {
+            self.positions.insert(user_id, position);
+        }
+
+        if self.events_enabled() {
+            self.record_event(OrderBookEvent::PositionSet { user_id, position, timestamp: self.clock.now() });
+        }
+    }
+
+    // Dry run of add_order: the fills the order would get against the book as it stands, without mutating anything.
+    // Stop orders only rest on arrival, so they preview as no fills
+    pub fn preview(&self, order: &Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        self.validate_order(order, None)?;
+        self.check_order_id_available(order.order_id)?;
+        self.simulate_order(order)
+    }
+
+    // Whether the order is still live in the book, resting on a level or pending as a stop or midpoint order
+    pub fn contains_order(&self, order_id: OrderId) -> bool {
+        self.check_order_id_available(order_id).is_err()
+    }
+
+    fn check_order_id_available(&self, order_id: OrderId) -> Result<(), OrderBookError> {
+        if self.index_mappings.contains_key(&order_id)
+            || self.stop_orders.iter().any(|stop_order| stop_order.order_id == order_id)
+            || self.midpoint_orders.iter().any(|midpoint_order| midpoint_order.order_id == order_id) {
+            return Err(OrderBookError::DuplicateOrderId(order_id));
+        }
+
+        Ok(())
+    }
+
+    fn simulate_order(&self, order: &Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let mut remaining_quantity = order.quantity;
+
+        let fills = match order.order_type {
+            OrderType::MarketNotional(notional) => {
+                let sized_order = Order { quantity: self.notional_order_quantity(order, notional)?, ..order.clone() };
+                remaining_quantity = sized_order.quantity;
+
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(&sized_order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                self.preview_against_book(&sized_order, &mut remaining_quantity, true)
+            },
+            OrderType::Limit => {
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                self.preview_against_book(order, &mut remaining_quantity, false)
+            },
+            OrderType::Market => {
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                let fills = self.preview_against_book(order, &mut remaining_quantity, true);
+
+                if fills.is_empty() {
+                    return Err(OrderBookError::NoLiquidity);
+                }
+
+                fills
+            },
+            OrderType::AllOrNone => {
+                let can_fill_completely = self.can_fill_completely(order)?;
+
+                if order.time_in_force == TimeInForce::Fok && !can_fill_completely {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                if can_fill_completely {
+                    self.preview_against_book(order, &mut remaining_quantity, false)
+                }
+                else {
+                    vec![]
+                }
+            },
+            OrderType::MidpointPeg => {
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                let mut fills = vec![];
+
+                if let Some(mid_price) = self.mid_price() {
+                    for resting_order in self.midpoint_orders.iter().filter(|resting_order| resting_order.order_side != order.order_side) {
+                        if remaining_quantity == 0 {
+                            break;
+                        }
+
+                        let quantity = remaining_quantity.min(resting_order.quantity);
+                        remaining_quantity -= quantity;
+
+                        fills.push(OrderFill {
+                            aggressive_order_id: order.order_id,
+                            resting_order_id: resting_order.order_id,
+                            aggressor_side: order.order_side.clone(),
+                            aggressive_user_id: order.user_id,
+                            resting_user_id: resting_order.user_id,
+                            price: mid_price,
+                            quantity,
+                            timestamp: self.clock.now()
+                        });
+                    }
+                }
+
+                fills
+            },
+            // Conditional orders only park on arrival, and validate_order has already checked the trigger
+            OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched => vec![]
+        };
+
+        Ok(fills)
+    }
+
+    // Mirrors match_order_against_book and match_level over the current queues
+    fn preview_against_book(&self, order: &Order, remaining_quantity: &mut u64, sweep_all_levels: bool) -> Vec<OrderFill> {
+        let mut fills = vec![];
+
+        let (sweep_start_index, sweep_end_index) = self.market_sweep_range(&order.order_side);
+
+        let level_indices: Box<dyn Iterator<Item = &usize>> = match order.order_side {
+            OrderSide::Buy => {
+                let end_index = if sweep_all_levels { sweep_end_index } else { self.level_index(order.price) };
+                Box::new(self.occupied_ask_levels.range(..=end_index))
+            },
+            OrderSide::Sell => {
+                let start_index = if sweep_all_levels { sweep_start_index } else { self.level_index(order.price) };
+                Box::new(self.occupied_bid_levels.range(start_index..).rev())
+            }
+        };
+
+        let levels = match order.order_side {
+            OrderSide::Buy => &self.asks,
+            OrderSide::Sell => &self.bids
+        };
+
+        for &i in level_indices {
+            if *remaining_quantity == 0 {
+                break;
+            }
+
+            let Some(queue) = levels.get(i) else {
+                continue;
+            };
+
+            for (&idx, allocation) in queue.iter().zip(self.level_allocations(queue, *remaining_quantity)) {
+                if allocation == 0 {
+                    continue;
+                }
+
+                let resting_order = &self.order_ledger[idx];
+
+                fills.push(OrderFill {
+                    aggressive_order_id: order.order_id,
+                    resting_order_id: resting_order.order_id,
+                    aggressor_side: order.order_side.clone(),
+                    aggressive_user_id: order.user_id,
+                    resting_user_id: resting_order.user_id,
+                    price: resting_order.price,
+                    quantity: allocation,
+                    timestamp: self.clock.now()
+                });
+                *remaining_quantity -= allocation;
+            }
+        }
+
+        fills
+    }
+
+    pub fn add_order_at_price(&mut self, mut order: Order, price: Decimal) -> Result<Vec<OrderFill>, OrderBookError> {
+        order.price = self.config.tick_price(self.config.price_to_tick(price)?);
+
+        self.add_order(order)
+    }
+
+    // Limit-style entry point for callers working in Decimal prices and quantities rather than ticks and units
+    pub fn submit(&mut self, order_id: OrderId, user_id: UserId, order_side: OrderSide, order_type: OrderType, price: Decimal, quantity: Decimal) -> Result<Vec<OrderFill>, OrderBookError> {
+        let quantity = self.config.qty_to_units(quantity)?;
+
+        let order = Order {
+            order_id,
+            order_type,
+            order_status: OrderStatus::PendingNew,
+            order_side,
+            user_id,
+            price: 0,
+            quantity,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        self.add_order_at_price(order, price)
+    }
+
+    // Buy stops trigger at or above their stop price, sell stops at or below it, and if-touched orders the other way
+    // round (see Order::trigger_direction). Stops and MarketIfTouched orders are submitted as Market orders,
+    // StopLimits and LimitIfTouched orders as Limit orders.
+    pub fn on_trade_price(&mut self, price: u32) -> Vec<OrderId> {
+        self.last_trade_price = Some(price);
+
+        let mut activated_order_ids = vec![];
+
+        loop {
+            let trade_price = self.last_trade_price.unwrap_or(price);
+
+            let triggered_orders: Vec<Order> = self.stop_orders
+                .extract_if(.., |order| match (order.trigger_direction(), order.stop_price) {
+                    (Some(trigger_direction), Some(stop_price)) => trigger_direction.is_triggered(stop_price, trade_price),
+                    _ => false
+                })
+                .collect();
+
+            if triggered_orders.is_empty() {
+                break;
+            }
+
+            for mut order in triggered_orders {
+                activated_order_ids.push(order.order_id);
+
+                order.order_type = if matches!(order.order_type, OrderType::Stop | OrderType::MarketIfTouched) {
+                    OrderType::Market
+                }
+                else {
+                    OrderType::Limit
+                };
+
+                // The trade that triggered the stop has already happened, so a triggered order that
+                // can't be filled (e.g. a stop-market with no liquidity) is dropped rather than surfaced
+                let _ = self.execute_fill_by_order_type(order);
+            }
+        }
+
+        self.record_bbo_change();
+
+        activated_order_ids
+    }
+
+    pub fn cancel_order(&mut self, order_id: OrderId) -> Result<(), OrderBookError> {
+        let result = self.try_cancel_order(order_id);
+
+        self.record_bbo_change();
+
+        result
+    }
+
+    fn try_cancel_order(&mut self, order_id: OrderId) -> Result<(), OrderBookError> {
+        let order = self.remove_order(order_id)?;
+
+        self.stats.orders_canceled += 1;
+
+        if self.events_enabled() {
+            self.record_event(OrderBookEvent::OrderCanceled { order_id, remaining_quantity: order.quantity, timestamp: self.clock.now() });
+        }
+
+        Ok(())
+    }
+
+    // Expires Gtd orders whose expiry has passed, plus Day orders when the session has ended, returning the expired
+    // ids in ascending order
+    pub fn expire_orders(&mut self, end_of_session: bool) -> Vec<OrderId> {
+        let now = self.clock.now();
+
+        let mut expired_order_ids: Vec<OrderId> = self.order_ledger.iter()
+            .map(|(_, order)| order)
+            .chain(&self.stop_orders)
+            .chain(&self.midpoint_orders)
+            .filter(|order| match order.time_in_force {
+                TimeInForce::Day => end_of_session,
+                TimeInForce::Gtd(expiry) => expiry <= now,
+                TimeInForce::Gtc | TimeInForce::Ioc | TimeInForce::Fok => false
+            })
+            .map(|order| order.order_id)
+            .collect();
+        expired_order_ids.sort_unstable();
+
+        for &order_id in &expired_order_ids {
+            if let Ok(order) = self.remove_order(order_id)
+                && self.events_enabled() {
+                self.record_event(OrderBookEvent::OrderExpired { order_id, remaining_quantity: order.quantity, timestamp: now });
+            }
+        }
+
+        self.record_bbo_change();
+
+        expired_order_ids
+    }
+
+    // Takes a live order out of every structure tracking it, whether resting, midpoint or a pending stop. Counting
+    // and reporting why it left is up to the caller
+    fn remove_order(&mut self, order_id: OrderId) -> Result<Order, OrderBookError> {
+        if let Some(position) = self.stop_orders.iter().position(|order| order.order_id == order_id) {
+            return Ok(self.stop_orders.remove(position));
+        }
+
+        if let Some(position) = self.midpoint_orders.iter().position(|order| order.order_id == order_id) {
+            return Ok(self.midpoint_orders.remove(position));
+        }
+
+        if !self.order_ledger.iter().any(|(_, order)| order.order_id == order_id) {
+            return Err(OrderBookError::OrderNotFound);
+        }
+
+        let ledger_index = self.index_mappings[&order_id];
+
+        let order = &self.order_ledger[ledger_index];
+        let Some(index) = self.checked_level_index(order.price) else {
+            return Err(OrderBookError::PriceOutOfRange);
+        };
+
+        let queue = match order.order_side {
+            OrderSide::Buy => self.bids.get_mut(index),
+            OrderSide::Sell => self.asks.get_mut(index)
+        };
+
+        queue.ok_or(OrderBookError::OrderNotFound)?
+            .retain(|&idx| idx != ledger_index);
+
+        let order = self.order_ledger.remove(ledger_index);
+
+        self.remove_level_quantity(&order.order_side, order.price, order.quantity, order.hidden);
+        self.index_mappings.remove(&order_id);
+        self.remove_user_order(order.user_id, order_id);
+
+        Ok(order)
+    }
+
+    // Pulls every resting, midpoint and pending stop order for the user, returning the canceled ids in ascending order
+    pub fn cancel_all_for_user(&mut self, user_id: UserId) -> Vec<OrderId> {
+        let mut canceled_order_ids: Vec<OrderId> = self.user_orders.get(&user_id)
+            .map(|order_ids| order_ids.iter().copied().collect())
+            .unwrap_or_default();
+        canceled_order_ids.sort_unstable();
+
+        for &order_id in &canceled_order_ids {
+            let _ = self.try_cancel_order(order_id);
+        }
+
+        let mut pending_orders: Vec<Order> = self.stop_orders.extract_if(.., |order| order.user_id == user_id).collect();
+        pending_orders.extend(self.midpoint_orders.extract_if(.., |order| order.user_id == user_id));
+        self.stats.orders_canceled += pending_orders.len() as u64;
+
+        for pending_order in pending_orders {
+            if self.events_enabled() {
+                self.record_event(OrderBookEvent::OrderCanceled { order_id: pending_order.order_id, remaining_quantity: pending_order.quantity, timestamp: self.clock.now() });
+            }
+
+            canceled_order_ids.push(pending_order.order_id);
+        }
+        canceled_order_ids.sort_unstable();
+
+        self.record_bbo_change();
+
+        canceled_order_ids
+    }
+
+    // Cancels up to n of the oldest orders at the level, returning their ids oldest first
+    pub fn cancel_front(&mut self, side: OrderSide, price: u32, n: usize) -> Vec<OrderId> {
+        let front_order_ids: Vec<OrderId> = self.iter_level(side, price)
+            .take(n)
+            .map(|order| order.order_id)
+            .collect();
+
+        for &order_id in &front_order_ids {
+            let _ = self.try_cancel_order(order_id);
+        }
+
+        self.record_bbo_change();
+
+        front_order_ids
+    }
+
+    // Cancels every resting order on one side priced within from_price..=to_price, returning their ids in ascending
+    // price then queue order. Levels are drained whole and the best price is refreshed once at the end
+    pub fn cancel_range(&mut self, side: OrderSide, from_price: u32, to_price: u32) -> Vec<OrderId> {
+        let mut canceled_order_ids = vec![];
+
+        let (Some(from_index), Some(to_index)) = (self.level_index_at_or_above(from_price), self.level_index_at_or_below(to_price)) else {
+            return canceled_order_ids;
+        };
+
+        for index in from_index..=to_index {
+            let price = self.level_price(index);
+            let queue = match side {
+                OrderSide::Buy => std::mem::take(&mut self.bids[index]),
+                OrderSide::Sell => std::mem::take(&mut self.asks[index])
+            };
+
+            for ledger_index in queue {
+                let order = self.order_ledger.remove(ledger_index);
+
+                self.remove_level_quantity(&side, price, order.quantity, order.hidden);
+                self.index_mappings.remove(&order.order_id);
+                self.remove_user_order(order.user_id, order.order_id);
+                self.stats.orders_canceled += 1;
+
+                if self.events_enabled() {
+                    self.record_event(OrderBookEvent::OrderCanceled { order_id: order.order_id, remaining_quantity: order.quantity, timestamp: self.clock.now() });
+                }
+
+                canceled_order_ids.push(order.order_id);
+            }
+        }
+
+        self.record_bbo_change();
+
+        canceled_order_ids
+    }
+
+    fn remove_user_order(&mut self, user_id: UserId, order_id: OrderId) {
+        if let Some(order_ids) = self.user_orders.get_mut(&user_id) {
+            order_ids.remove(&order_id);
+
+            if order_ids.is_empty() {
+                self.user_orders.remove(&user_id);
+            }
+        }
+    }
+
+    pub fn modify_order(&mut self, order_id: OrderId, order: Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let original_order = match self.index_mappings.get(&order_id) {
+            Some(&ledger_index) => &self.order_ledger[ledger_index],
+            None => self.stop_orders.iter()
+                .chain(&self.midpoint_orders)
+                .find(|pending_order| pending_order.order_id == order_id)
+                .ok_or(OrderBookError::OrderNotFound)?
+        };
+
+        // An amend can change price and size, but flipping the side or type needs a cancel and a new order
+        if order.order_side != original_order.order_side || order.order_type != original_order.order_type {
+            return Err(OrderBookError::InvalidModification);
+        }
+
+        // A pure size reduction keeps the order's place in its queue, anything else loses time priority
+        let reduces_in_place = self.index_mappings.get(&order_id).is_some_and(|&ledger_index| {
+            let resting_order = &self.order_ledger[ledger_index];
+
+            order.order_id == order_id
+                && order.order_side == resting_order.order_side
+                && order.order_type == resting_order.order_type
+                && order.price == resting_order.price
+                && order.hidden == resting_order.hidden
+                && order.time_in_force == resting_order.time_in_force
+                && order.quantity > 0
+                && order.quantity <= resting_order.quantity
+        });
+
+        // Everything that could reject the replacement is checked before the original is touched, so a failed
+        // amend leaves the original live with its queue position. Unless it stays where it is, the replacement is
+        // checked against the book without the original
+        self.validate_order(&order, (!reduces_in_place).then_some(order_id))?;
+
+        if order.order_id != order_id {
+            self.check_order_id_available(order.order_id)?;
+        }
+
+        // Once validated, only these types can still be rejected by the book
+        if matches!(order.order_type, OrderType::Market | OrderType::MarketNotional(_) | OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched)
+            || order.time_in_force == TimeInForce::Fok {
+            self.simulate_order(&order)?;
+        }
+
+        // A remainder moving to a full level would be rejected after the original was already gone
+        if let Some(&ledger_index) = self.index_mappings.get(&order_id)
+            && order.price != self.order_ledger[ledger_index].price
+            && order.time_in_force.rests() {
+            let index = self.level_index(order.price);
+            let level_order_count = match order.order_side {
+                OrderSide::Buy => self.bids[index].len(),
+                OrderSide::Sell => self.asks[index].len()
+            };
+
+            if level_order_count >= self.config.max_orders_per_level {
+                return Err(OrderBookError::LevelOrderLimitReached(self.config.max_orders_per_level));
+            }
+        }
+
+        if reduces_in_place {
+            let ledger_index = self.index_mappings[&order_id];
+            let resting_order = &self.order_ledger[ledger_index];
+            let (order_side, order_price, order_hidden) = (resting_order.order_side.clone(), resting_order.price, resting_order.hidden);
+            let reduction = resting_order.quantity - order.quantity;
+
+            self.order_ledger[ledger_index].quantity = order.quantity;
+            self.remove_level_quantity(&order_side, order_price, reduction, order_hidden);
+
+            if self.events_enabled() {
+                self.record_event(OrderBookEvent::OrderModified { order_id, quantity: order.quantity, timestamp: self.clock.now() });
+            }
+
+            return Ok(vec![]);
+        }
+
+        // The replacement continues the original's lineage, so what the original already traded carries across
+        let filled_quantity = match self.index_mappings.get(&order_id) {
+            Some(&ledger_index) => self.order_ledger[ledger_index].filled_quantity,
+            None => self.stop_orders.iter()
+                .chain(&self.midpoint_orders)
+                .find(|pending_order| pending_order.order_id == order_id)
+                .map_or(0, |pending_order| pending_order.filled_quantity)
+        };
+
+        self.try_cancel_order(order_id)?;
+        self.add_order(Order { filled_quantity, ..order })
+    }
+
+    // FIX-style cancel/replace. Built on modify_order, so either the original is replaced or it's left live untouched
+    pub fn cancel_replace(&mut self, old_id: OrderId, new_order: Order) -> Result<CancelReplaceOutcome, OrderBookError> {
+        let new_id = new_order.order_id;
+        let fills = self.modify_order(old_id, new_order)?;
+
+        Ok(CancelReplaceOutcome { canceled_id: old_id, new_id, fills })
+    }
+
+    #[inline(never)]
+    fn execute_fill_by_order_type(&mut self, mut order: Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let fills = match order.order_type {
+            OrderType::Limit => {
+                let fills = match order.time_in_force {
+                    TimeInForce::Ioc => self.fill_immediate_or_cancel_order(&mut order)?,
+                    TimeInForce::Fok => self.fill_fill_or_kill_order(&mut order)?,
+                    TimeInForce::Gtc | TimeInForce::Day | TimeInForce::Gtd(_) => self.fill_limit_order(&mut order)?
+                };
+
+                // Reduce-only remainders are dropped like an IOC's rather than rested
+                if order.quantity > 0 && order.time_in_force.rests() && !order.reduce_only {
+                    self.rest_limit_remainder(order, &fills)?;
+                }
+
+                fills
+            },
+            OrderType::Market => {
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(&order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                let fills = self.fill_market_order(&mut order)?;
+
+                if fills.is_empty() {
+                    return Err(OrderBookError::NoLiquidity);
+                }
+
+                // Short of Fill or Kill, whatever the book or the slippage limit leaves unfilled is dropped like an
+                // IOC remainder, and the fills that did happen go back to the caller
+                fills
+            },
+            OrderType::AllOrNone => {
+                let can_fill_completely = self.can_fill_completely(&order)?;
+
+                if order.time_in_force == TimeInForce::Fok && !can_fill_completely {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                // Only trade on arrival if the whole order can go in one shot, otherwise rest it untouched
+                let fills = if can_fill_completely {
+                    self.fill_limit_order(&mut order)?
+                }
+                else {
+                    vec![]
+                };
+
+                if order.quantity > 0 && order.time_in_force.rests() && !order.reduce_only {
+                    self.rest_limit_remainder(order, &fills)?;
+                }
+
+                fills
+            },
+            OrderType::MarketNotional(notional) => {
+                order.quantity = self.notional_order_quantity(&order, notional)?;
+
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(&order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                self.fill_market_order(&mut order)?
+            },
+            OrderType::MidpointPeg => {
+                if order.time_in_force == TimeInForce::Fok && !self.can_fill_completely(&order)? {
+                    return Err(OrderBookError::CannotFillCompletely);
+                }
+
+                let fills = self.match_order_against_book(&mut order, 0, 0)?;
+                self.record_trades(&fills);
+
+                if order.quantity > 0 && order.time_in_force.rests() {
+                    order.order_status = if fills.is_empty() && order.filled_quantity == 0 {
+                        OrderStatus::Active
+                    }
+                    else {
+                        OrderStatus::PartiallyFilled
+                    };
+
+                    self.midpoint_orders.push(order);
+                }
+
+                fills
+            },
+            OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched => {
+                self.rest_stop_order(order)?;
+                vec![]
+            }
+        };
+    
+        Ok(fills)
+    }
+
+    // Whole lots the notional buys (or sells into) from the opposing side as it stands
+    fn notional_order_quantity(&self, order: &Order, notional: Decimal) -> Result<u64, OrderBookError> {
+        let opposing_quantity = match order.order_side {
+            OrderSide::Buy => self.total_ask_quantity,
+            OrderSide::Sell => self.total_bid_quantity
+        };
+
+        if opposing_quantity == 0 {
+            return Err(OrderBookError::NoLiquidity);
+        }
+
+        let (quantity, _) = self.fillable_quantity_for_notional(order.order_side.clone(), notional);
+        let quantity = quantity - quantity % self.config.lot_size;
+
+        // validate_order can't size a notional order, so its quantity is only checked here
+        self.check_order_quantity(quantity)?;
+
+        if order.reduce_only {
+            self.check_reduce_only(&Order { quantity, ..order.clone() })?;
+        }
+
+        Ok(quantity)
+    }
+
+    // Quantity the order left untraded, given the fills it produced, e.g. what a market order that ran out of book
+    // dropped. None for notional orders, which are sized in cash
+    pub fn unfilled_quantity(&self, order: &Order, fills: &[OrderFill]) -> Option<u64> {
+        if matches!(order.order_type, OrderType::MarketNotional(_)) {
+            return None;
+        }
+
+        let filled: u64 = fills.iter().map(|fill| fill.quantity).sum();
+
+        Some(order.quantity.saturating_sub(filled))
+    }
+
+    // Cash a notional order didn't spend, given the fills it produced. None for any other order type
+    pub fn unfilled_notional(&self, order: &Order, fills: &[OrderFill]) -> Option<Decimal> {
+        let OrderType::MarketNotional(notional) = order.order_type else {
+            return None;
+        };
+
+        let spent: Decimal = fills.iter()
+            .map(|fill| self.config.notional(fill.price, fill.quantity))
+            .sum();
+
+        Some(notional - spent)
+    }
+
+    #[inline(never)]
+    fn fill_limit_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let fills = match order.order_side {
+            OrderSide::Buy => {
+                self.match_order_against_book(order, 0, self.level_index(order.price))?
+            }
+            OrderSide::Sell => {
+                self.match_order_against_book(order, self.level_index(order.price), self.bids.len() - 1)?
+            }
+        };
+
+        self.record_trades(&fills);
+
+        Ok(fills)
+    }
+
+    #[inline(never)]
+    fn fill_market_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let (start_index, end_index) = self.market_sweep_range(&order.order_side);
+        let fills = self.match_order_against_book(order, start_index, end_index)?;
+
+        self.record_trades(&fills);
+
+        Ok(fills)
+    }
+
+    // Levels a market order on this side may match, cut off max_slippage_ticks past the opposing best price
+    fn market_sweep_range(&self, order_side: &OrderSide) -> (usize, usize) {
+        let max_slippage = self.config.max_slippage_ticks as usize;
+
+        match order_side {
+            OrderSide::Buy => {
+                let end_index = self.occupied_ask_levels.first()
+                    .map_or(self.asks.len() - 1, |&best_ask| best_ask.saturating_add(max_slippage).min(self.asks.len() - 1));
+
+                (0, end_index)
+            },
+            OrderSide::Sell => {
+                let start_index = self.occupied_bid_levels.last()
+                    .map_or(0, |&best_bid| best_bid.saturating_sub(max_slippage));
+
+                (start_index, self.bids.len() - 1)
+            }
+        }
+    }
+
+    // Worst price a market order on this side may trade at, the far end of its sweep range
+    fn market_limit_price(&self, order_side: &OrderSide) -> u32 {
+        let (start_index, end_index) = self.market_sweep_range(order_side);
+
+        match order_side {
+            OrderSide::Buy => self.level_price(end_index),
+            OrderSide::Sell => self.level_price(start_index)
+        }
+    }
+
+    #[inline(never)]
+    fn fill_immediate_or_cancel_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let fills = self.fill_limit_order(order)?;
+        
+        Ok(fills)
+    }
+
+    #[inline(never)]
+    fn fill_fill_or_kill_order(&mut self, order: &mut Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        if !self.can_fill_completely(order)? {
+            return Err(OrderBookError::CannotFillCompletely);
+        }
+
+        let fills = self.fill_limit_order(order)?;
+
+        Ok(fills)
+    }
+
+    // Trades the aggressor against contra midpoint orders in arrival order, all at the one mid price
+    fn match_midpoint_orders(&mut self, aggressive_order: &mut Order, mid_price: u32, fills: &mut Vec<OrderFill>) {
+        let mut i = 0;
+
+        while aggressive_order.quantity > 0 && i < self.midpoint_orders.len() {
+            if self.midpoint_orders[i].order_side == aggressive_order.order_side {
+                i += 1;
+                continue;
+            }
+
+            let timestamp = self.clock.now();
+            let resting_order = &mut self.midpoint_orders[i];
+            let quantity = aggressive_order.quantity.min(resting_order.quantity);
+
+            fills.push(OrderFill {
+                aggressive_order_id: aggressive_order.order_id,
+                resting_order_id: resting_order.order_id,
+                aggressor_side: aggressive_order.order_side.clone(),
+                aggressive_user_id: aggressive_order.user_id,
+                resting_user_id: resting_order.user_id,
+                price: mid_price,
+                quantity,
+                timestamp
+            });
+
+            aggressive_order.quantity -= quantity;
+            aggressive_order.filled_quantity += quantity;
+            resting_order.quantity -= quantity;
+            resting_order.filled_quantity += quantity;
+            self.last_trade_price = Some(mid_price);
+
+            if resting_order.quantity == 0 {
+                self.midpoint_orders.remove(i);
+                self.stats.orders_filled += 1;
+            }
+            else {
+                resting_order.order_status = OrderStatus::PartiallyFilled;
+                i += 1;
+            }
+        }
+    }
+
+    // Crosses resting midpoint orders left over from a time without a mid, oldest first, each taking the role of
+    // aggressor in turn
+    fn match_resting_midpoint_orders(&mut self) {
+        if self.mid_price().is_none() {
+            return;
+        }
+
+        let mut i = 0;
+
+        while i < self.midpoint_orders.len() {
+            let mut order = self.midpoint_orders.remove(i);
+
+            let fills = self.match_order_against_book(&mut order, 0, 0).unwrap_or_default();
+            self.record_trades(&fills);
+
+            if order.quantity > 0 {
+                if !fills.is_empty() {
+                    order.order_status = OrderStatus::PartiallyFilled;
+                }
+
+                self.midpoint_orders.insert(i, order);
+                i += 1;
+            }
+        }
+    }
+
+    #[inline(never)]
+    fn match_order_against_book(&mut self, aggressive_order: &mut Order, start_index: usize, end_index: usize) -> Result<Vec<OrderFill>, OrderBookError> {
+        let mut fills = Vec::new();
+
+        // Midpoint orders never touch the lit levels, only contra midpoint orders at the current mid
+        if aggressive_order.order_type == OrderType::MidpointPeg {
+            if let Some(mid_price) = self.mid_price() {
+                self.match_midpoint_orders(aggressive_order, mid_price, &mut fills);
+            }
+
+            if !fills.is_empty() {
+                if aggressive_order.quantity == 0 {
+                    self.stats.orders_filled += 1;
+                }
+                else {
+                    self.stats.orders_partially_filled += 1;
+                }
+
+                if self.events_enabled() {
+                    self.record_fill_events(aggressive_order, &fills);
+                }
+            }
+
+            return Ok(fills);
+        }
+
+        let match_side = if aggressive_order.order_side == OrderSide::Buy {
+            OrderSide::Sell
+        }
+        else {
+            OrderSide::Buy
+        };
+
+        match match_side {
+            OrderSide::Buy => {
+                // Exclusive, and pulled down past each level matched so emptied levels are never revisited
+                let mut end_bound = end_index + 1;
+                while aggressive_order.quantity > 0
+                    && start_index < end_bound
+                    && let Some(&i) = self.occupied_bid_levels.range(start_index..end_bound).next_back() {
+                    let mut queue = std::mem::take(&mut self.bids[i]);
+
+                    let match_result = self.match_level(&mut queue, aggressive_order, &mut fills);
+
+                    self.bids[i] = queue;
+                    match_result?;
+
+                    end_bound = i;
+                }
+            },
+            OrderSide::Sell => {
+                let mut start_index = start_index;
+                while aggressive_order.quantity > 0
+                    && start_index <= end_index
+                    && let Some(&i) = self.occupied_ask_levels.range(start_index..=end_index).next() {
+                    let mut queue = std::mem::take(&mut self.asks[i]);
+
+                    let match_result = self.match_level(&mut queue, aggressive_order, &mut fills);
+
+                    self.asks[i] = queue;
+                    match_result?;
+
+                    start_index = i + 1;
+                }
+            }
+        }
+
+        if !fills.is_empty() {
+            if aggressive_order.quantity == 0 {
+                self.stats.orders_filled += 1;
+            }
+            else {
+                self.stats.orders_partially_filled += 1;
+            }
+
+            if self.events_enabled() {
+                self.record_fill_events(aggressive_order, &fills);
+            }
+        }
+
+        Ok(fills)
+    }
+
+    // Each resting order trades at most once per aggressor, so its state after matching is its state after its fill
+    fn record_fill_events(&mut self, aggressive_order: &Order, fills: &[OrderFill]) {
+        let mut aggressor_remaining = aggressive_order.quantity + fills.iter().map(|fill| fill.quantity).sum::<u64>();
+
+        for fill in fills {
+            aggressor_remaining -= fill.quantity;
+
+            let resting_remaining = match self.index_mappings.get(&fill.resting_order_id) {
+                Some(&idx) => self.order_ledger[idx].quantity,
+                None => self.midpoint_orders.iter()
+                    .find(|order| order.order_id == fill.resting_order_id)
+                    .map_or(0, |order| order.quantity)
+            };
+
+            for (order_id, remaining_quantity) in [(fill.aggressive_order_id, aggressor_remaining), (fill.resting_order_id, resting_remaining)] {
+                let event = if remaining_quantity == 0 {
+                    OrderBookEvent::OrderFilled { order_id, price: fill.price, quantity: fill.quantity, timestamp: fill.timestamp }
+                }
+                else {
+                    OrderBookEvent::OrderPartiallyFilled { order_id, price: fill.price, quantity: fill.quantity, remaining_quantity, timestamp: fill.timestamp }
+                };
+
+                self.record_event(event);
+            }
+        }
+    }
+
+    fn match_level(&mut self, queue: &mut VecDeque<usize>, aggressive_order: &mut Order, fills: &mut Vec<OrderFill>) -> Result<(), OrderBookError> {
+        match self.config.matching_policy {
+            MatchingPolicy::PriceTime => {
+                // All or None orders passed over keep their place at the front of the level
+                let mut skipped_order_indices = vec![];
+                let mut fill_result = Ok(());
+
+                while aggressive_order.quantity > 0
+                    && let Some(&resting_order_index) = queue.front() {
+                    if !self.can_match_resting_order(resting_order_index, aggressive_order.quantity) {
+                        queue.pop_front();
+                        skipped_order_indices.push(resting_order_index);
+                        continue;
+                    }
+
+                    if let Err(err) = self.fill_order(queue, aggressive_order, resting_order_index, fills) {
+                        fill_result = Err(err);
+                        break;
+                    }
+                }
+
+                for resting_order_index in skipped_order_indices.into_iter().rev() {
+                    queue.push_front(resting_order_index);
+                }
+
+                fill_result?;
+            },
+            MatchingPolicy::ProRata { min_lot } => {
+                let allocations = self.pro_rata_level_allocations(queue, aggressive_order.quantity, min_lot);
+
+                let resting_order_indices = std::mem::take(queue);
+                let mut survivor = VecDeque::with_capacity(1);
+                let mut remaining_quantity = aggressive_order.quantity;
+
+                for (resting_order_index, allocation) in resting_order_indices.into_iter().zip(allocations) {
+                    if allocation == 0 {
+                        queue.push_back(resting_order_index);
+                        continue;
+                    }
+
+                    // fill_order consumes min(resting, aggressive), so cap the aggressor at this order's allocation
+                    aggressive_order.quantity = allocation;
+                    survivor.push_back(resting_order_index);
+                    let fill_result = self.fill_order(&mut survivor, aggressive_order, resting_order_index, fills);
+                    remaining_quantity -= allocation - aggressive_order.quantity;
+                    aggressive_order.quantity = remaining_quantity;
+                    fill_result?;
+
+                    queue.extend(survivor.drain(..));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // What each order in the queue would take from aggressive_quantity under the matching policy, in queue order.
+    // match_level trades exactly these, so previews and fill checks go through here too
+    fn level_allocations(&self, queue: &VecDeque<usize>, aggressive_quantity: u64) -> Vec<u64> {
+        match self.config.matching_policy {
+            MatchingPolicy::PriceTime => {
+                let mut remaining_quantity = aggressive_quantity;
+
+                queue.iter()
+                    .map(|&idx| {
+                        if remaining_quantity == 0 || !self.can_match_resting_order(idx, remaining_quantity) {
+                            return 0;
+                        }
+
+                        let allocation = self.order_ledger[idx].quantity.min(remaining_quantity);
+                        remaining_quantity -= allocation;
+                        allocation
+                    })
+                    .collect()
+            },
+            MatchingPolicy::ProRata { min_lot } => self.pro_rata_level_allocations(queue, aggressive_quantity, min_lot)
+        }
+    }
+
+    // Visible orders split the aggressor pro rata first, and hidden orders share whatever is left
+    fn pro_rata_level_allocations(&self, queue: &VecDeque<usize>, aggressive_quantity: u64, min_lot: u64) -> Vec<u64> {
+        let mut allocations = vec![0; queue.len()];
+        let mut remaining_quantity = aggressive_quantity;
+
+        for hidden_pass in [false, true] {
+            let mut eligible: Vec<bool> = queue.iter()
+                .map(|&idx| self.order_ledger[idx].hidden == hidden_pass)
+                .collect();
+
+            // All or None orders are only eligible for their full size, so one whose share falls short drops out
+            // and the split is rerun among the rest. Dropping an order only grows the others' shares, so an All
+            // or None order that got its full size keeps it
+            let pass_allocations = loop {
+                let resting_quantities: Vec<u64> = queue.iter().zip(&eligible)
+                    .map(|(&idx, &is_eligible)| {
+                        if is_eligible && self.can_match_resting_order(idx, remaining_quantity) {
+                            self.order_ledger[idx].quantity
+                        }
+                        else {
+                            0
+                        }
+                    })
+                    .collect();
+
+                if remaining_quantity == 0 || resting_quantities.iter().all(|&quantity| quantity == 0) {
+                    break vec![0; queue.len()];
+                }
+
+                let pass_allocations = Self::pro_rata_allocations(&resting_quantities, remaining_quantity, min_lot);
+
+                let mut all_or_none_short = false;
+                for ((&idx, is_eligible), &pass_allocation) in queue.iter().zip(eligible.iter_mut()).zip(&pass_allocations) {
+                    let resting_order = &self.order_ledger[idx];
+
+                    if *is_eligible && resting_order.order_type == OrderType::AllOrNone && pass_allocation > 0 && pass_allocation != resting_order.quantity {
+                        *is_eligible = false;
+                        all_or_none_short = true;
+                    }
+                }
+
+                if !all_or_none_short {
+                    break pass_allocations;
+                }
+            };
+
+            for (allocation, pass_allocation) in allocations.iter_mut().zip(pass_allocations) {
+                *allocation += pass_allocation;
+                remaining_quantity -= pass_allocation;
+            }
+        }
+
+        allocations
+    }
+
+    fn can_match_resting_order(&self, resting_order_index: usize, aggressive_quantity: u64) -> bool {
+        let resting_order = &self.order_ledger[resting_order_index];
+
+        resting_order.order_type != OrderType::AllOrNone || aggressive_quantity >= resting_order.quantity
+    }
+
+    // Splits aggressive_quantity across resting_quantities proportionally to their size, rounded down to
+    // multiples of min_lot. Whatever is left over is handed out in time priority so the level is used fully.
+    pub fn pro_rata_allocations(resting_quantities: &[u64], aggressive_quantity: u64, min_lot: u64) -> Vec<u64> {
+        let level_quantity: u128 = resting_quantities.iter().map(|&quantity| quantity as u128).sum();
+
+        if level_quantity <= aggressive_quantity as u128 {
+            return resting_quantities.to_vec();
+        }
+
+        let min_lot = min_lot.max(1);
+
+        let mut allocations: Vec<u64> = resting_quantities.iter()
+            .map(|&quantity| {
+                // Widened so the product can't overflow for quantities near u64::MAX
+                let proportional = (aggressive_quantity as u128 * quantity as u128 / level_quantity) as u64;
+                proportional - proportional % min_lot
+            })
+            .collect();
+
+        let mut remainder = aggressive_quantity - allocations.iter().sum::<u64>();
+
+        for (allocation, &quantity) in allocations.iter_mut().zip(resting_quantities) {
+            if remainder == 0 {
+                break;
+            }
+
+            let top_up = remainder.min(quantity - *allocation);
+            *allocation += top_up;
+            remainder -= top_up;
+        }
+
+        allocations
+    }
+
+    #[inline(never)]
+    // Once the order has traded, a remainder that the level or book limits won't take is dropped like an IOC's, so
+    // the caller still gets the fills. Only an order that hasn't traded is rejected for them
+    fn rest_limit_remainder(&mut self, order: Order, fills: &[OrderFill]) -> Result<(), OrderBookError> {
+        match self.rest_remaining_limit_order(order, !fills.is_empty()) {
+            Err(OrderBookError::LevelOrderLimitReached(_) | OrderBookError::BookFull(_)) if !fills.is_empty() => Ok(()),
+            result => result
+        }
+    }
+
+    fn rest_remaining_limit_order(&mut self, mut order: Order, partially_filled: bool) -> Result<(), OrderBookError> {
+        if order.order_type != OrderType::Limit && order.order_type != OrderType::AllOrNone {
+            return Err(OrderBookError::NonLimitOrderRestAttempt);
+        }
+
+        let index = self.level_index(order.price);
+        let level_order_count = match order.order_side {
+            OrderSide::Buy => self.bids.get(index).map_or(0, VecDeque::len),
+            OrderSide::Sell => self.asks.get(index).map_or(0, VecDeque::len)
+        };
+
+        if level_order_count >= self.config.max_orders_per_level {
+            return Err(OrderBookError::LevelOrderLimitReached(self.config.max_orders_per_level));
+        }
+
+        if self.resting_order_count() >= self.config.max_resting_orders {
+            return Err(OrderBookError::BookFull(self.config.max_resting_orders));
+        }
+
+        // An amended order that traded before keeps its partially filled status
+        order.order_status = if partially_filled || order.filled_quantity > 0 {
+            OrderStatus::PartiallyFilled
+        }
+        else {
+            OrderStatus::Active
+        };
+
+        // Visible orders queue ahead of any hidden orders already at the level
+        let queue_behind_visible = !order.hidden && match order.order_side {
+            OrderSide::Buy => self.bid_hidden_quantities[index] > 0,
+            OrderSide::Sell => self.ask_hidden_quantities[index] > 0
+        };
+
+        self.add_level_quantity(&order.order_side, order.price, order.quantity, order.hidden);
+        self.user_orders.entry(order.user_id).or_default().insert(order.order_id);
+
+        if self.events_enabled() {
+            self.record_event(OrderBookEvent::OrderRested {
+                order_id: order.order_id,
+                order_side: order.order_side.clone(),
+                price: order.price,
+                quantity: order.quantity,
+                timestamp: self.clock.now()
+            });
+        }
+
+        match order.order_side {
+            OrderSide::Buy => {
+                if let Some(queue) = self.bids.get_mut(index) {
+                    let order_id = order.order_id;
+                    let order_index = self.order_ledger.insert(order);
+                    Self::enqueue(queue, &self.order_ledger, order_index, queue_behind_visible);
+                    self.index_mappings.insert(order_id, order_index);
+                }
+                else {
+                    let order_id = order.order_id;
+                    let order_index = self.order_ledger.insert(order);
+                    let mut queue = VecDeque::new();
+                    queue.push_back(order_index);
+                    self.bids.insert(index, queue);
+                    self.index_mappings.insert(order_id, order_index);
+                }
+            },
+            OrderSide::Sell => {
+                if let Some(queue) = self.asks.get_mut(index) {
+                    let order_id = order.order_id;
+                    let order_index = self.order_ledger.insert(order);
+                    Self::enqueue(queue, &self.order_ledger, order_index, queue_behind_visible);
+                    self.index_mappings.insert(order_id, order_index);
+                }
+                else {
+                    let order_id = order.order_id;
+                    let order_index = self.order_ledger.insert(order);
+                    let mut queue = VecDeque::new();
+                    queue.push_back(order_index);
+                    self.asks.insert(index, queue);
+                    self.index_mappings.insert(order_id, order_index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // validate_order has already checked the trigger
+    fn rest_stop_order(&mut self, order: Order) -> Result<(), OrderBookError> {
+        self.stop_orders.push(order);
+
+        Ok(())
+    }
+
+    fn enqueue(queue: &mut VecDeque<usize>, order_ledger: &Slab<Order>, order_index: usize, behind_visible: bool) {
+        if behind_visible {
+            let position = queue.iter().position(|&idx| order_ledger[idx].hidden).unwrap_or(queue.len());
+            queue.insert(position, order_index);
+        }
+        else {
+            queue.push_back(order_index);
+        }
+    }
+
+    fn add_level_quantity(&mut self, side: &OrderSide, price: u32, quantity: u64, hidden: bool) {
+        // Hidden orders never change what's displayed, so they don't produce deltas
+        if self.config.track_level_deltas && !hidden {
+            self.changed_levels.push((side.clone(), price));
+        }
+
+        let index = self.level_index(price);
+
+        match side {
+            OrderSide::Buy => {
+                if self.bid_quantities[index] == 0 && quantity > 0 {
+                    self.occupied_bid_levels.insert(index);
+                }
+                self.bid_quantities[index] += quantity;
+                self.total_bid_quantity += quantity;
+
+                if hidden {
+                    self.bid_hidden_quantities[index] += quantity;
+                }
+            },
+            OrderSide::Sell => {
+                if self.ask_quantities[index] == 0 && quantity > 0 {
+                    self.occupied_ask_levels.insert(index);
+                }
+                self.ask_quantities[index] += quantity;
+                self.total_ask_quantity += quantity;
+
+                if hidden {
+                    self.ask_hidden_quantities[index] += quantity;
+                }
+            }
+        }
+    }
+
+    // Saturating so that orders placed directly into the ledger (bypassing resting) can't underflow the totals
+    fn remove_level_quantity(&mut self, side: &OrderSide, price: u32, quantity: u64, hidden: bool) {
+        if self.config.track_level_deltas && !hidden {
+            self.changed_levels.push((side.clone(), price));
+        }
+
+        let index = self.level_index(price);
+
+        match side {
+            OrderSide::Buy => {
+                if self.bid_quantities[index] > 0 && self.bid_quantities[index] <= quantity {
+                    self.occupied_bid_levels.remove(&index);
+                }
+                self.bid_quantities[index] = self.bid_quantities[index].saturating_sub(quantity);
+                self.total_bid_quantity = self.total_bid_quantity.saturating_sub(quantity);
+
+                if hidden {
+                    self.bid_hidden_quantities[index] = self.bid_hidden_quantities[index].saturating_sub(quantity);
+                }
+            },
+            OrderSide::Sell => {
+                if self.ask_quantities[index] > 0 && self.ask_quantities[index] <= quantity {
+                    self.occupied_ask_levels.remove(&index);
+                }
+                self.ask_quantities[index] = self.ask_quantities[index].saturating_sub(quantity);
+                self.total_ask_quantity = self.total_ask_quantity.saturating_sub(quantity);
+
+                if hidden {
+                    self.ask_hidden_quantities[index] = self.ask_hidden_quantities[index].saturating_sub(quantity);
+                }
+            }
+        }
+    }
+
+    // Checks the book's internal structures agree with each other, e.g. after restoring state or pushing into the
+    // levels directly
+    pub fn validate(&self) -> Result<(), OrderBookError> {
+        for (&order_id, &ledger_index) in &self.index_mappings {
+            match self.order_ledger.get(ledger_index) {
+                Some(order) if order.order_id == order_id => {},
+                Some(order) => return Err(OrderBookError::CorruptBook(format!("order {order_id} maps to ledger slot {ledger_index} holding order {}", order.order_id))),
+                None => return Err(OrderBookError::CorruptBook(format!("order {order_id} maps to empty ledger slot {ledger_index}")))
+            }
+        }
+
+        let mut queued_orders = 0;
+
+        for (side, levels) in [(OrderSide::Buy, &self.bids), (OrderSide::Sell, &self.asks)] {
+            for (index, queue) in levels.iter().enumerate() {
+                let price = self.level_price(index);
+
+                for &ledger_index in queue {
+                    let order = self.order_ledger.get(ledger_index)
+                        .ok_or_else(|| OrderBookError::CorruptBook(format!("{side} level {price} queues empty ledger slot {ledger_index}")))?;
+
+                    if order.order_side != side || self.checked_level_index(order.price) != Some(index) {
+                        return Err(OrderBookError::CorruptBook(format!("order {} ({} at {}) is queued on the {side} level {price}", order.order_id, order.order_side, order.price)));
+                    }
+
+                    if self.index_mappings.get(&order.order_id) != Some(&ledger_index) {
+                        return Err(OrderBookError::CorruptBook(format!("queued order {} has no mapping to ledger slot {ledger_index}", order.order_id)));
+                    }
+
+                    queued_orders += 1;
+                }
+            }
+        }
+
+        if queued_orders != self.order_ledger.len() || queued_orders != self.index_mappings.len() {
+            return Err(OrderBookError::CorruptBook(format!("{queued_orders} orders are queued but the ledger holds {} and {} are mapped", self.order_ledger.len(), self.index_mappings.len())));
+        }
+
+        let best_bid = self.bids.iter().rposition(|queue| self.is_matchable_level(queue));
+        let best_ask = self.asks.iter().position(|queue| self.is_matchable_level(queue));
+
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask)
+            && best_bid >= best_ask {
+            return Err(OrderBookError::CorruptBook(format!("book is crossed: best bid {best_bid} >= best ask {best_ask}")));
+        }
+
+        for (side, levels, occupied_levels) in [(OrderSide::Buy, &self.bids, &self.occupied_bid_levels), (OrderSide::Sell, &self.asks, &self.occupied_ask_levels)] {
+            if let Some(index) = (0..levels.len()).find(|index| levels[*index].is_empty() == occupied_levels.contains(index)) {
+                return Err(OrderBookError::CorruptBook(format!("{side} level {} occupancy is out of sync with its queue", self.level_price(index))));
+            }
+        }
+
+        Ok(())
+    }
+
+    // All or None orders may legitimately rest through the opposing side, so only levels holding at least one
+    // order that could have matched count towards crossing
+    fn is_matchable_level(&self, queue: &VecDeque<usize>) -> bool {
+        queue.iter().any(|&idx| self.order_ledger[idx].order_type != OrderType::AllOrNone)
+    }
+
+    fn debug_assert_book_not_crossed(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let is_matchable_level = |queue: &VecDeque<usize>| self.is_matchable_level(queue);
+
+        let best_matchable_bid = self.occupied_bid_levels.iter().rev().find(|&&i| is_matchable_level(&self.bids[i]));
+        let best_matchable_ask = self.occupied_ask_levels.iter().find(|&&i| is_matchable_level(&self.asks[i]));
+
+        if let (Some(best_bid), Some(best_ask)) = (best_matchable_bid, best_matchable_ask) {
+            assert!(best_bid < best_ask, "Order book is crossed: best bid {best_bid} >= best ask {best_ask}");
+        }
+    }
+
+    // Whether the whole order would trade on arrival. Market orders (notional ones once sized) reach as far as
+    // their sweep range, and midpoint orders only ever meet contra midpoint orders at the current mid
+    #[inline(never)]
+    fn can_fill_completely(&self, order: &Order) -> Result<bool, OrderBookError> {
+        if order.order_type == OrderType::MidpointPeg {
+            return Ok(self.midpoint_contra_quantity(&order.order_side) >= order.quantity);
+        }
+
+        let limit_price = if matches!(order.order_type, OrderType::Market | OrderType::MarketNotional(_)) {
+            self.market_limit_price(&order.order_side)
+        }
+        else if order.price > self.config.max_price {
+            return Err(OrderBookError::PriceOutOfRange);
+        }
+        else {
+            order.price
+        };
+
+        let resting_side = match order.order_side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy
+        };
+
+        Ok(self.reachable_quantity(&resting_side, limit_price, order.quantity) == order.quantity)
+    }
+
+    // Resting midpoint quantity a midpoint order on this side could trade with right now, 0 without a mid
+    fn midpoint_contra_quantity(&self, order_side: &OrderSide) -> u64 {
+        if self.mid_price().is_none() {
+            return 0;
+        }
+
+        self.midpoint_orders.iter()
+            .filter(|resting_order| resting_order.order_side != *order_side)
+            .map(|resting_order| resting_order.quantity)
+            .sum()
+    }
+
+    // Levels on the resting side that an aggressor limited to limit_price can trade with: asks at or below it,
+    // bids at or above it
+    fn reachable_levels(&self, resting_side: &OrderSide, limit_price: u32) -> &[VecDeque<usize>] {
+        match resting_side {
+            OrderSide::Sell => self.level_index_at_or_below(limit_price).map_or(&[], |index| &self.asks[..=index]),
+            OrderSide::Buy => self.level_index_at_or_above(limit_price).map_or(&[], |index| &self.bids[index..])
+        }
+    }
+
+    // Levels are stored one per tick from min_price, so the arrays only hold prices the book can trade at. Callers
+    // must have checked the price is in range and on the tick grid
+    fn level_index(&self, price: u32) -> usize {
+        ((price - self.config.min_price) / self.config.tick_size) as usize
+    }
+
+    fn level_price(&self, index: usize) -> u32 {
+        self.config.min_price + index as u32 * self.config.tick_size
+    }
+
+    // None for prices outside the book or off the tick grid, which have no level
+    fn checked_level_index(&self, price: u32) -> Option<usize> {
+        (price >= self.config.min_price
+            && price <= self.config.max_price
+            && (price - self.config.min_price).is_multiple_of(self.config.tick_size))
+            .then(|| self.level_index(price))
+    }
+
+    // Highest level priced at or below price, None if the whole book is above it
+    fn level_index_at_or_below(&self, price: u32) -> Option<usize> {
+        (price >= self.config.min_price).then(|| self.level_index(price.min(self.config.max_price)))
+    }
+
+    // Lowest level priced at or above price, None if the whole book is below it
+    fn level_index_at_or_above(&self, price: u32) -> Option<usize> {
+        (price <= self.config.max_price)
+            .then(|| (price.max(self.config.min_price) - self.config.min_price).div_ceil(self.config.tick_size) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{enums::trigger_direction::TriggerDirection, models::order_builder::OrderBuilder, utils::MockClock};
+
+    #[test]
+    fn test_fill_order_correctly_fills_aggressive_order_resting_and_aggressive_order_quantities_equal() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 800,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let mut buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 800,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+
+        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
+        order_book.asks[price_index].push_back(sell_order_index);
+
+        let mut queue = order_book.asks[price_index].clone();
+        let mut fills = Vec::new();
+
+        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+
+        assert!(fill_order_result.is_ok());
+        assert!(fill_order_result.unwrap());
+        assert!(queue.is_empty());
+        assert!(fills.len() == 1);
+        assert!(fills[0].aggressive_order_id == buy_order.order_id);
+        assert!(fills[0].resting_order_id == sell_order.order_id);
+        assert!(fills[0].aggressor_side == OrderSide::Buy);
+        assert!(fills[0].aggressive_user_id == buy_order.user_id);
+        assert!(fills[0].resting_user_id == sell_order.user_id);
+    }
+
+    #[test]
+    fn test_fill_order_correctly_fills_aggressive_order_resting_order_quantity_greater_than_aggressive_order_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 800,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let mut buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
+        order_book.asks[price_index].push_back(sell_order_index);
+
+        let mut queue = order_book.asks[price_index].clone();
+        let mut fills = Vec::new();
+
+        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+
+        assert!(fill_order_result.is_ok());
+        assert!(fill_order_result.unwrap());
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0], sell_order_index);
+        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 500);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(fills[0].resting_order_id, sell_order.order_id);
+        assert_eq!(fills[0].aggressor_side, OrderSide::Buy);
+        assert_eq!(fills[0].aggressive_user_id, buy_order.user_id);
+        assert_eq!(fills[0].resting_user_id, sell_order.user_id);
+    }
+
+    #[test]
+    fn test_fill_order_correctly_fills_aggressive_order_aggressive_order_quantity_greater_than_resting_order_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let mut buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 800,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
+        order_book.asks[price_index].push_back(sell_order_index);
+
+        let mut queue = order_book.asks[price_index].clone();
+        let mut fills = Vec::new();
+
+        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+
+        assert!(fill_order_result.is_ok());
+        assert!(!fill_order_result.unwrap());
+        assert!(queue.is_empty());
+        assert_eq!(buy_order.quantity, 500);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(fills[0].resting_order_id, sell_order.order_id);
+        assert_eq!(fills[0].aggressor_side, OrderSide::Buy);
+        assert_eq!(fills[0].aggressive_user_id, buy_order.user_id);
+        assert_eq!(fills[0].resting_user_id, sell_order.user_id);
+    }
+
+    #[test]
+    fn test_add_order_correctly_adds_limit_order_to_empty_order_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = order.price as usize;
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        let order_index = order_book.index_mappings[&order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+    }
+
+    #[test]
+    fn test_add_order_correctly_executes_order_fill() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_sell_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_sell_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_buy_order_result = order_book.add_order(buy_order.clone());
+
+        assert!(add_buy_order_result.is_ok());
+        assert!(order_book.asks[price_index].is_empty());
+    }
+
+    #[test]
+    fn test_add_order_correctly_executes_order_fill_on_limit_order_and_adds_remaining_to_order_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_sell_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_sell_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let mut buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_buy_order_result = order_book.add_order(buy_order.clone());
+
+        buy_order.order_status = OrderStatus::PartiallyFilled;
+        buy_order.quantity = 200;
+
+        let buy_order_index = order_book.index_mappings[&buy_order.order_id];
+
+        assert!(add_buy_order_result.is_ok());
+        assert!(order_book.asks[price_index].is_empty());
+        assert_eq!(order_book.bids[price_index].len(), 1);
+        assert_eq!(order_book.bids[price_index][0], buy_order_index);
+    }
+
+    #[test]
+    fn test_add_order_errors_price_out_of_range() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 100000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        assert!(add_order_result.is_err());
+        assert_eq!(add_order_result.err().unwrap(), OrderBookError::PriceOutOfRange);
+    }
+
+    #[test]
+    fn test_cancel_order_correctly_cancels_resting_limit_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = order.price as usize;
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        order.order_status = OrderStatus::Active;
+
+        let order_index = order_book.index_mappings[&order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+
+        let cancel_order_result = order_book.cancel_order(order.order_id);
+
+        assert!(cancel_order_result.is_ok());
+        assert!(order_book.asks[price_index].is_empty());
+    }
+
+    #[test]
+    fn test_cancel_order_errors_order_not_found() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = order.price as usize;
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        order.order_status = OrderStatus::Active;
+
+        let order_index = order_book.index_mappings[&order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+
+        let cancel_order_result = order_book.cancel_order(OrderId(99));
+
+        assert!(cancel_order_result.is_err());
+        assert_eq!(cancel_order_result.err().unwrap(), OrderBookError::OrderNotFound);
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+    }
+
+    #[test]
+    fn test_cancel_order_errors_price_out_of_range() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10100,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = order.price as usize;
+
+        
+        let order_index = order_book.order_ledger.insert(order.clone());
+        order_book.asks.extend([const { VecDeque::new() }; 10000]);
+        order_book.asks[price_index].push_back(order_index);
+
+        let cancel_order_result = order_book.cancel_order(OrderId(99));
+
+        assert!(cancel_order_result.is_err());
+        assert_eq!(cancel_order_result.err().unwrap(), OrderBookError::OrderNotFound);
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+    }
+
+    #[test]
+    fn test_modify_order_correctly_modifies_resting_limit_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = order.price as usize;
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        order.order_status = OrderStatus::Active;
+
+        let order_index = order_book.index_mappings[&order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], order_index);
+
+        let mut modified_order = order.clone();
+        modified_order.quantity = 500;
+
+        let modify_order_result = order_book.modify_order(order.order_id, modified_order.clone());
+
+        let buy_order_index = order_book.index_mappings[&order.order_id];
+
+        assert!(modify_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[buy_order_index], modified_order);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_fills_limit_order_no_remaining_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert!(order_book.asks[price_index].is_empty());
+        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_fills_limit_order_with_remaining_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        let buy_order_index = order_book.index_mappings[&buy_order.order_id];
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert_eq!(order_book.bids[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[buy_order_index].quantity, 300);
+        assert!(order_book.asks[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_fills_market_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
+        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_returns_partial_fills_and_drops_market_order_remainder() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        let fills = execute_fill_by_order_type_result.unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, 300);
+        assert!(!order_book.contains_order(buy_order.order_id));
+        assert!(order_book.asks[price_index].is_empty());
+        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_fills_immediate_or_cancel_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
+        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_cancels_immediate_or_cancel_order_if_no_resting_order_exists() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc
+        };
+
+        let price_index = buy_order.price as usize;
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert!(order_book.asks[price_index].is_empty());
+        assert!(order_book.bids[price_index].is_empty());
+        assert!(order_book.trade_history.is_empty());
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_correctly_fills_fill_or_kill_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
+        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 300);
+    }
+
+    #[test]
+    fn test_execute_fill_by_order_type_errors_cannot_fill_completely() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 10000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 10000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        let price_index = sell_order.price as usize;
+
+        let add_order_result = order_book.add_order(sell_order.clone());
+
+        sell_order.order_status = OrderStatus::Active;
+
+        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+
+        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+
+        assert!(execute_fill_by_order_type_result.is_err());
+        assert_eq!(execute_fill_by_order_type_result.err().unwrap(), OrderBookError::CannotFillCompletely);
+        assert_eq!(order_book.asks[price_index].len(), 1);
+        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
+        assert!(order_book.bids[price_index].is_empty());
+        assert!(order_book.trade_history.is_empty());
+    }
+
+    #[test]
+    fn test_fill_limit_order_correctly_fills_buy_limit_order() {
+
+    }
+
+    #[test]
+    fn test_fill_limit_order_correctly_fills_sell_limit_order() {
+
+    }
+
+    #[test]
+    fn test_fill_market_order_correctly_fills_buy_market_order() {
+
+    }
+
+    #[test]
+    fn test_fill_market_order_correctly_fills_sell_market_order() {
+
+    }
+
+    #[test]
+    fn test_fill_immediate_or_cancel_order_correctly_fills_immediate_or_cancel_order() {
+
+    }
+
+    #[test]
+    fn test_fill_fill_or_kill_order_correctly_fills_fill_or_kill_order() {
+
+    }
+
+    #[test]
+    fn test_fill_fill_or_kill_order_errors_cannot_fill_completely() {
+
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_matches_and_fills_buy_order() {
+
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_matches_and_fills_buy_order_excess_quantity() {
+
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_matches_and_fills_sell_order() {
+
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_matches_and_fills_sell_order_excess_quantity() {
+
+    }
+
+    #[test]
+    fn test_rest_remaining_limit_order_correctly_rests_buy_limit_order() {
+
+    }
+
+    #[test]
+    fn test_rest_remaining_limit_order_correctly_rests_sell_limit_order() {
+
+    }
+
+    #[test]
+    fn test_rest_remaining_limit_order_errors_non_limit_order_rest_attempt() {
+
+    }
+
+    #[test]
+    fn test_can_fill_completely_correctly_returns_true_for_buy_order_that_can_be_filled_completely() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [
+            (0, 5000, 100),
+            (1, 5001, 100),
+            (2, 5002, 100)
+        ];
+
+        for (order_id, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let order = Order {
+            order_id: OrderId(10),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5001,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.can_fill_completely(&order), Ok(true));
+    }
+
+    #[test]
+    fn test_can_fill_completely_correctly_returns_false_for_buy_order_with_remaining_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [
+            (0, 5000, 100),
+            (1, 5001, 100),
+            (2, 5002, 500)
+        ];
+
+        for (order_id, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let order = Order {
+            order_id: OrderId(10),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5001,
+            quantity: 201,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.can_fill_completely(&order), Ok(false));
+    }
+
+    #[test]
+    fn test_can_fill_completely_correctly_returns_true_for_sell_order_that_can_be_filled_completely() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [
+            (0, 5000, 100),
+            (1, 4999, 100),
+            (2, 4998, 100)
+        ];
+
+        for (order_id, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let order = Order {
+            order_id: OrderId(10),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 4999,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.can_fill_completely(&order), Ok(true));
+    }
+
+    #[test]
+    fn test_can_fill_completely_correctly_returns_false_for_sell_order_with_remaining_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [
+            (0, 5000, 100),
+            (1, 4999, 100),
+            (2, 4998, 500)
+        ];
+
+        for (order_id, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let order = Order {
+            order_id: OrderId(10),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 4999,
+            quantity: 201,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.can_fill_completely(&order), Ok(false));
+    }
+
+    #[test]
+    fn test_total_quantity_correctly_tracks_resting_quantity_across_rest_fill_and_cancel() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order_1 = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5001,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order_2 = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5002,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 4999,
+            quantity: 400,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order_1.clone()).is_ok());
+        assert!(order_book.add_order(sell_order_2.clone()).is_ok());
+        assert!(order_book.add_order(buy_order.clone()).is_ok());
+
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 500);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 400);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5001), 300);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5002), 200);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4999), 400);
+
+        let aggressive_buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 350,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(aggressive_buy_order).is_ok());
+
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 150);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5001), 0);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5002), 150);
+
+        assert!(order_book.cancel_order(buy_order.order_id).is_ok());
+
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 0);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4999), 0);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 150);
+    }
+
+    #[test]
+    fn test_total_quantity_at_returns_zero_for_price_outside_of_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let order_book = OrderBook::new(config);
+
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 20000), 0);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 0);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 0);
+    }
+
+    #[test]
+    fn test_on_trade_price_correctly_triggers_buy_stop_order_when_price_rises() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5010,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_stop_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Stop,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 200,
+            stop_price: Some(5005),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+        assert!(order_book.add_order(buy_stop_order.clone()).is_ok());
+
+        assert_eq!(order_book.stop_orders.len(), 1);
+        assert!(order_book.bids.iter().all(|queue| queue.is_empty()));
+
+        let activated_below_trigger = order_book.on_trade_price(5004);
+
+        assert!(activated_below_trigger.is_empty());
+        assert_eq!(order_book.stop_orders.len(), 1);
+
+        let activated_at_trigger = order_book.on_trade_price(5005);
+
+        assert_eq!(activated_at_trigger, vec![buy_stop_order.order_id]);
+        assert!(order_book.stop_orders.is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_stop_order.order_id);
+        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.trade_history[0].quantity, 200);
+        assert_eq!(order_book.last_trade_price, Some(5010));
+    }
+
+    #[test]
+    fn test_on_trade_price_correctly_triggers_sell_stop_limit_order_when_price_falls() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_stop_limit_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::StopLimit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 300,
+            stop_price: Some(4995),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_stop_limit_order.clone()).is_ok());
+
+        let activated_above_trigger = order_book.on_trade_price(5000);
+
+        assert!(activated_above_trigger.is_empty());
+        assert!(order_book.asks[4990].is_empty());
+
+        let activated_below_trigger = order_book.on_trade_price(4994);
+
+        assert_eq!(activated_below_trigger, vec![sell_stop_limit_order.order_id]);
+        assert!(order_book.stop_orders.is_empty());
+        assert_eq!(order_book.asks[4990].len(), 1);
+
+        let resting_order = &order_book.order_ledger[order_book.asks[4990][0]];
+
+        assert_eq!(resting_order.order_type, OrderType::Limit);
+        assert_eq!(resting_order.order_status, OrderStatus::Active);
+        assert_eq!(resting_order.quantity, 300);
+    }
+
+    #[test]
+    fn test_add_order_correctly_triggers_sell_stop_order_from_trade() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order_1 = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order_2 = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_stop_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Stop,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: Some(5000),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(2),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_order_1.clone()).is_ok());
+        assert!(order_book.add_order(buy_order_2.clone()).is_ok());
+        assert!(order_book.add_order(sell_stop_order.clone()).is_ok());
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+
+        assert!(order_book.stop_orders.is_empty());
+        assert_eq!(order_book.trade_history.len(), 2);
+        assert_eq!(order_book.trade_history[1].aggressive_order_id, sell_stop_order.order_id);
+        assert_eq!(order_book.trade_history[1].resting_order_id, buy_order_2.order_id);
+        assert_eq!(order_book.last_trade_price, Some(4990));
+    }
+
+    #[test]
+    fn test_add_order_errors_missing_stop_price() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_stop_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Stop,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = order_book.add_order(buy_stop_order);
+
+        assert_eq!(add_order_result.err().unwrap(), OrderBookError::MissingStopPrice);
+        assert!(order_book.stop_orders.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_order_correctly_cancels_pending_stop_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_stop_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Stop,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: Some(5005),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_stop_order.clone()).is_ok());
+        assert!(order_book.cancel_order(buy_stop_order.order_id).is_ok());
+        assert!(order_book.stop_orders.is_empty());
+        assert!(order_book.on_trade_price(6000).is_empty());
+    }
+
+    #[test]
+    fn test_add_order_correctly_returns_fills_generated_by_submission() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order_1 = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order_2 = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5001,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5001,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order_1.clone()).unwrap().is_empty());
+        assert!(order_book.add_order(sell_order_2.clone()).unwrap().is_empty());
+
+        let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].aggressive_order_id, buy_order.order_id);
+        assert_eq!(fills[0].resting_order_id, sell_order_1.order_id);
+        assert_eq!(fills[0].aggressor_side, OrderSide::Buy);
+        assert_eq!(fills[0].resting_user_id, sell_order_1.user_id);
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 200);
+        assert_eq!(fills[1].resting_order_id, sell_order_2.order_id);
+        assert_eq!(fills[1].price, 5001);
+        assert_eq!(fills[1].quantity, 100);
+        assert_eq!(order_book.trade_history.len(), 2);
+    }
+
+    #[test]
+    fn test_add_order_at_price_correctly_converts_real_price_to_tick() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = order_book.add_order_at_price(buy_order, Decimal::new(5025, 2));
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.bids[5025].len(), 1);
+        assert_eq!(order_book.best_bid_index(), Some(5025));
+    }
+
+    #[test]
+    fn test_add_order_at_price_errors_invalid_tick_and_price_out_of_range() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let misaligned_result = order_book.add_order_at_price(buy_order.clone(), Decimal::new(50255, 3));
+        let out_of_range_result = order_book.add_order_at_price(buy_order, Decimal::new(10001, 2));
+
+        assert_eq!(misaligned_result.err().unwrap(), OrderBookError::InvalidTick(1));
+        assert_eq!(out_of_range_result.err().unwrap(), OrderBookError::PriceOutOfRange);
+        assert!(order_book.order_ledger.is_empty());
+    }
+
+    #[test]
+    fn test_add_order_errors_duplicate_order_id_and_leaves_original_order_untouched() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let duplicate_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 6000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_order.clone()).is_ok());
+
+        let buy_order_index = order_book.index_mappings[&buy_order.order_id];
+
+        let add_order_result = order_book.add_order(duplicate_order);
+
+        assert_eq!(add_order_result.err().unwrap(), OrderBookError::DuplicateOrderId(OrderId(0)));
+        assert_eq!(order_book.index_mappings[&buy_order.order_id], buy_order_index);
+        assert_eq!(order_book.bids[5000].len(), 1);
+        assert_eq!(order_book.order_ledger[buy_order_index].quantity, 100);
+        assert_eq!(order_book.order_ledger[buy_order_index].order_side, OrderSide::Buy);
+        assert!(order_book.asks[6000].is_empty());
+        assert!(order_book.cancel_order(buy_order.order_id).is_ok());
+        assert!(order_book.bids[5000].is_empty());
+    }
+
+    #[test]
+    fn test_add_order_errors_duplicate_order_id_of_pending_stop_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_stop_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Stop,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: Some(5005),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let duplicate_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_stop_order).is_ok());
+        assert_eq!(order_book.add_order(duplicate_order).err().unwrap(), OrderBookError::DuplicateOrderId(OrderId(0)));
+        assert!(order_book.bids[5000].is_empty());
+    }
+
+    #[test]
+    fn test_add_order_correctly_accepts_reused_order_id_once_original_has_filled_or_canceled() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+        assert!(order_book.add_order(buy_order.clone()).is_ok());
+        assert!(order_book.index_mappings.is_empty());
+
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+        assert!(order_book.cancel_order(sell_order.order_id).is_ok());
+        assert!(order_book.index_mappings.is_empty());
+
+        assert!(order_book.add_order(sell_order).is_ok());
+    }
+
+    #[test]
+    fn test_best_bid_and_best_ask_correctly_return_best_prices() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert_eq!(order_book.best_bid(), None);
+        assert_eq!(order_book.best_ask(), None);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5010,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_order).is_ok());
+        assert!(order_book.add_order(sell_order).is_ok());
+
+        assert_eq!(order_book.best_bid(), Some(4990));
+        assert_eq!(order_book.best_ask(), Some(5010));
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_splits_aggressive_order_pro_rata() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            matching_policy: MatchingPolicy::ProRata { min_lot: 1 },
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order_1 = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order_2 = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 700,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(2),
+            price: 5000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order_1.clone()).is_ok());
+        assert!(order_book.add_order(sell_order_2.clone()).is_ok());
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].resting_order_id, sell_order_1.order_id);
+        assert_eq!(fills[0].quantity, 150);
+        assert_eq!(fills[1].resting_order_id, sell_order_2.order_id);
+        assert_eq!(fills[1].quantity, 350);
+
+        assert_eq!(order_book.asks[5000].len(), 2);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][0]].order_id, sell_order_1.order_id);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][0]].quantity, 150);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][1]].quantity, 350);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), 500);
+        assert!(order_book.bids[5000].is_empty());
+    }
+
+    #[test]
+    fn test_pro_rata_allocations_correctly_rounds_to_min_lot_and_allocates_remainder_in_time_priority() {
+        let allocations = OrderBook::pro_rata_allocations(&[100, 100, 100], 100, 10);
+
+        assert_eq!(allocations, vec![40, 30, 30]);
+    }
+
+    #[test]
+    fn test_pro_rata_allocations_correctly_fills_entire_level_when_aggressive_quantity_exceeds_it() {
+        let allocations = OrderBook::pro_rata_allocations(&[300, 700], 1500, 1);
+
+        assert_eq!(allocations, vec![300, 700]);
+    }
+
+    #[test]
+    fn test_pro_rata_allocations_correctly_gives_orders_below_min_lot_only_remainder() {
+        let allocations = OrderBook::pro_rata_allocations(&[50, 950], 100, 10);
+
+        assert_eq!(allocations, vec![10, 90]);
+        assert_eq!(allocations.iter().sum::<u64>(), 100);
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_passes_over_all_or_none_order_for_small_aggressor() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let all_or_none_sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::AllOrNone,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(2),
+            price: 5000,
+            quantity: 200,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(all_or_none_sell_order.clone()).is_ok());
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, sell_order.order_id);
+        assert_eq!(fills[0].quantity, 200);
+
+        assert_eq!(order_book.asks[5000].len(), 2);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][0]].order_id, all_or_none_sell_order.order_id);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][0]].quantity, 500);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][1]].quantity, 100);
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_clears_all_or_none_order_for_large_aggressor() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let all_or_none_sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::AllOrNone,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(2),
+            price: 5000,
+            quantity: 600,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(all_or_none_sell_order.clone()).is_ok());
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].resting_order_id, all_or_none_sell_order.order_id);
+        assert_eq!(fills[0].quantity, 500);
+        assert_eq!(fills[1].resting_order_id, sell_order.order_id);
+        assert_eq!(fills[1].quantity, 100);
+
+        assert_eq!(order_book.asks[5000].len(), 1);
+        assert_eq!(order_book.order_ledger[order_book.asks[5000][0]].quantity, 200);
+    }
+
+    #[test]
+    fn test_add_order_correctly_rests_all_or_none_order_that_cannot_fill_completely() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let all_or_none_buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::AllOrNone,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+
+        let fills = order_book.add_order(all_or_none_buy_order.clone()).unwrap();
+
+        assert!(fills.is_empty());
+        assert_eq!(order_book.asks[5000].len(), 1);
+        assert_eq!(order_book.bids[5000].len(), 1);
+        assert_eq!(order_book.order_ledger[order_book.bids[5000][0]].quantity, 300);
+        assert_eq!(order_book.order_ledger[order_book.bids[5000][0]].order_status, OrderStatus::Active);
+    }
+
+    #[test]
+    fn test_depth_correctly_aggregates_levels_best_price_first_and_skips_emptied_levels() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (OrderSide::Buy, 4990, 100),
+            (OrderSide::Buy, 4995, 200),
+            (OrderSide::Buy, 4995, 300),
+            (OrderSide::Buy, 4998, 50),
+            (OrderSide::Sell, 5005, 400),
+            (OrderSide::Sell, 5010, 100)
+        ];
+
+        for (order_id, (order_side, price, quantity)) in orders.into_iter().enumerate() {
+            let order = Order {
+                order_id: OrderId(order_id as u64),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert!(order_book.cancel_order(OrderId(3)).is_ok());
+
+        let snapshot = order_book.depth(5);
+
+        assert_eq!(snapshot.bids, vec![
+            DepthLevel { price: 4995, quantity: 500, order_count: 2 },
+            DepthLevel { price: 4990, quantity: 100, order_count: 1 }
+        ]);
+        assert_eq!(snapshot.asks, vec![
+            DepthLevel { price: 5005, quantity: 400, order_count: 1 },
+            DepthLevel { price: 5010, quantity: 100, order_count: 1 }
+        ]);
+        assert_eq!(order_book.depth(1).bids.len(), 1);
+        assert_eq!(order_book.depth(0), BookSnapshot::default());
+    }
+
+    #[test]
+    fn test_add_order_correctly_sweeps_opposing_side_up_to_limit_after_best_levels_empty() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (OrderSide::Sell, 5000, 100),
+            (OrderSide::Sell, 5002, 100),
+            (OrderSide::Sell, 5004, 100),
+            (OrderSide::Buy, 4998, 100)
+        ];
+
+        for (order_id, (order_side, price, quantity)) in orders.into_iter().enumerate() {
+            let order = Order {
+                order_id: OrderId(order_id as u64),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        // Empty the best ask by cancel, then the next by a fill, leaving only 5004
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+
+        let buy_order_1 = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5002,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order_2 = Order {
+            order_id: OrderId(5),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5005,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.add_order(buy_order_1).unwrap().len(), 1);
+        assert_eq!(order_book.best_ask(), Some(5004));
+
+        let fills = order_book.add_order(buy_order_2).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 5004);
+        assert_eq!(fills[0].quantity, 100);
+        assert_eq!(order_book.best_bid(), Some(5005));
+        assert_eq!(order_book.best_ask(), None);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5005), 50);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Order book is crossed")]
+    fn test_debug_assert_book_not_crossed_panics_for_crossed_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5001,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order_index = order_book.order_ledger.insert(buy_order);
+        let sell_order_index = order_book.order_ledger.insert(sell_order);
+        order_book.bids[5001].push_back(buy_order_index);
+        order_book.asks[5000].push_back(sell_order_index);
+        order_book.occupied_bid_levels.insert(5001);
+        order_book.occupied_ask_levels.insert(5000);
+
+        order_book.debug_assert_book_not_crossed();
+    }
+
+    #[test]
+    fn test_clear_correctly_empties_book_and_preserves_queue_capacity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (OrderType::Limit, OrderSide::Buy, 4999, 100),
+            (OrderType::Limit, OrderSide::Sell, 5001, 100),
+            (OrderType::Limit, OrderSide::Sell, 5002, 100),
+            (OrderType::Market, OrderSide::Buy, 0, 50)
+        ];
+
+        for (order_id, (order_type, order_side, price, quantity)) in orders.into_iter().enumerate() {
+            let order = Order {
+                order_id: OrderId(order_id as u64),
+                order_type,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        order_book.bench_stats.add_order.push(100);
+
+        assert!(!order_book.trade_history.is_empty());
+
+        order_book.clear();
+
+        assert_eq!(order_book.depth(10), BookSnapshot::default());
+        assert!(order_book.order_ledger.is_empty());
+        assert!(order_book.index_mappings.is_empty());
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.best_bid_index(), None);
+        assert_eq!(order_book.best_ask_index(), None);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 0);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 0);
+        assert!(order_book.bench_stats.add_order.is_empty());
+        assert!(order_book.bids.iter().all(|queue| queue.is_empty() && queue.capacity() >= 100));
+        assert!(order_book.asks.iter().all(|queue| queue.is_empty() && queue.capacity() >= 100));
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(buy_order).is_ok());
+        assert_eq!(order_book.best_bid(), Some(5000));
+    }
+
+    #[test]
+    fn test_try_new_correctly_creates_order_book_for_valid_config() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        assert!(OrderBook::try_new(config).is_ok());
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_min_price_not_below_max_price() {
+        let config = OrderBookConfig {
+            min_price: 10000,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config), Err(OrderBookError::InvalidConfigData(_))));
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_zero_tick_size() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 0,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config), Err(OrderBookError::InvalidConfigData(_))));
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_bad_order_size_limits() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            min_order_qty: 500,
+            max_order_qty: 100,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config.clone()), Err(OrderBookError::InvalidConfigData(_))));
+        assert!(matches!(OrderBook::try_new(OrderBookConfig { max_order_qty: 1000, lot_size: 0, ..config }), Err(OrderBookError::InvalidConfigData(_))));
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_range_not_multiple_of_tick_size() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10001,
+            tick_size: 5,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config), Err(OrderBookError::InvalidConfigData(_))));
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_excessive_capacity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: u32::MAX - 1,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config.clone()), Err(OrderBookError::InvalidConfigData(_))));
+
+        // Few enough levels on their own, but each reserves a huge queue
+        let deep_queue_config = OrderBookConfig { max_price: 10_000, queue_size: 1_000_000, ..config };
+
+        assert!(matches!(OrderBook::try_new(deep_queue_config), Err(OrderBookError::InvalidConfigData(_))));
+    }
+
+    #[test]
+    fn test_try_new_errors_invalid_config_data_for_excessive_price_scale() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 20,
+            ..Default::default()
+        };
+
+        assert!(matches!(OrderBook::try_new(config.clone()), Err(OrderBookError::InvalidConfigData(_))));
+        assert!(OrderBook::try_new(OrderBookConfig { price_scale: 19, ..config }).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "tick_size must be greater than 0")]
+    fn test_new_panics_for_invalid_config() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 0,
+            queue_size: 100,
+            ..Default::default()
+        };
+
+        OrderBook::new(config);
+    }
+
+    #[test]
+    fn test_add_order_correctly_fills_market_order_with_price_above_max_price() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: u32::MAX,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 100);
+        assert!(order_book.asks[5000].is_empty());
+    }
+
+    #[test]
+    fn test_trades_between_correctly_returns_fills_with_inclusive_start_and_exclusive_end() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (i, timestamp) in [100, 200, 200, 300, 400].into_iter().enumerate() {
+            order_book.trade_history.push(OrderFill {
+                aggressive_order_id: OrderId(i as u64),
+                resting_order_id: OrderId(100 + i as u64),
+                aggressor_side: OrderSide::Buy,
+                aggressive_user_id: UserId(0),
+                resting_user_id: UserId(1),
+                price: 5000,
+                quantity: 10,
+                timestamp
+            });
+        }
+
+        let trades = order_book.trades_between(200, 400);
+
+        assert_eq!(trades.len(), 3);
+        assert_eq!(trades[0].aggressive_order_id, OrderId(1));
+        assert_eq!(trades[2].aggressive_order_id, OrderId(3));
+
+        assert_eq!(order_book.trades_between(0, 100).len(), 0);
+        assert_eq!(order_book.trades_between(0, 101).len(), 1);
+        assert_eq!(order_book.trades_between(400, 401).len(), 1);
+        assert_eq!(order_book.trades_between(500, 600).len(), 0);
+        assert_eq!(order_book.trades_between(300, 200).len(), 0);
+    }
+
+    #[test]
+    fn test_last_trade_correctly_returns_most_recent_fill() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert!(order_book.last_trade().is_none());
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 60,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+        assert!(order_book.add_order(buy_order).is_ok());
+
+        let last_trade = order_book.last_trade().unwrap();
+
+        assert_eq!(last_trade.aggressive_order_id, OrderId(1));
+        assert_eq!(last_trade.quantity, 60);
+        assert_eq!(order_book.trades_between(0, u128::MAX).len(), 1);
+    }
+
+    #[test]
+    fn test_ohlcv_correctly_aggregates_fills_into_bars_and_skips_empty_windows() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let fills = [
+            (1_000, 5000, 10),
+            (1_200, 5004, 20),
+            (1_500, 4998, 5),
+            (1_999, 5001, 15),
+            (4_000, 5010, 30),
+            (4_500, 5007, 10)
+        ];
+
+        for (i, (timestamp, price, quantity)) in fills.into_iter().enumerate() {
+            order_book.trade_history.push(OrderFill {
+                aggressive_order_id: OrderId(i as u64),
+                resting_order_id: OrderId(100 + i as u64),
+                aggressor_side: OrderSide::Buy,
+                aggressive_user_id: UserId(0),
+                resting_user_id: UserId(1),
+                price,
+                quantity,
+                timestamp
+            });
+        }
+
+        let bars = order_book.ohlcv(1_000);
+
+        assert_eq!(bars, vec![
+            Bar { start_ts: 1_000, open: 5000, high: 5004, low: 4998, close: 5001, volume: 50 },
+            Bar { start_ts: 4_000, open: 5010, high: 5010, low: 5007, close: 5007, volume: 40 }
+        ]);
+        assert!(order_book.ohlcv(0).is_empty());
+    }
+
+    #[test]
+    fn test_modify_order_correctly_retains_queue_position_on_size_reduction() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..3 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let ledger_index = order_book.index_mappings[&OrderId(0)];
+
+        let reduced_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 40,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let modify_order_result = order_book.modify_order(OrderId(0), reduced_order);
+
+        assert!(modify_order_result.unwrap().is_empty());
+        assert_eq!(order_book.index_mappings[&OrderId(0)], ledger_index);
+        assert_eq!(order_book.bids[5000][0], ledger_index);
+        assert_eq!(order_book.order_ledger[ledger_index].quantity, 40);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 240);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 240);
+
+        let sell_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 40,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(sell_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(0));
+        assert_eq!(fills[0].quantity, 40);
+    }
+
+    #[test]
+    fn test_modify_order_correctly_loses_queue_position_on_size_increase_or_price_change() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..3 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let increased_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.modify_order(OrderId(0), increased_order).is_ok());
+        assert_eq!(order_book.bids[5000].len(), 3);
+        assert_eq!(*order_book.bids[5000].back().unwrap(), order_book.index_mappings[&OrderId(0)]);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 350);
+
+        let repriced_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4999,
+            quantity: 50,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.modify_order(OrderId(1), repriced_order).is_ok());
+        assert_eq!(order_book.bids[5000].len(), 2);
+        assert_eq!(order_book.bids[4999][0], order_book.index_mappings[&OrderId(1)]);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4999), 50);
+    }
+
+    #[test]
+    fn test_level_count_correctly_tracks_populated_levels_across_cancels() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [(0, 4998), (1, 4999), (2, 5000), (3, 5000)];
+
+        for (order_id, price) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.capacity_levels(), 10001);
+        assert_eq!(order_book.level_count(OrderSide::Buy), 3);
+        assert_eq!(order_book.level_count(OrderSide::Sell), 0);
+
+        assert!(order_book.cancel_order(OrderId(2)).is_ok());
+        assert_eq!(order_book.level_count(OrderSide::Buy), 3);
+
+        assert!(order_book.cancel_order(OrderId(3)).is_ok());
+        assert_eq!(order_book.level_count(OrderSide::Buy), 2);
+    }
+
+    #[test]
+    fn test_preview_correctly_predicts_fills_without_mutating_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [(0, 5000, 100), (1, 5000, 50), (2, 5001, 200)];
+
+        for (order_id, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5001,
+            quantity: 250,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let previewed_fills = order_book.preview(&buy_order).unwrap();
+
+        let fill_keys = |fills: &[OrderFill]| fills.iter()
+            .map(|fill| (fill.resting_order_id, fill.price, fill.quantity))
+            .collect::<Vec<_>>();
+
+        assert_eq!(fill_keys(&previewed_fills), vec![(OrderId(0), 5000, 100), (OrderId(1), 5000, 50), (OrderId(2), 5001, 100)]);
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 350);
+        assert_eq!(order_book.best_ask(), Some(5000));
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fill_keys(&fills), fill_keys(&previewed_fills));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 100);
+    }
+
+    #[test]
+    fn test_preview_correctly_respects_order_type_semantics() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            matching_policy: MatchingPolicy::ProRata { min_lot: 1 },
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [(0, 100), (1, 300)];
+
+        for (order_id, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut sell_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.preview(&sell_order).err().unwrap(), OrderBookError::CannotFillCompletely);
+
+        sell_order.order_type = OrderType::Market;
+
+        assert_eq!(order_book.preview(&sell_order).err().unwrap(), OrderBookError::CannotFillCompletely);
+
+        sell_order.time_in_force = TimeInForce::Ioc;
+
+        assert_eq!(order_book.preview(&sell_order).unwrap().iter().map(|fill| fill.quantity).sum::<u64>(), 400);
+
+        sell_order.quantity = 200;
+
+        let previewed_fills = order_book.preview(&sell_order).unwrap();
+        let fills = order_book.add_order(sell_order).unwrap();
+
+        assert_eq!(previewed_fills.len(), 2);
+        assert_eq!((previewed_fills[0].resting_order_id, previewed_fills[0].quantity), (OrderId(0), 50));
+        assert_eq!((previewed_fills[1].resting_order_id, previewed_fills[1].quantity), (OrderId(1), 150));
+        assert_eq!(fills.iter().map(|fill| fill.quantity).collect::<Vec<_>>(), vec![50, 150]);
+    }
+
+    #[test]
+    fn test_stats_correctly_count_mixed_order_flow() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (0, TimeInForce::Gtc, OrderSide::Sell, 5000, 100),
+            (1, TimeInForce::Gtc, OrderSide::Sell, 5001, 100),
+            (2, TimeInForce::Gtc, OrderSide::Buy, 5000, 40),
+            (3, TimeInForce::Gtc, OrderSide::Buy, 5001, 100),
+            (4, TimeInForce::Ioc, OrderSide::Buy, 5001, 100),
+            (5, TimeInForce::Gtc, OrderSide::Buy, 4990, 10)
+        ];
+
+        for (order_id, time_in_force, order_side, price, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert!(order_book.cancel_order(OrderId(5)).is_ok());
+        assert!(order_book.cancel_order(OrderId(99)).is_err());
+
+        let fill_or_kill_order = Order {
+            order_id: OrderId(6),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert!(order_book.add_order(fill_or_kill_order).is_err());
+
+        assert_eq!(*order_book.stats(), OrderBookStats {
+            orders_received: 7,
+            orders_filled: 4,
+            orders_partially_filled: 1,
+            orders_canceled: 1,
+            orders_rejected: 1
+        });
+        assert_eq!(order_book.stats().fill_ratio(), 4.0 / 7.0);
+        assert_eq!(order_book.stats().cancel_ratio(), 1.0 / 7.0);
+    }
+
+    #[test]
+    fn test_add_order_errors_invalid_tick_for_price_off_tick_grid() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 12,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = order_book.add_order(order.clone());
+
+        assert_eq!(add_order_result.err().unwrap(), OrderBookError::InvalidTick(5));
+        assert!(order_book.index_mappings.is_empty());
+
+        order.price = 15;
+
+        let add_order_result = order_book.add_order(order);
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(order_book.best_bid(), Some(15));
+    }
+
+    #[test]
+    fn test_imbalance_correctly_measures_top_of_book_quantity_skew() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert_eq!(order_book.imbalance(5), None);
+
+        let orders = [
+            (0, OrderSide::Buy, 5000, 300),
+            (1, OrderSide::Buy, 4999, 300),
+            (2, OrderSide::Buy, 4990, 1000),
+            (3, OrderSide::Sell, 5001, 100),
+            (4, OrderSide::Sell, 5002, 100)
+        ];
+
+        for (order_id, order_side, price, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        // (600 - 200) / 800 over the top two levels
+        assert!((order_book.imbalance(2).unwrap() - 0.5).abs() < 1e-9);
+        assert!(order_book.imbalance(3).unwrap() > 0.5);
+
+        let sell_order = Order {
+            order_id: OrderId(5),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5001,
+            quantity: 400,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+        assert!(order_book.imbalance(2).unwrap().abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_add_orders_correctly_returns_per_order_results_in_batch_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let batch = [
+            (0, OrderSide::Sell, 5000, 100),
+            (1, OrderSide::Sell, 20000, 100),
+            (0, OrderSide::Sell, 5001, 100),
+            (2, OrderSide::Buy, 5000, 60)
+        ];
+
+        let orders = batch.into_iter()
+            .map(|(order_id, order_side, price, quantity)| Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            })
+            .collect();
+
+        let results = order_book.add_orders(orders);
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].as_ref().unwrap().is_empty());
+        assert_eq!(*results[1].as_ref().err().unwrap(), OrderBookError::PriceOutOfRange);
+        assert_eq!(*results[2].as_ref().err().unwrap(), OrderBookError::DuplicateOrderId(OrderId(0)));
+
+        let fills = results[3].as_ref().unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(0));
+        assert_eq!(fills[0].quantity, 60);
+        assert_eq!(order_book.best_ask(), Some(5000));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 40);
+    }
+
+    #[test]
+    fn test_cancel_all_for_user_correctly_cancels_only_target_user_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (0, 1, OrderType::Limit, OrderSide::Buy, 5000, None),
+            (1, 2, OrderType::Limit, OrderSide::Buy, 5000, None),
+            (2, 1, OrderType::Limit, OrderSide::Sell, 5005, None),
+            (3, 2, OrderType::Limit, OrderSide::Sell, 5006, None),
+            (4, 1, OrderType::Limit, OrderSide::Buy, 4999, None),
+            (5, 1, OrderType::Stop, OrderSide::Sell, 0, Some(4990))
+        ];
+
+        for (order_id, user_id, order_type, order_side, price, stop_price) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(user_id),
+                price,
+                quantity: 100,
+                stop_price,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let canceled_order_ids = order_book.cancel_all_for_user(UserId(1));
+
+        assert_eq!(canceled_order_ids, vec![OrderId(0), OrderId(2), OrderId(4), OrderId(5)]);
+        assert!(order_book.stop_orders.is_empty());
+        assert!(!order_book.user_orders.contains_key(&UserId(1)));
+        assert_eq!(order_book.user_orders[&UserId(2)], HashSet::from([OrderId(1), OrderId(3)]));
+        assert_eq!(order_book.index_mappings.len(), 2);
+        assert_eq!(order_book.best_bid(), Some(5000));
+        assert_eq!(order_book.best_ask(), Some(5006));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 100);
+        assert!(order_book.cancel_all_for_user(UserId(1)).is_empty());
+    }
+
+    #[test]
+    fn test_queue_position_correctly_reports_orders_and_quantity_ahead() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [(0, 150), (1, 200), (2, 250)];
+
+        for (order_id, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price: 5000,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.queue_position(OrderId(0)), Some(QueuePosition { orders_ahead: 0, quantity_ahead: 0 }));
+        assert_eq!(order_book.queue_position(OrderId(1)), Some(QueuePosition { orders_ahead: 1, quantity_ahead: 150 }));
+        assert_eq!(order_book.queue_position(OrderId(2)), Some(QueuePosition { orders_ahead: 2, quantity_ahead: 350 }));
+        assert_eq!(order_book.queue_position(OrderId(3)), None);
+
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+
+        assert_eq!(order_book.queue_position(OrderId(2)), Some(QueuePosition { orders_ahead: 1, quantity_ahead: 200 }));
+    }
+
+    #[test]
+    fn test_iter_orders_correctly_yields_live_orders_with_remaining_quantities() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (0, OrderType::Limit, OrderSide::Buy, 4999, 100),
+            (1, OrderType::Limit, OrderSide::Buy, 5000, 200),
+            (2, OrderType::Limit, OrderSide::Buy, 5000, 300),
+            (3, OrderType::Limit, OrderSide::Sell, 5002, 400),
+            (4, OrderType::Limit, OrderSide::Sell, 5001, 500),
+            (5, OrderType::Market, OrderSide::Sell, 0, 250)
+        ];
+
+        for (order_id, order_type, order_side, price, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+
+        let live_orders: Vec<(OrderId, u64)> = order_book.iter_orders()
+            .map(|order| (order.order_id, order.quantity))
+            .collect();
+
+        assert_eq!(live_orders, vec![(OrderId(2), 250), (OrderId(4), 500), (OrderId(3), 400)]);
+
+        let level_order_ids: Vec<OrderId> = order_book.iter_level(OrderSide::Sell, 5002)
+            .map(|order| order.order_id)
+            .collect();
+
+        assert_eq!(level_order_ids, vec![OrderId(3)]);
+        assert_eq!(order_book.iter_level(OrderSide::Buy, 4999).count(), 0);
+        assert_eq!(order_book.iter_level(OrderSide::Buy, 20000).count(), 0);
+    }
+
+    #[test]
+    fn test_submit_correctly_places_decimal_priced_orders_at_cent_ticks() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let submit_result = order_book.submit(OrderId(0), UserId(0), OrderSide::Sell, OrderType::Limit, Decimal::new(5025, 2), Decimal::from(100));
+
+        assert!(submit_result.unwrap().is_empty());
+        assert_eq!(order_book.best_ask(), Some(5025));
+        assert_eq!(order_book.asks[5025].len(), 1);
+        assert_eq!(order_book.config.scale_price(order_book.best_ask().unwrap()), Decimal::new(5025, 2));
+
+        let fills = order_book.submit(OrderId(1), UserId(1), OrderSide::Buy, OrderType::Limit, Decimal::new(503, 1), Decimal::from(40)).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 5025);
+        assert_eq!(fills[0].quantity, 40);
+
+        let submit_result = order_book.submit(OrderId(2), UserId(0), OrderSide::Buy, OrderType::Limit, Decimal::new(50255, 3), Decimal::from(10));
+
+        assert_eq!(submit_result.err().unwrap(), OrderBookError::InvalidTick(1));
+    }
+
+    #[test]
+    fn test_match_order_against_book_correctly_moves_best_indices_past_emptied_levels() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let orders = [
+            (0, OrderSide::Sell, 5001, 100),
+            (1, OrderSide::Sell, 5003, 100),
+            (2, OrderSide::Buy, 4999, 100)
+        ];
+
+        for (order_id, order_side, price, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut market_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(market_order.clone()).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(0));
+        assert_eq!(fills[0].price, 5001);
+        assert_eq!(order_book.best_ask(), Some(5003));
+        assert_eq!(order_book.best_ask_index(), Some(5003));
+
+        market_order.order_id = OrderId(4);
+        market_order.order_side = OrderSide::Sell;
+
+        let fills = order_book.add_order(market_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(2));
+        assert_eq!(fills[0].aggressor_side, OrderSide::Sell);
+        assert_eq!(fills[0].price, 4999);
+        assert_eq!(order_book.best_bid(), None);
+        assert_eq!(order_book.best_bid_index(), None);
+    }
+
+    #[test]
+    fn test_add_order_errors_cannot_fill_completely_when_liquidity_lies_beyond_fill_or_kill_limit() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let resting_orders = [
+            (0, OrderSide::Sell, 5000, 50),
+            (1, OrderSide::Sell, 5005, 1000),
+            (2, OrderSide::Buy, 4990, 50),
+            (3, OrderSide::Buy, 4985, 1000)
+        ];
+
+        for (order_id, order_side, price, quantity) in resting_orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut fill_or_kill_order = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5004,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert_eq!(order_book.add_order(fill_or_kill_order.clone()).err().unwrap(), OrderBookError::CannotFillCompletely);
+
+        fill_or_kill_order.order_side = OrderSide::Sell;
+        fill_or_kill_order.price = 4986;
+
+        assert_eq!(order_book.add_order(fill_or_kill_order.clone()).err().unwrap(), OrderBookError::CannotFillCompletely);
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 1050);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 1050);
+
+        fill_or_kill_order.price = 4985;
+
+        assert_eq!(order_book.add_order(fill_or_kill_order).unwrap().len(), 2);
+    }
+
+    fn event_without_timestamp(event: &OrderBookEvent) -> OrderBookEvent {
+        let mut event = event.clone();
+
+        match &mut event {
+            OrderBookEvent::OrderAccepted { timestamp, .. }
+            | OrderBookEvent::OrderRested { timestamp, .. }
+            | OrderBookEvent::OrderFilled { timestamp, .. }
+            | OrderBookEvent::OrderPartiallyFilled { timestamp, .. }
+            | OrderBookEvent::OrderModified { timestamp, .. }
+            | OrderBookEvent::OrderCanceled { timestamp, .. }
+            | OrderBookEvent::OrderRejected { timestamp, .. }
+            | OrderBookEvent::OrderExpired { timestamp, .. }
+            | OrderBookEvent::PositionSet { timestamp, .. } => *timestamp = 0
+        }
+
+        event
+    }
+
+    #[test]
+    fn test_events_correctly_record_partial_fill_then_cancel_sequence() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 40,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let mut rejected_order = buy_order.clone();
+        rejected_order.price = 20000;
+
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+        assert!(order_book.add_order(buy_order.clone()).is_ok());
+        assert!(order_book.cancel_order(OrderId(1)).is_ok());
+        assert!(order_book.add_order(rejected_order).is_err());
+
+        let events: Vec<OrderBookEvent> = order_book.events().iter().map(event_without_timestamp).collect();
+
+        assert_eq!(events, vec![
+            OrderBookEvent::OrderAccepted { order: sell_order, timestamp: 0 },
+            OrderBookEvent::OrderRested { order_id: OrderId(0), order_side: OrderSide::Sell, price: 5000, quantity: 40, timestamp: 0 },
+            OrderBookEvent::OrderAccepted { order: buy_order, timestamp: 0 },
+            OrderBookEvent::OrderPartiallyFilled { order_id: OrderId(1), price: 5000, quantity: 40, remaining_quantity: 60, timestamp: 0 },
+            OrderBookEvent::OrderFilled { order_id: OrderId(0), price: 5000, quantity: 40, timestamp: 0 },
+            OrderBookEvent::OrderRested { order_id: OrderId(1), order_side: OrderSide::Buy, price: 5000, quantity: 60, timestamp: 0 },
+            OrderBookEvent::OrderCanceled { order_id: OrderId(1), remaining_quantity: 60, timestamp: 0 },
+            OrderBookEvent::OrderRejected { order_id: OrderId(1), reason: OrderBookError::PriceOutOfRange.to_string(), timestamp: 0 }
+        ]);
+
+        assert_eq!(order_book.drain_events().len(), 8);
+        assert!(order_book.events().is_empty());
+    }
+
+    #[test]
+    fn test_events_correctly_drop_oldest_beyond_capacity_and_stay_empty_when_disabled() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config.clone());
+        let mut unlogged_order_book = OrderBook::new(OrderBookConfig { event_log_capacity: 0, ..config });
+
+        for order_id in 0..3 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 10,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order.clone()).is_ok());
+            assert!(unlogged_order_book.add_order(order).is_ok());
+        }
+
+        let events: Vec<OrderBookEvent> = order_book.events().iter().map(event_without_timestamp).collect();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], OrderBookEvent::OrderRested { order_id: OrderId(1), order_side: OrderSide::Buy, price: 5000, quantity: 10, timestamp: 0 });
+        assert!(matches!(&events[1], OrderBookEvent::OrderAccepted { order, .. } if order.order_id == OrderId(2)));
+        assert!(unlogged_order_book.events().is_empty());
+    }
+
+    #[test]
+    fn test_best_bid_order_correctly_returns_oldest_order_at_best_level() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert!(order_book.best_bid_order().is_none());
+        assert!(order_book.best_ask_order().is_none());
+
+        let orders = [(0, 4999), (1, 5000), (2, 5000)];
+
+        for (order_id, price) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.best_bid_order().unwrap().order_id, OrderId(1));
+        assert!(order_book.best_ask_order().is_none());
+
+        assert!(order_book.cancel_order(OrderId(1)).is_ok());
+
+        assert_eq!(order_book.best_bid_order().unwrap().order_id, OrderId(2));
+
+        // Emptying the best level hands over to the next one down
+        assert!(order_book.cancel_order(OrderId(2)).is_ok());
+
+        assert_eq!(order_book.best_bid_order().unwrap().order_id, OrderId(0));
+    }
+
+    #[test]
+    fn test_add_order_correctly_fills_quantities_beyond_old_i32_and_u32_limits() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let large_quantity = u32::MAX as u64 + 1_000;
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: large_quantity,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: i32::MAX as u64 + 1,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Fok
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), large_quantity);
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, i32::MAX as u64 + 1);
+
+        let remaining_quantity = large_quantity - (i32::MAX as u64 + 1);
+
+        assert_eq!(order_book.order_ledger[order_book.index_mappings[&OrderId(0)]].quantity, remaining_quantity);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), remaining_quantity);
+        assert!(remaining_quantity > i32::MAX as u64);
+    }
+
+    #[test]
+    fn test_add_order_errors_invalid_quantity_for_zero_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 0,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.add_order(order).err().unwrap(), OrderBookError::InvalidQuantity);
+        assert!(order_book.index_mappings.is_empty());
+    }
+
+    #[test]
+    fn test_add_order_correctly_enforces_order_size_range_and_lot_size() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            min_order_qty: 100,
+            max_order_qty: 10_000,
+            lot_size: 50,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let mut order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 50,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.add_order(order.clone()).err().unwrap(), OrderBookError::OrderSizeOutOfRange { min: 100, max: 10_000, got: 50 });
+
+        order.quantity = 10_050;
+
+        assert_eq!(order_book.add_order(order.clone()).err().unwrap(), OrderBookError::OrderSizeOutOfRange { min: 100, max: 10_000, got: 10_050 });
+
+        order.quantity = 125;
+
+        assert_eq!(order_book.add_order(order.clone()).err().unwrap(), OrderBookError::InvalidLotSize(50));
+        assert!(order_book.index_mappings.is_empty());
+
+        order.quantity = 150;
+
+        assert!(order_book.add_order(order).is_ok());
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 150);
+    }
+
+    #[test]
+    fn test_modify_order_errors_and_leaves_original_in_place_for_rejected_replacement() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..3 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let ledger_index = order_book.index_mappings[&OrderId(1)];
+
+        let mut replacement = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 20000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.modify_order(OrderId(1), replacement.clone()).err().unwrap(), OrderBookError::PriceOutOfRange);
+
+        replacement.price = 5000;
+        replacement.time_in_force = TimeInForce::Fok;
+
+        assert_eq!(order_book.modify_order(OrderId(1), replacement.clone()).err().unwrap(), OrderBookError::CannotFillCompletely);
+
+        replacement.order_type = OrderType::AllOrNone;
+
+        assert_eq!(order_book.modify_order(OrderId(1), replacement.clone()).err().unwrap(), OrderBookError::InvalidModification);
+
+        replacement.order_type = OrderType::Limit;
+        replacement.time_in_force = TimeInForce::Gtc;
+        replacement.order_id = OrderId(2);
+
+        assert_eq!(order_book.modify_order(OrderId(1), replacement).err().unwrap(), OrderBookError::DuplicateOrderId(OrderId(2)));
+
+        assert_eq!(order_book.index_mappings[&OrderId(1)], ledger_index);
+        assert_eq!(order_book.bids[5000][1], ledger_index);
+        assert_eq!(order_book.queue_position(OrderId(1)), Some(QueuePosition { orders_ahead: 1, quantity_ahead: 100 }));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 300);
+        assert_eq!(order_book.stats().orders_canceled, 0);
+    }
+
+    #[test]
+    fn test_with_clock_correctly_stamps_fills_and_events_from_injected_clock() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 16,
+            ..Default::default()
+        };
+        let clock = MockClock::new(1_000);
+        let mut order_book = OrderBook::with_clock(config, Box::new(clock.clone()));
+
+        let resting_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(resting_order).is_ok());
+
+        clock.set(5_000);
+
+        let mut aggressive_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 40,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(aggressive_order.clone()).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].timestamp, 5_000);
+
+        clock.advance(250);
+
+        aggressive_order.order_id = OrderId(2);
+        let fills = order_book.add_order(aggressive_order).unwrap();
+        assert_eq!(fills[0].timestamp, 5_250);
+
+        assert_eq!(order_book.trades_between(5_000, 5_100).len(), 1);
+        assert_eq!(order_book.events().front(), Some(&OrderBookEvent::OrderAccepted {
+            order: Order {
+                order_id: OrderId(0),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            },
+            timestamp: 1_000
+        }));
+        assert!(order_book.events().iter().skip(1).all(|event| match event {
+            OrderBookEvent::OrderRested { timestamp, .. } => *timestamp == 1_000,
+            OrderBookEvent::OrderAccepted { timestamp, .. }
+            | OrderBookEvent::OrderFilled { timestamp, .. }
+            | OrderBookEvent::OrderPartiallyFilled { timestamp, .. } => *timestamp == 5_000 || *timestamp == 5_250,
+            _ => false
+        }));
+    }
+
+    #[test]
+    fn test_add_order_errors_no_liquidity_for_market_order_on_empty_side() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(bid_order).is_ok());
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.preview(&buy_order).err().unwrap(), OrderBookError::NoLiquidity);
+        assert_eq!(order_book.add_order(buy_order).err().unwrap(), OrderBookError::NoLiquidity);
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 100);
+    }
+
+    #[test]
+    fn test_add_order_returns_partial_fills_for_market_order_that_runs_out_of_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5000), (1, 5002)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 150,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 420,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let previewed_fills = order_book.preview(&buy_order).unwrap();
+        let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+        assert_eq!(previewed_fills.iter().map(|fill| fill.quantity).collect::<Vec<_>>(), vec![150, 150]);
+        assert_eq!(fills.iter().map(|fill| fill.quantity).collect::<Vec<_>>(), vec![150, 150]);
+        assert_eq!(order_book.unfilled_quantity(&buy_order, &fills), Some(120));
+        assert_eq!(order_book.trade_history.len(), 2);
+        assert_eq!(order_book.stats().orders_rejected, 0);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 0);
+    }
+
+    #[test]
+    fn test_validate_correctly_accepts_consistent_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, order_side, price) in [(0, OrderSide::Buy, 4999), (1, OrderSide::Sell, 5001), (2, OrderSide::Sell, 5001)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_errors_corrupt_book_for_dangling_and_misplaced_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(order).is_ok());
+
+        let ledger_index = order_book.index_mappings[&OrderId(0)];
+
+        // Mapping pointing at a slot nothing lives in
+        order_book.index_mappings.insert(OrderId(0), ledger_index + 1);
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(_))));
+        order_book.index_mappings.insert(OrderId(0), ledger_index);
+
+        // Order queued on a level that doesn't match its price
+        order_book.bids[5000].clear();
+        order_book.bids[4000].push_back(ledger_index);
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(_))));
+        order_book.bids[4000].clear();
+
+        // Queue entry for a slot that isn't in the ledger
+        order_book.bids[5000].push_back(ledger_index);
+        order_book.bids[5000].push_back(ledger_index + 1);
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(_))));
+        order_book.bids[5000].pop_back();
+
+        // Ledger entry that no queue references
+        order_book.bids[5000].clear();
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(_))));
+        order_book.bids[5000].push_back(ledger_index);
+
+        assert_eq!(order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_errors_corrupt_book_for_crossed_levels() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let ask_order = Order {
+            order_id: OrderId(1),
+            order_side: OrderSide::Sell,
+            price: 4990,
+            ..bid_order.clone()
+        };
+
+        // Pushed straight into the levels, bypassing matching
+        for order in [bid_order, ask_order] {
+            let order_id = order.order_id;
+            let price = order.price as usize;
+            let side = order.order_side.clone();
+            let ledger_index = order_book.order_ledger.insert(order);
+
+            order_book.index_mappings.insert(order_id, ledger_index);
+
+            match side {
+                OrderSide::Buy => order_book.bids[price].push_back(ledger_index),
+                OrderSide::Sell => order_book.asks[price].push_back(ledger_index)
+            }
+        }
+
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(reason)) if reason.contains("crossed")));
+    }
+
+    #[test]
+    fn test_fills_for_order_correctly_returns_fills_for_aggressive_and_resting_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5000), (1, 5001)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5001,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 2);
+
+        let aggressive_fills = order_book.fills_for_order(OrderId(2));
+
+        assert_eq!(aggressive_fills.len(), 2);
+        assert_eq!(aggressive_fills[0].resting_order_id, OrderId(0));
+        assert_eq!(aggressive_fills[0].quantity, 100);
+        assert_eq!(aggressive_fills[1].resting_order_id, OrderId(1));
+        assert_eq!(aggressive_fills[1].quantity, 50);
+
+        let resting_fills = order_book.fills_for_order(OrderId(0));
+
+        assert_eq!(resting_fills.len(), 1);
+        assert_eq!(resting_fills[0].aggressive_order_id, fills[0].aggressive_order_id);
+        assert_eq!(resting_fills[0].quantity, fills[0].quantity);
+
+        let resting_fills = order_book.fills_for_order(OrderId(1));
+
+        assert_eq!(resting_fills.len(), 1);
+        assert_eq!(resting_fills[0].aggressive_order_id, fills[1].aggressive_order_id);
+        assert_eq!(resting_fills[0].quantity, fills[1].quantity);
+        assert!(order_book.fills_for_order(OrderId(3)).is_empty());
+    }
+
+    #[test]
+    fn test_add_order_correctly_fills_reduce_only_order_and_drops_remainder() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 60,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(bid_order).is_ok());
+
+        order_book.set_position(UserId(1), 100);
+
+        let sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: true,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.preview(&sell_order).unwrap().len(), 1);
+
+        let fills = order_book.add_order(sell_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, 60);
+        assert!(!order_book.index_mappings.contains_key(&OrderId(1)));
+        assert!(order_book.asks[5000].is_empty());
+        assert_eq!(order_book.best_ask(), None);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 0);
+    }
+
+    #[test]
+    fn test_add_order_errors_reduce_only_violation() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(bid_order).is_ok());
+
+        let mut sell_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: true,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        // Flat, so nothing to reduce
+        assert_eq!(order_book.add_order(sell_order.clone()).err().unwrap(), OrderBookError::ReduceOnlyViolation { position: 0, got: 100 });
+
+        // Selling into a short would grow it
+        order_book.set_position(UserId(1), -100);
+        assert_eq!(order_book.add_order(sell_order.clone()).err().unwrap(), OrderBookError::ReduceOnlyViolation { position: -100, got: 100 });
+
+        // Larger than the long, so it would flip to short
+        order_book.set_position(UserId(1), 50);
+        assert_eq!(order_book.add_order(sell_order.clone()).err().unwrap(), OrderBookError::ReduceOnlyViolation { position: 50, got: 100 });
+
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 500);
+
+        sell_order.quantity = 50;
+
+        assert_eq!(order_book.add_order(sell_order).unwrap()[0].quantity, 50);
+    }
+
+    #[test]
+    fn test_spread_correctly_returns_spread_for_populated_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert_eq!(order_book.spread(), None);
+        assert_eq!(order_book.spread_decimal(), None);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(bid_order).is_ok());
+
+        assert_eq!(order_book.spread(), None);
+
+        let ask_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5005,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(ask_order).is_ok());
+
+        assert_eq!(order_book.spread(), Some(15));
+        assert_eq!(order_book.spread_decimal(), Some(Decimal::new(15, 2)));
+
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+
+        assert_eq!(order_book.spread(), None);
+    }
+
+    #[test]
+    fn test_fillable_quantity_for_notional_correctly_walks_levels_and_leaves_leftover_cash() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, order_side, price, quantity) in [(0, OrderSide::Sell, 5000, 100), (1, OrderSide::Sell, 5000, 50), (2, OrderSide::Sell, 5100, 100), (3, OrderSide::Buy, 4900, 20)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        // $7,500 clears the 150 at $50.00, then $30 left can't buy a whole unit at $51.00
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Buy, Decimal::new(7530, 0)), (150, Decimal::new(7500, 0)));
+
+        // $10,000 clears the first level and buys 49 at $51.00, leaving $1
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Buy, Decimal::new(10000, 0)), (199, Decimal::new(9999, 0)));
+
+        // More cash than the side holds stops at the available liquidity
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Buy, Decimal::new(1_000_000, 0)), (250, Decimal::new(12600, 0)));
+
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Sell, Decimal::new(500, 0)), (10, Decimal::new(490, 0)));
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Sell, Decimal::ZERO), (0, Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_add_order_correctly_fills_market_notional_order_that_exactly_clears_a_level() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5000), (1, 5100)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::MarketNotional(Decimal::new(5000, 0)),
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 0,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let previewed_fills = order_book.preview(&buy_order).unwrap();
+        let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+        assert_eq!(previewed_fills.len(), 1);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 100);
+        assert_eq!(order_book.unfilled_notional(&buy_order, &fills), Some(Decimal::ZERO));
+        assert_eq!(order_book.best_ask(), Some(5100));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 100);
+    }
+
+    #[test]
+    fn test_add_order_correctly_fills_market_notional_order_and_returns_remainder_below_a_lot() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            lot_size: 10,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5000), (1, 5100)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        // $6,000 buys the 100 at $50.00 plus 19 at $51.00, which rounds down to a whole lot of 10
+        let buy_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::MarketNotional(Decimal::new(6000, 0)),
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 0,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].price, fills[0].quantity), (5000, 100));
+        assert_eq!((fills[1].price, fills[1].quantity), (5100, 10));
+        assert_eq!(order_book.unfilled_notional(&buy_order, &fills), Some(Decimal::new(490, 0)));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 90);
+
+        let mut sell_order = buy_order.clone();
+        sell_order.order_id = OrderId(3);
+        sell_order.order_side = OrderSide::Sell;
+
+        assert_eq!(order_book.add_order(sell_order).err().unwrap(), OrderBookError::NoLiquidity);
+
+        let mut empty_order = buy_order;
+        empty_order.order_id = OrderId(4);
+        empty_order.order_type = OrderType::MarketNotional(Decimal::ZERO);
+
+        assert_eq!(order_book.add_order(empty_order).err().unwrap(), OrderBookError::InvalidQuantity);
+    }
+
+    #[test]
+    fn test_modify_order_correctly_retains_filled_quantity_of_partially_filled_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order.clone()).is_ok());
+        assert_eq!(order_book.add_order(buy_order).unwrap().len(), 1);
+
+        let resting_order = &order_book.order_ledger[order_book.index_mappings[&OrderId(0)]];
+
+        assert_eq!(resting_order.quantity, 200);
+        assert_eq!(resting_order.filled_quantity, 100);
+        assert_eq!(resting_order.order_status, OrderStatus::PartiallyFilled);
+
+        // Amending the remainder down keeps the order in place
+        let mut amended_order = Order { quantity: 150, ..sell_order.clone() };
+
+        assert!(order_book.modify_order(OrderId(0), amended_order.clone()).is_ok());
+
+        let resting_order = &order_book.order_ledger[order_book.index_mappings[&OrderId(0)]];
+
+        assert_eq!(resting_order.quantity, 150);
+        assert_eq!(resting_order.filled_quantity, 100);
+        assert_eq!(resting_order.order_status, OrderStatus::PartiallyFilled);
+
+        // Repricing re-queues the order but carries its fills across
+        amended_order.price = 5010;
+
+        assert!(order_book.modify_order(OrderId(0), amended_order).is_ok());
+
+        let resting_order = &order_book.order_ledger[order_book.index_mappings[&OrderId(0)]];
+
+        assert_eq!(resting_order.price, 5010);
+        assert_eq!(resting_order.quantity, 150);
+        assert_eq!(resting_order.filled_quantity, 100);
+        assert_eq!(resting_order.order_status, OrderStatus::PartiallyFilled);
+    }
+
+    #[test]
+    fn test_add_order_correctly_hides_hidden_order_from_displayed_book_and_fills_it_after_visible_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        // The hidden order arrives first at the top price, and the visible one at that price arrives after it
+        for (order_id, price, hidden) in [(0, 5000, true), (1, 5001, false), (2, 5000, false)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.best_ask(), Some(5000));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), 100);
+        assert_eq!(order_book.depth(1).asks, vec![DepthLevel { price: 5000, quantity: 100, order_count: 1 }]);
+
+        let mut buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(2));
+
+        // Only the hidden order is left at 5000, so the displayed book starts at 5001
+        assert_eq!(order_book.best_ask(), Some(5001));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), 0);
+        assert_eq!(order_book.depth(5).asks, vec![DepthLevel { price: 5001, quantity: 100, order_count: 1 }]);
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 200);
+
+        buy_order.order_id = OrderId(4);
+        buy_order.quantity = 60;
+
+        let fills = order_book.add_order(buy_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(0));
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 60);
+        assert_eq!(order_book.best_ask(), Some(5001));
+    }
+
+    #[test]
+    fn test_add_order_correctly_gives_visible_orders_pro_rata_priority_over_hidden_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            matching_policy: MatchingPolicy::ProRata { min_lot: 1 },
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, quantity, hidden) in [(0, 300, true), (1, 100, false), (2, 100, false)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let sell_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 250,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let previewed_fills = order_book.preview(&sell_order).unwrap();
+        let fills = order_book.add_order(sell_order).unwrap();
+
+        let fill_quantities: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect();
+        let previewed_quantities: Vec<(OrderId, u64)> = previewed_fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect();
+
+        assert_eq!(fill_quantities, vec![(OrderId(1), 100), (OrderId(2), 100), (OrderId(0), 50)]);
+        assert_eq!(previewed_quantities, vec![(OrderId(1), 100), (OrderId(2), 100), (OrderId(0), 50)]);
+        assert_eq!(order_book.best_bid(), None);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 250);
+    }
+
+    #[test]
+    fn test_submit_auto_id_correctly_assigns_strictly_increasing_unique_ids() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        // A client-supplied id that the generator will reach while it is still live
+        let client_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(client_order).is_ok());
+
+        let mut assigned_ids = vec![];
+
+        for i in 0..6 {
+            let order = Order {
+                order_id: OrderId(0),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(1),
+                price: 5000 + i,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            let (order_id, result) = order_book.submit_auto_id(order);
+
+            assert!(result.is_ok());
+            assert!(order_book.index_mappings.contains_key(&order_id));
+            assigned_ids.push(order_id);
+
+            // Cancelling frees the id in the book, but it must not be handed out again
+            if i % 2 == 0 {
+                assert!(order_book.cancel_order(order_id).is_ok());
+            }
+        }
+
+        assert_eq!(assigned_ids, vec![OrderId(0), OrderId(1), OrderId(2), OrderId(4), OrderId(5), OrderId(6)]);
+        assert!(assigned_ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(order_book.next_order_id, 7);
+    }
+
+    #[test]
+    fn test_drain_level_deltas_correctly_reports_levels_changed_by_add_fill_and_cancel() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            track_level_deltas: true,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5000), (1, 5000), (2, 5001)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.drain_level_deltas(), vec![
+            LevelDelta { side: OrderSide::Sell, price: 5000, new_total_quantity: 200, new_order_count: 2 },
+            LevelDelta { side: OrderSide::Sell, price: 5001, new_total_quantity: 100, new_order_count: 1 }
+        ]);
+        assert!(order_book.drain_level_deltas().is_empty());
+
+        let buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 140,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(order_book.add_order(buy_order).unwrap().len(), 2);
+        assert_eq!(order_book.drain_level_deltas(), vec![
+            LevelDelta { side: OrderSide::Sell, price: 5000, new_total_quantity: 60, new_order_count: 1 }
+        ]);
+
+        assert!(order_book.cancel_order(OrderId(2)).is_ok());
+        assert_eq!(order_book.drain_level_deltas(), vec![
+            LevelDelta { side: OrderSide::Sell, price: 5001, new_total_quantity: 0, new_order_count: 0 }
+        ]);
+    }
+
+    #[test]
+    fn test_is_crossed_and_is_locked_correctly_return_false_for_normal_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert!(!order_book.is_crossed());
+        assert!(!order_book.is_locked());
+
+        // Each aggressive order trades through the touch, which must never leave the book locked or crossed
+        for (order_id, order_side, price) in [(0, OrderSide::Buy, 4999), (1, OrderSide::Sell, 5001), (2, OrderSide::Buy, 5001), (3, OrderSide::Sell, 4999), (4, OrderSide::Sell, 5000), (5, OrderSide::Buy, 5000)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+            assert!(!order_book.is_crossed());
+            assert!(!order_book.is_locked());
+        }
+    }
+
+    #[test]
+    fn test_is_crossed_and_is_locked_correctly_detect_forced_states() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let bid_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let bid_index = order_book.order_ledger.insert(bid_order);
+        order_book.bids[5000].push_back(bid_index);
+        order_book.occupied_bid_levels.insert(5000);
+
+        let ask_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::Active,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let ask_index = order_book.order_ledger.insert(ask_order);
+        order_book.asks[5000].push_back(ask_index);
+        order_book.occupied_ask_levels.insert(5000);
+
+        assert!(order_book.is_locked());
+        assert!(!order_book.is_crossed());
+
+        order_book.order_ledger[ask_index].price = 4990;
+        order_book.asks[5000].clear();
+        order_book.asks[4990].push_back(ask_index);
+        order_book.occupied_ask_levels.remove(&5000);
+        order_book.occupied_ask_levels.insert(4990);
+
+        assert!(order_book.is_crossed());
+        assert!(!order_book.is_locked());
+    }
+
+    #[test]
+    fn test_cumulative_quantity_correctly_sums_levels_reachable_from_limit_price() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, order_side, price, quantity) in [(0, OrderSide::Sell, 5000, 100), (1, OrderSide::Sell, 5001, 150), (2, OrderSide::Sell, 5001, 50), (3, OrderSide::Sell, 5003, 400), (4, OrderSide::Buy, 4998, 70), (5, OrderSide::Buy, 4995, 30)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Sell, 5002), 300);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Sell, 4999), 0);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Sell, 20000), 700);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Buy, 4998), 70);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Buy, 0), 100);
+    }
+
+    #[test]
+    fn test_fill_order_correctly_keeps_partially_filled_resting_order_at_front_of_level() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..3 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 30,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        // Two partial fills in a row both land on the front order
+        for (order_id, remaining_quantity) in [(3, 70), (4, 40)] {
+            buy_order.order_id = OrderId(order_id);
+
+            let fills = order_book.add_order(buy_order.clone()).unwrap();
+
+            assert_eq!(fills.len(), 1);
+            assert_eq!(fills[0].resting_order_id, OrderId(0));
+            assert_eq!(order_book.best_ask_order().unwrap().order_id, OrderId(0));
+            assert_eq!(order_book.best_ask_order().unwrap().quantity, remaining_quantity);
+        }
+
+        let level_order_ids: Vec<OrderId> = order_book.iter_level(OrderSide::Sell, 5000).map(|order| order.order_id).collect();
+
+        assert_eq!(level_order_ids, vec![OrderId(0), OrderId(1), OrderId(2)]);
+
+        // An aggressor larger than the reduced front order finishes it, then moves on in time priority
+        buy_order.order_id = OrderId(5);
+        buy_order.quantity = 90;
+
+        let fills = order_book.add_order(buy_order).unwrap();
+        let fill_keys: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect();
+
+        assert_eq!(fill_keys, vec![(OrderId(0), 40), (OrderId(1), 50)]);
+
+        let level_order_ids: Vec<OrderId> = order_book.iter_level(OrderSide::Sell, 5000).map(|order| order.order_id).collect();
+
+        assert_eq!(level_order_ids, vec![OrderId(1), OrderId(2)]);
+        assert_eq!(order_book.best_ask_order().unwrap().quantity, 50);
+    }
+
+    #[test]
+    fn test_cancel_front_correctly_cancels_oldest_orders_and_keeps_remaining_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..5 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100 + order_id,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.cancel_front(OrderSide::Buy, 5000, 2), vec![OrderId(0), OrderId(1)]);
+
+        let level_order_ids: Vec<OrderId> = order_book.iter_level(OrderSide::Buy, 5000).map(|order| order.order_id).collect();
+
+        assert_eq!(level_order_ids, vec![OrderId(2), OrderId(3), OrderId(4)]);
+        assert!(!order_book.index_mappings.contains_key(&OrderId(0)));
+        assert!(!order_book.index_mappings.contains_key(&OrderId(1)));
+        assert_eq!(order_book.order_ledger.len(), 3);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 309);
+        assert_eq!(order_book.stats().orders_canceled, 2);
+
+        // Emptying the level moves the best bid off it
+        assert_eq!(order_book.cancel_front(OrderSide::Buy, 5000, 10), vec![OrderId(2), OrderId(3), OrderId(4)]);
+        assert_eq!(order_book.best_bid(), None);
+        assert!(order_book.cancel_front(OrderSide::Buy, 20000, 1).is_empty());
+    }
+
+    #[test]
+    fn test_modify_order_correctly_amends_price_and_quantity_on_same_side() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(order.clone()).is_ok());
+
+        let replacement = Order {
+            price: 4990,
+            quantity: 250,
+            ..order
+        };
+
+        assert!(order_book.modify_order(OrderId(1), replacement).is_ok());
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 0);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 250);
+        assert_eq!(order_book.best_bid(), Some(4990));
+    }
+
+    #[test]
+    fn test_modify_order_errors_invalid_modification_for_side_flip_and_keeps_original() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(order.clone()).is_ok());
+
+        let ledger_index = order_book.index_mappings[&OrderId(1)];
+
+        let replacement = Order {
+            order_side: OrderSide::Sell,
+            price: 5010,
+            ..order
+        };
+
+        assert_eq!(order_book.modify_order(OrderId(1), replacement).err().unwrap(), OrderBookError::InvalidModification);
+        assert_eq!(order_book.index_mappings[&OrderId(1)], ledger_index);
+        assert_eq!(order_book.order_ledger[ledger_index].order_side, OrderSide::Buy);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 100);
+        assert_eq!(order_book.best_ask(), None);
+    }
+
+    #[test]
+    fn test_add_order_errors_level_order_limit_reached_once_level_is_full() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            max_orders_per_level: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..4 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 10,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            let result = order_book.add_order(order);
+
+            if order_id < 3 {
+                assert!(result.is_ok());
+            }
+            else {
+                assert_eq!(result.err().unwrap(), OrderBookError::LevelOrderLimitReached(3));
+            }
+        }
+
+        assert_eq!(order_book.bids[5000].len(), 3);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 30);
+        assert!(!order_book.index_mappings.contains_key(&OrderId(3)));
+
+        let other_bid = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4999,
+            quantity: 10,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+        let other_ask = Order {
+            order_id: OrderId(5),
+            order_side: OrderSide::Sell,
+            price: 5010,
+            ..other_bid.clone()
+        };
+
+        assert!(order_book.add_order(other_bid).is_ok());
+        assert!(order_book.add_order(other_ask).is_ok());
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4999), 10);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5010), 10);
+    }
+
+    #[test]
+    fn test_add_order_correctly_crosses_midpoint_orders_at_the_mid() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, order_side, price) in [(0, OrderSide::Buy, 4990), (1, OrderSide::Sell, 5010)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.mid_price(), Some(5000));
+
+        let midpoint_sell = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::MidpointPeg,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 50,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+        let midpoint_buy = Order {
+            order_id: OrderId(3),
+            order_side: OrderSide::Buy,
+            user_id: UserId(2),
+            quantity: 80,
+            ..midpoint_sell.clone()
+        };
+
+        assert!(order_book.add_order(midpoint_sell).unwrap().is_empty());
+
+        let fills = order_book.add_order(midpoint_buy).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(2));
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 50);
+
+        // The midpoint remainder rests in the dark, leaving the lit book untouched
+        assert_eq!(order_book.midpoint_orders.len(), 1);
+        assert_eq!(order_book.midpoint_orders[0].order_id, OrderId(3));
+        assert_eq!(order_book.midpoint_orders[0].quantity, 30);
+        assert_eq!(order_book.midpoint_orders[0].order_status, OrderStatus::PartiallyFilled);
+        assert_eq!(order_book.best_bid(), Some(4990));
+        assert_eq!(order_book.best_ask(), Some(5010));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 100);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5010), 100);
+    }
+
+    #[test]
+    fn test_add_order_correctly_rests_one_sided_midpoint_order_until_contra_arrives() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let midpoint_buy = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::MidpointPeg,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 40,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+        let midpoint_sell = Order {
+            order_id: OrderId(1),
+            order_side: OrderSide::Sell,
+            user_id: UserId(2),
+            ..midpoint_buy.clone()
+        };
+
+        // With no BBO there is no mid, so both sides rest
+        assert!(order_book.add_order(midpoint_buy).unwrap().is_empty());
+        assert!(order_book.add_order(midpoint_sell).unwrap().is_empty());
+        assert_eq!(order_book.midpoint_orders.len(), 2);
+        assert_eq!(order_book.midpoint_orders[0].order_status, OrderStatus::Active);
+        assert_eq!(order_book.best_bid(), None);
+
+        for (order_id, order_side, price) in [(2, OrderSide::Buy, 4990), (3, OrderSide::Sell, 5020)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).unwrap().is_empty());
+        }
+
+        // Once the BBO forms a mid, the resting midpoint orders cross there
+        assert!(order_book.midpoint_orders.is_empty());
+        assert_eq!(order_book.trade_history.len(), 1);
+        assert_eq!(order_book.trade_history[0].aggressive_order_id, OrderId(0));
+        assert_eq!(order_book.trade_history[0].resting_order_id, OrderId(1));
+        assert_eq!(order_book.trade_history[0].price, 5005);
+        assert_eq!(order_book.trade_history[0].quantity, 40);
+        assert_eq!(order_book.stats().orders_filled, 2);
+    }
+
+    #[test]
+    fn test_add_order_errors_book_full_until_an_order_is_canceled() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            max_resting_orders: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 10,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        for order_id in 0..3 {
+            assert!(order_book.add_order(Order { order_id: OrderId(order_id), price: 4998 + order_id as u32, ..order.clone() }).is_ok());
+        }
+
+        assert_eq!(order_book.resting_order_count(), 3);
+        assert_eq!(order_book.add_order(Order { order_id: OrderId(3), ..order.clone() }).err().unwrap(), OrderBookError::BookFull(3));
+        assert_eq!(order_book.resting_order_count(), 3);
+
+        // A marketable order still trades against the full book
+        let sell = Order {
+            order_id: OrderId(4),
+            order_side: OrderSide::Sell,
+            price: 5000,
+            quantity: 5,
+            time_in_force: TimeInForce::Ioc,
+            ..order.clone()
+        };
+
+        assert_eq!(order_book.add_order(sell).unwrap().len(), 1);
+
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+        assert_eq!(order_book.resting_order_count(), 2);
+        assert!(order_book.add_order(Order { order_id: OrderId(3), ..order }).is_ok());
+        assert_eq!(order_book.resting_order_count(), 3);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 15);
+    }
+
+    #[test]
+    fn test_checksum_correctly_matches_for_same_book_built_differently() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut first_book = OrderBook::new(config.clone());
+        let mut second_book = OrderBook::new(config.clone());
+        let mut different_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+        let levels = [(OrderSide::Buy, 4990, 100), (OrderSide::Buy, 4980, 50), (OrderSide::Sell, 5010, 70), (OrderSide::Sell, 5020, 30)];
+
+        for (order_id, (order_side, price, quantity)) in levels.iter().cloned().enumerate() {
+            assert!(first_book.add_order(Order { order_id: OrderId(order_id as u64), order_side, price, quantity, ..order.clone() }).is_ok());
+        }
+
+        // Same levels in reverse order, with the best ask reached by trading down a larger order
+        for (order_id, (order_side, price, quantity)) in levels.iter().cloned().rev().enumerate() {
+            let quantity = if price == 5010 { quantity + 20 } else { quantity };
+            assert!(second_book.add_order(Order { order_id: OrderId(order_id as u64), order_side, price, quantity, ..order.clone() }).is_ok());
+        }
+
+        let buy = Order { order_id: OrderId(10), time_in_force: TimeInForce::Ioc, price: 5010, quantity: 20, ..order.clone() };
+        assert_eq!(second_book.add_order(buy).unwrap().len(), 1);
+
+        for (order_id, (order_side, price, quantity)) in levels.iter().cloned().enumerate() {
+            let quantity = if price == 4980 { quantity + 1 } else { quantity };
+            assert!(different_book.add_order(Order { order_id: OrderId(order_id as u64), order_side, price, quantity, ..order.clone() }).is_ok());
+        }
+
+        assert_eq!(first_book.checksum(), second_book.checksum());
+        assert_ne!(first_book.checksum(), different_book.checksum());
+        assert_ne!(first_book.checksum(), OrderBook::new(OrderBookConfig::default()).checksum());
+    }
+
+    #[test]
+    fn test_cancel_range_correctly_cancels_middle_levels_and_updates_best_prices() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        // Two orders per level on five bid levels, 4996..=5000
+        for order_id in 0..10 {
+            let price = 4996 + order_id as u32 / 2;
+            assert!(order_book.add_order(Order { order_id: OrderId(order_id), price, user_id: UserId(order_id as u32 % 2), ..order.clone() }).is_ok());
+        }
+
+        assert_eq!(order_book.cancel_range(OrderSide::Buy, 4997, 4999), (2..8).map(OrderId).collect::<Vec<_>>());
+        assert_eq!(order_book.best_bid(), Some(5000));
+        assert_eq!(order_book.level_count(OrderSide::Buy), 2);
+        assert_eq!(order_book.total_bid_quantity, 400);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4996), 200);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4998), 0);
+        assert_eq!(order_book.resting_order_count(), 4);
+        assert_eq!(order_book.stats().orders_canceled, 6);
+        assert!(!order_book.index_mappings.contains_key(&OrderId(5)));
+        assert_eq!(order_book.user_orders[&UserId(0)].len(), 2);
+
+        // Taking out the best level moves the best bid down past the emptied range
+        assert_eq!(order_book.cancel_range(OrderSide::Buy, 4998, 5000), vec![OrderId(8), OrderId(9)]);
+        assert_eq!(order_book.best_bid(), Some(4996));
+        assert!(order_book.cancel_range(OrderSide::Sell, 0, 10000).is_empty());
+        assert_eq!(order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_match_level_correctly_leaves_partially_filled_order_in_place_at_the_front() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, quantity) in [(0, 100), (1, 200), (2, 50)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price: 5000,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let ledger_index = order_book.index_mappings[&OrderId(1)];
+
+        let buy = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc
+        };
+
+        let fills = order_book.add_order(buy.clone()).unwrap();
+
+        assert_eq!(fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect::<Vec<_>>(), vec![(OrderId(0), 100), (OrderId(1), 50)]);
+        assert_eq!(order_book.asks[5000].len(), 2);
+        assert_eq!(order_book.asks[5000][0], ledger_index);
+        assert_eq!(order_book.order_ledger[ledger_index].quantity, 150);
+        assert_eq!(order_book.order_ledger[ledger_index].order_status, OrderStatus::PartiallyFilled);
+
+        let fills = order_book.add_order(Order { order_id: OrderId(4), quantity: 160, ..buy }).unwrap();
+
+        assert_eq!(fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect::<Vec<_>>(), vec![(OrderId(1), 150), (OrderId(2), 10)]);
+        assert_eq!(order_book.asks[5000].len(), 1);
+        assert_eq!(order_book.best_ask_order().unwrap().order_id, OrderId(2));
+        assert_eq!(order_book.best_ask_order().unwrap().quantity, 40);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), 40);
+    }
+
+    #[test]
+    fn test_bbo_history_correctly_records_only_bbo_changes() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            bbo_history_capacity: 10,
+            ..Default::default()
+        };
+        let clock = MockClock::new(1_000);
+        let mut order_book = OrderBook::with_clock(config, Box::new(clock.clone()));
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(order.clone()).is_ok());
+
+        clock.advance(10);
+
+        // Joining or backing the best bid leaves the BBO where it was
+        assert!(order_book.add_order(Order { order_id: OrderId(1), ..order.clone() }).is_ok());
+        assert!(order_book.add_order(Order { order_id: OrderId(2), price: 4980, ..order.clone() }).is_ok());
+        assert!(order_book.cancel_order(OrderId(2)).is_ok());
+
+        assert!(order_book.add_order(Order { order_id: OrderId(3), order_side: OrderSide::Sell, price: 5010, ..order.clone() }).is_ok());
+
+        assert_eq!(order_book.bbo_history(), &[
+            BboTick { timestamp: 1_000, best_bid: Some(4990), best_ask: None },
+            BboTick { timestamp: 1_010, best_bid: Some(4990), best_ask: Some(5010) }
+        ]);
+
+        let mut unrecorded_order_book = OrderBook::new(OrderBookConfig { bbo_history_capacity: 0, ..order_book.config.clone() });
+        assert!(unrecorded_order_book.add_order(order).is_ok());
+        assert!(unrecorded_order_book.bbo_history().is_empty());
+    }
+
+    #[test]
+    fn test_bbo_history_correctly_keeps_most_recent_ticks_up_to_capacity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            bbo_history_capacity: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for order_id in 0..8 {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 4990 + order_id as u32,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let best_bids: Vec<Option<u32>> = order_book.bbo_history().iter().map(|tick| tick.best_bid).collect();
+        assert_eq!(best_bids, vec![Some(4995), Some(4996), Some(4997)]);
+    }
+
+    #[test]
+    fn test_add_order_errors_price_band_violation_far_from_mid() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            max_price_deviation_bps: 1000,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 2500,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        // No reference price on an empty book, so anything in range is accepted
+        assert!(order_book.add_order(Order { price: 4990, ..order.clone() }).is_ok());
+        assert!(order_book.add_order(Order { order_id: OrderId(1), order_side: OrderSide::Sell, price: 5010, ..order.clone() }).is_ok());
+
+        // 50% below the 5000 mid
+        assert_eq!(
+            order_book.add_order(Order { order_id: OrderId(2), ..order.clone() }).err().unwrap(),
+            OrderBookError::PriceBandViolation { price: 2500, max_deviation_bps: 1000 }
+        );
+        assert_eq!(
+            order_book.add_order(Order { order_id: OrderId(3), time_in_force: TimeInForce::Ioc, order_side: OrderSide::Sell, price: 7500, ..order.clone() }).err().unwrap(),
+            OrderBookError::PriceBandViolation { price: 7500, max_deviation_bps: 1000 }
+        );
+
+        // Exactly 10% away is still within the band
+        assert!(order_book.add_order(Order { order_id: OrderId(4), price: 4500, ..order.clone() }).is_ok());
+        assert!(order_book.add_order(Order { order_id: OrderId(5), order_side: OrderSide::Sell, price: 5500, ..order.clone() }).is_ok());
+        assert_eq!(order_book.resting_order_count(), 4);
+
+        // Market orders carry no price and aren't banded
+        let market_order = Order { order_id: OrderId(6), order_type: OrderType::Market, price: 0, quantity: 10, ..order };
+        assert!(order_book.add_order(market_order).is_ok());
+    }
+
+    #[test]
+    fn test_add_order_correctly_rests_only_gtc_day_and_gtd_remainders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 50,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(order_book.add_order(sell_order).is_ok());
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc
+        };
+
+        // Fill or Kill can't trade the full 100 against 50, so nothing happens
+        assert_eq!(order_book.add_order(Order { price: 5000, time_in_force: TimeInForce::Fok, ..buy_order.clone() }).err().unwrap(), OrderBookError::CannotFillCompletely);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), 50);
+
+        // Immediate or Cancel takes the 50 available and drops the rest
+        assert_eq!(order_book.add_order(Order { price: 5000, ..buy_order.clone() }).unwrap().len(), 1);
+        assert!(!order_book.contains_order(OrderId(1)));
+        assert_eq!(order_book.best_bid(), None);
+
+        for (order_id, time_in_force) in [(2, TimeInForce::Gtc), (3, TimeInForce::Day), (4, TimeInForce::Gtd(1_000))] {
+            assert!(order_book.add_order(Order { order_id: OrderId(order_id), time_in_force, ..buy_order.clone() }).is_ok());
+            assert!(order_book.contains_order(OrderId(order_id)));
+        }
+
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 300);
+    }
+
+    #[test]
+    fn test_expire_orders_correctly_expires_day_orders_at_session_end_and_gtd_orders_at_expiry() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 100,
+            ..Default::default()
+        };
+        let clock = MockClock::new(1_000);
+        let mut order_book = OrderBook::with_clock(config, Box::new(clock.clone()));
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let time_in_forces = [TimeInForce::Gtc, TimeInForce::Day, TimeInForce::Gtd(1_500), TimeInForce::Gtd(3_000)];
+
+        for (order_id, time_in_force) in time_in_forces.into_iter().enumerate() {
+            assert!(order_book.add_order(Order { order_id: OrderId(order_id as u64), time_in_force, ..order.clone() }).is_ok());
+        }
+
+        // A pending stop carries its time in force too
+        let stop_order = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::StopLimit,
+            order_side: OrderSide::Sell,
+            stop_price: Some(4000),
+            time_in_force: TimeInForce::Day,
+            ..order.clone()
+        };
+        assert!(order_book.add_order(stop_order).is_ok());
+
+        assert!(order_book.expire_orders(false).is_empty());
+
+        clock.set(2_000);
+        order_book.drain_events();
+
+        assert_eq!(order_book.expire_orders(false), vec![OrderId(2)]);
+        assert_eq!(order_book.drain_events(), vec![OrderBookEvent::OrderExpired { order_id: OrderId(2), remaining_quantity: 100, timestamp: 2_000 }]);
+        assert_eq!(order_book.expire_orders(true), vec![OrderId(1), OrderId(4)]);
+        assert!(order_book.stop_orders.is_empty());
+        assert_eq!(order_book.stats().orders_canceled, 0);
+
+        clock.set(3_000);
+
+        assert_eq!(order_book.expire_orders(true), vec![OrderId(3)]);
+        assert_eq!(order_book.resting_order_count(), 1);
+        assert!(order_book.contains_order(OrderId(0)));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 100);
+        assert_eq!(order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_replace_correctly_reprices_into_opposing_liquidity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5010,
+            quantity: 60,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-    fn recalculate_best_ask(&mut self, order_price: u32) -> Result<(), OrderBookError> {
-        if let Some(current_best) = self.best_ask_index {
-            if (order_price as usize) < current_best {
-                self.best_ask_index = Some(order_price as usize);
-            }
-        }
-        else {
-            self.best_ask_index = Some(order_price as usize);
-        }
+        assert!(order_book.add_order(order.clone()).is_ok());
+        assert!(order_book.add_order(Order { order_id: OrderId(1), order_side: OrderSide::Buy, user_id: UserId(1), price: 4990, quantity: 100, ..order.clone() }).is_ok());
 
-        Ok(())
+        let outcome = order_book.cancel_replace(OrderId(1), Order {
+            order_id: OrderId(2),
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 5010,
+            quantity: 100,
+            ..order
+        }).unwrap();
+
+        assert_eq!(outcome.canceled_id, OrderId(1));
+        assert_eq!(outcome.new_id, OrderId(2));
+        assert_eq!(outcome.fills.len(), 1);
+        assert_eq!(outcome.fills[0].resting_order_id, OrderId(0));
+        assert_eq!(outcome.fills[0].price, 5010);
+        assert_eq!(outcome.fills[0].quantity, 60);
+
+        assert!(!order_book.contains_order(OrderId(1)));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 0);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5010), 40);
+        assert_eq!(order_book.best_ask(), None);
     }
 
-    #[inline(never)]
-    fn can_fill_completely(&mut self, order: &Order) -> Result<bool, OrderBookError> {
-        let mut available_quantity = 0u32;
+    #[test]
+    fn test_cancel_replace_errors_and_leaves_original_resting_for_out_of_range_price() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        match order.order_side {
-            OrderSide::Buy => {
-                for i in 0..=order.price as usize {
-                    let queue = &self.asks[i];
-                    available_quantity += queue.iter().map(|&idx| self.order_ledger[idx].quantity as u32).sum::<u32>();
-                    if available_quantity >= order.quantity as u32 {
-                        return Ok(true);
-                    }
-                }
-            },
-            OrderSide::Sell => {
-                for i in (order.price as usize..self.bids.len()).rev() {
-                    let queue = &self.bids[i];
-                    available_quantity += queue.iter().map(|&idx| self.order_ledger[idx].quantity as u32).sum::<u32>();
-                    if available_quantity >= order.quantity as u32 {
-                        return Ok(true);
-                    }
-                }
-            }
-        }
+        let order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-        Ok(false)
-    }
-}
+        assert!(order_book.add_order(order.clone()).is_ok());
 
-#[cfg(test)]
-mod tests {
+        let ledger_index = order_book.index_mappings[&OrderId(0)];
 
-    use super::*;
+        assert_eq!(order_book.cancel_replace(OrderId(0), Order { order_id: OrderId(1), price: 20000, ..order }).err().unwrap(), OrderBookError::PriceOutOfRange);
+        assert_eq!(order_book.index_mappings[&OrderId(0)], ledger_index);
+        assert!(!order_book.contains_order(OrderId(1)));
+        assert_eq!(order_book.best_bid(), Some(4990));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 100);
+        assert_eq!(order_book.stats().orders_canceled, 0);
+    }
 
     #[test]
-    fn test_fill_order_correctly_fills_aggressive_order_resting_and_aggressive_order_quantities_equal() {
+    fn test_modify_order_errors_before_canceling_when_new_level_is_full() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            max_orders_per_level: 1,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let sell_order = Order {
-            order_id: 0,
+        let order = Order {
+            order_id: OrderId(0),
             order_type: OrderType::Limit,
-            order_status: OrderStatus::Active,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 800
-        };
-
-        let mut buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Market,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 800
+            user_id: UserId(0),
+            price: 4990,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let price_index = sell_order.price as usize;
+        assert!(order_book.add_order(order.clone()).is_ok());
+        assert!(order_book.add_order(Order { order_id: OrderId(1), price: 4980, ..order.clone() }).is_ok());
 
+        assert_eq!(order_book.modify_order(OrderId(0), Order { price: 4980, ..order }).err().unwrap(), OrderBookError::LevelOrderLimitReached(1));
+        assert!(order_book.contains_order(OrderId(0)));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 100);
+    }
 
-        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
-        order_book.asks[price_index].push_back(sell_order_index);
+    #[test]
+    fn test_take_bench_stats_correctly_returns_samples_and_empties_book_stats() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        let mut queue = order_book.asks[price_index].clone();
-        let mut fills = Vec::new();
+        order_book.bench_stats.add_order.extend([100, 200, 300]);
+        order_book.bench_stats.fill_order.push(50);
 
-        queue.pop_front();
+        let bench_stats = order_book.take_bench_stats();
 
-        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+        assert_eq!(bench_stats.add_order, vec![100, 200, 300]);
+        assert_eq!(bench_stats.fill_order, vec![50]);
+        assert!(order_book.bench_stats.summary().functions.iter().all(|(_, summary)| summary.count == 0));
 
-        assert!(fill_order_result.is_ok());
-        assert!(fill_order_result.unwrap());
-        assert!(queue.is_empty());
-        assert!(fills.len() == 1);
-        assert!(fills[0].aggressive_order_id == buy_order.order_id);
-        assert!(fills[0].resting_order_id == sell_order.order_id);
+        order_book.bench_stats.add_order.push(400);
+
+        assert_eq!(order_book.take_bench_stats().add_order, vec![400]);
     }
 
     #[test]
-    fn test_fill_order_correctly_fills_aggressive_order_resting_order_quantity_greater_than_aggressive_order_quantity() {
+    fn test_add_order_correctly_sweeps_bids_from_highest_price_for_market_sell() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::Active,
+        // Added out of price order so the sweep can't just follow insertion order
+        for (order_id, price) in [(0, 5000), (1, 4990), (2, 5010), (3, 4980)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let market_sell = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 800
+            user_id: UserId(1),
+            price: 0,
+            quantity: 250,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let mut buy_order = Order {
-            order_id: 1,
+        let fills = order_book.add_order(market_sell).unwrap();
+
+        assert_eq!(fills.iter().map(|fill| (fill.price, fill.quantity)).collect::<Vec<_>>(), vec![(5010, 100), (5000, 100), (4990, 50)]);
+        assert_eq!(order_book.best_bid(), Some(4990));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4990), 50);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 4980), 100);
+    }
+
+    #[test]
+    fn test_add_order_correctly_sweeps_asks_from_lowest_price_for_market_buy() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        for (order_id, price) in [(0, 5010), (1, 5020), (2, 5000)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let market_buy = Order {
+            order_id: OrderId(3),
             order_type: OrderType::Market,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(1),
+            price: 0,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let price_index = sell_order.price as usize;
-
-        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
-        order_book.asks[price_index].push_back(sell_order_index);
+        let fills = order_book.add_order(market_buy).unwrap();
 
-        let mut queue = order_book.asks[price_index].clone();
-        let mut fills = Vec::new();
+        assert_eq!(fills.iter().map(|fill| (fill.price, fill.quantity)).collect::<Vec<_>>(), vec![(5000, 100), (5010, 50)]);
+        assert_eq!(order_book.best_ask(), Some(5010));
+    }
 
-        queue.pop_front();
+    #[test]
+    fn test_add_order_with_outcome_correctly_classifies_limit_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+        // (order_id, side, quantity, expected outcome), all at 5000
+        let scenarios = [
+            (0, OrderSide::Sell, 100, OrderOutcome::Rested),
+            (1, OrderSide::Buy, 40, OrderOutcome::FullyFilled),
+            (2, OrderSide::Buy, 100, OrderOutcome::PartiallyFilledResting)
+        ];
+
+        for (order_id, order_side, quantity, expected) in scenarios {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(order_id as u32),
+                price: 5000,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            let (outcome, result) = order_book.add_order_with_outcome(order);
+
+            assert!(result.is_ok());
+            assert_eq!(outcome, expected);
+        }
 
-        assert!(fill_order_result.is_ok());
-        assert!(fill_order_result.unwrap());
-        assert_eq!(queue.len(), 1);
-        assert_eq!(queue[0], sell_order_index);
-        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 500);
-        assert_eq!(fills.len(), 1);
-        assert_eq!(fills[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(fills[0].resting_order_id, sell_order.order_id);
+        assert_eq!(order_book.best_bid(), Some(5000));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 5000), 40);
     }
 
     #[test]
-    fn test_fill_order_correctly_fills_aggressive_order_aggressive_order_quantity_greater_than_resting_order_quantity() {
+    fn test_add_order_with_outcome_correctly_classifies_canceled_and_rejected_orders() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let sell_order = Order {
-            order_id: 0,
+        let ask = Order {
+            order_id: OrderId(0),
             order_type: OrderType::Limit,
-            order_status: OrderStatus::Active,
+            order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(0),
+            price: 5010,
+            quantity: 50,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let mut buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Market,
+        assert_eq!(order_book.add_order_with_outcome(ask).0, OrderOutcome::Rested);
+
+        let unmatched_ioc = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Limit,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 800
+            user_id: UserId(1),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Ioc
         };
 
-        let price_index = sell_order.price as usize;
+        let (outcome, result) = order_book.add_order_with_outcome(unmatched_ioc);
 
-        let sell_order_index = order_book.order_ledger.insert(sell_order.clone());
-        order_book.asks[price_index].push_back(sell_order_index);
+        assert!(result.is_ok_and(|fills| fills.is_empty()));
+        assert_eq!(outcome, OrderOutcome::Canceled);
 
-        let mut queue = order_book.asks[price_index].clone();
-        let mut fills = Vec::new();
+        // Sweeps the 50 resting and drops the other 50
+        let market_buy = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-        queue.pop_front();
+        assert_eq!(order_book.add_order_with_outcome(market_buy).0, OrderOutcome::Canceled);
 
-        let fill_order_result = order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills);
+        let out_of_range = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 20000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-        assert!(fill_order_result.is_ok());
-        assert!(!fill_order_result.unwrap());
-        assert!(queue.is_empty());
-        assert_eq!(buy_order.quantity, 500);
-        assert_eq!(fills.len(), 1);
-        assert_eq!(fills[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(fills[0].resting_order_id, sell_order.order_id);
+        let (outcome, result) = order_book.add_order_with_outcome(out_of_range);
+
+        assert!(result.is_err());
+        assert_eq!(outcome, OrderOutcome::Rejected);
+        assert_eq!(order_book.resting_order_count(), 0);
     }
 
     #[test]
-    fn test_add_order_correctly_adds_limit_order_to_empty_order_book() {
+    fn test_match_order_against_book_correctly_keeps_occupied_levels_in_sync_across_fills_and_cancels() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
+        // Deliberately sparse, so a sweep has thousands of empty levels between the bids
+        for (order_id, order_side, price) in [(0, OrderSide::Buy, 100), (1, OrderSide::Buy, 5000), (2, OrderSide::Buy, 9000), (3, OrderSide::Sell, 9500), (4, OrderSide::Sell, 9900)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        assert_eq!(order_book.occupied_bid_levels.iter().copied().collect::<Vec<_>>(), vec![100, 5000, 9000]);
+        assert_eq!(order_book.occupied_ask_levels.iter().copied().collect::<Vec<_>>(), vec![9500, 9900]);
+
+        let market_sell = Order {
+            order_id: OrderId(5),
+            order_type: OrderType::Market,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(1),
+            price: 0,
+            quantity: 150,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let price_index = order.price as usize;
+        let fills = order_book.add_order(market_sell).unwrap();
 
-        let add_order_result = order_book.add_order(order.clone());
+        assert_eq!(fills.iter().map(|fill| (fill.price, fill.quantity)).collect::<Vec<_>>(), vec![(9000, 100), (5000, 50)]);
+        assert_eq!(order_book.occupied_bid_levels.iter().copied().collect::<Vec<_>>(), vec![100, 5000]);
+        assert_eq!(order_book.validate(), Ok(()));
 
-        let order_index = order_book.index_mappings[&order.order_id];
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+        assert_eq!(order_book.occupied_bid_levels.iter().copied().collect::<Vec<_>>(), vec![5000]);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        assert_eq!(order_book.cancel_range(OrderSide::Sell, 9000, 10000), vec![OrderId(3), OrderId(4)]);
+        assert!(order_book.occupied_ask_levels.is_empty());
+        assert_eq!(order_book.validate(), Ok(()));
+
+        order_book.clear();
+        assert!(order_book.occupied_bid_levels.is_empty());
     }
 
     #[test]
-    fn test_add_order_correctly_executes_order_fill() {
+    fn test_validate_errors_corrupt_book_for_out_of_sync_occupied_levels() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
+        let order = Order {
+            order_id: OrderId(0),
             order_type: OrderType::Limit,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let price_index = sell_order.price as usize;
+        assert!(order_book.add_order(order).is_ok());
 
-        let add_sell_order_result = order_book.add_order(sell_order.clone());
+        order_book.occupied_ask_levels.insert(5001);
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(reason)) if reason.contains("occupancy")));
+        order_book.occupied_ask_levels.remove(&5001);
 
-        sell_order.order_status = OrderStatus::Active;
+        order_book.occupied_ask_levels.remove(&5000);
+        assert!(matches!(order_book.validate(), Err(OrderBookError::CorruptBook(_))));
+        order_book.occupied_ask_levels.insert(5000);
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        assert_eq!(order_book.validate(), Ok(()));
+    }
 
-        assert!(add_sell_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+    #[test]
+    fn test_marketable_quantity_correctly_sums_reachable_asks_capped_at_order_quantity() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Market,
+        for (order_id, price, quantity) in [(0, 5000, 100), (1, 5005, 200), (2, 5010, 300), (3, 5020, 400)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut limit_buy = Order {
+            order_id: OrderId(4),
+            order_type: OrderType::Limit,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(1),
+            price: 5010,
+            quantity: 1000,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let add_buy_order_result = order_book.add_order(buy_order.clone());
+        // Reaches the three levels up to 5010, and would rest the other 400
+        assert_eq!(order_book.marketable_quantity(&limit_buy), 600);
 
-        assert!(add_buy_order_result.is_ok());
-        assert!(order_book.asks[price_index].is_empty());
+        limit_buy.quantity = 250;
+        assert_eq!(order_book.marketable_quantity(&limit_buy), 250);
+
+        limit_buy.price = 4999;
+        assert_eq!(order_book.marketable_quantity(&limit_buy), 0);
+
+        // Nothing is traded, so the same order still matches for real
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 1000);
     }
 
     #[test]
-    fn test_add_order_correctly_executes_order_fill_on_limit_order_and_adds_remaining_to_order_book() {
+    fn test_marketable_quantity_correctly_handles_market_and_fill_or_kill_orders() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
+        for (order_id, price) in [(0, 5000), (1, 4990)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let mut sell = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Market,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
+            user_id: UserId(1),
+            price: 0,
+            quantity: 500,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let price_index = sell_order.price as usize;
+        assert_eq!(order_book.marketable_quantity(&sell), 200);
 
-        let add_sell_order_result = order_book.add_order(sell_order.clone());
+        sell.order_type = OrderType::Limit;
+        sell.price = 5000;
+        sell.time_in_force = TimeInForce::Fok;
+        assert_eq!(order_book.marketable_quantity(&sell), 0);
 
-        sell_order.order_status = OrderStatus::Active;
+        sell.quantity = 100;
+        assert_eq!(order_book.marketable_quantity(&sell), 100);
+    }
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+    #[test]
+    fn test_submit_correctly_fills_fractional_quantities_exactly() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            qty_scale: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        assert!(add_sell_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        // 0.1 + 0.2 famously isn't 0.3 in floating point
+        assert!(order_book.submit(OrderId(0), UserId(0), OrderSide::Sell, OrderType::Limit, Decimal::new(5000, 2), Decimal::new(1, 1)).is_ok());
+        assert!(order_book.submit(OrderId(1), UserId(0), OrderSide::Sell, OrderType::Limit, Decimal::new(5001, 2), Decimal::new(2, 1)).is_ok());
 
-        let mut buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 500
+        let fills = order_book.submit(OrderId(2), UserId(1), OrderSide::Buy, OrderType::Limit, Decimal::new(5001, 2), Decimal::new(3, 1)).unwrap();
+
+        let filled: Decimal = fills.iter().map(|fill| order_book.config.units_to_qty(fill.quantity)).sum();
+        let notional: Decimal = fills.iter().map(|fill| order_book.config.notional(fill.price, fill.quantity)).sum();
+
+        assert_eq!(filled, Decimal::new(3, 1));
+        assert_eq!(notional, Decimal::new(15002, 3));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 0);
+
+        let submit_result = order_book.submit(OrderId(3), UserId(1), OrderSide::Buy, OrderType::Limit, Decimal::new(5000, 2), Decimal::new(1, 4));
+        assert_eq!(submit_result.err(), Some(OrderBookError::InvalidQuantityScale(3)));
+    }
+
+    #[test]
+    fn test_fillable_quantity_for_notional_correctly_sizes_fractional_units() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            price_scale: 2,
+            qty_scale: 3,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert!(order_book.submit(OrderId(0), UserId(0), OrderSide::Sell, OrderType::Limit, Decimal::new(5000, 2), Decimal::from(1)).is_ok());
+
+        // 1.01 buys 0.020 at 50.00, with a cent left over
+        assert_eq!(order_book.fillable_quantity_for_notional(OrderSide::Buy, Decimal::new(101, 2)), (20, Decimal::new(100, 2)));
+    }
+
+    #[test]
+    fn test_add_order_stops_market_order_at_slippage_limit_instead_of_jumping_a_gap() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            max_slippage_ticks: 10,
+            ..Default::default()
         };
+        let mut order_book = OrderBook::new(config);
 
-        let add_buy_order_result = order_book.add_order(buy_order.clone());
+        // 5000 and 5010 are within 10 ticks of the best ask, 6000 is across the gap
+        for (order_id, price) in [(0, 5000), (1, 5010), (2, 6000)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Sell,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
 
-        buy_order.order_status = OrderStatus::PartiallyFilled;
-        buy_order.quantity = 200;
+        let market_buy = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 300,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-        let buy_order_index = order_book.index_mappings[&buy_order.order_id];
+        assert_eq!(order_book.preview(&market_buy).unwrap().len(), 2);
+        assert_eq!(order_book.add_order(market_buy).unwrap().iter().map(|fill| fill.quantity).sum::<u64>(), 200);
 
-        assert!(add_buy_order_result.is_ok());
-        assert!(order_book.asks[price_index].is_empty());
-        assert_eq!(order_book.bids[price_index].len(), 1);
-        assert_eq!(order_book.bids[price_index][0], buy_order_index);
+        assert_eq!(order_book.trade_history.iter().map(|fill| fill.price).collect::<Vec<_>>(), vec![5000, 5010]);
+        assert_eq!(order_book.best_ask(), Some(6000));
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 6000), 100);
     }
 
     #[test]
-    fn test_add_order_errors_price_out_of_range() {
+    fn test_add_order_drops_market_order_remainder_when_book_runs_out_within_slippage_limit() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            max_slippage_ticks: 10,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
+        for (order_id, price) in [(0, 5000), (1, 4995)] {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(order_book.add_order(order).is_ok());
+        }
+
+        let market_sell = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Market,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 100000,
-            quantity: 300
+            user_id: UserId(1),
+            price: 0,
+            quantity: 250,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
 
-        let add_order_result = order_book.add_order(order.clone());
-
-        assert!(add_order_result.is_err());
-        assert_eq!(add_order_result.err().unwrap(), OrderBookError::PriceOutOfRange);
+        assert_eq!(order_book.add_order(market_sell).unwrap().iter().map(|fill| fill.quantity).sum::<u64>(), 200);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 0);
     }
 
     #[test]
-    fn test_cancel_order_correctly_cancels_resting_limit_order() {
+    fn test_is_empty_has_bids_and_has_asks_correctly_track_each_side() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
+        assert!(order_book.is_empty());
+        assert!(!order_book.has_bids() && !order_book.has_asks());
 
-        let price_index = order.price as usize;
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 4990, 100)).is_ok());
 
-        let add_order_result = order_book.add_order(order.clone());
+        assert!(!order_book.is_empty());
+        assert!(order_book.has_bids() && !order_book.has_asks());
 
-        order.order_status = OrderStatus::Active;
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(0), 5010, 100)).is_ok());
 
-        let order_index = order_book.index_mappings[&order.order_id];
+        assert!(!order_book.is_empty());
+        assert!(order_book.has_bids() && order_book.has_asks());
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        // Taking out the only bid leaves a one-sided book again
+        assert!(order_book.add_order(Order::market_sell(OrderId(2), UserId(1), 100)).is_ok());
 
-        let cancel_order_result = order_book.cancel_order(order.order_id);
+        assert!(!order_book.has_bids() && order_book.has_asks());
 
-        assert!(cancel_order_result.is_ok());
-        assert!(order_book.asks[price_index].is_empty());
+        assert!(order_book.cancel_order(OrderId(1)).is_ok());
+
+        assert!(order_book.is_empty());
     }
 
     #[test]
-    fn test_cancel_order_errors_order_not_found() {
+    fn test_add_order_correctly_maps_coarse_ticks_to_consecutive_levels() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 1100,
+            tick_size: 25,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        // One level per tick rather than per unit of price
+        assert_eq!(order_book.capacity_levels(), 41);
+
+        for (order_id, price) in [(0, 100), (1, 125), (2, 150)] {
+            assert!(order_book.add_order(Order::limit_buy(OrderId(order_id), UserId(0), price, 10 * (order_id + 1))).is_ok());
+        }
+
+        assert!(order_book.add_order(Order::limit_sell(OrderId(3), UserId(0), 1100, 40)).is_ok());
+
+        for (index, order_id) in [(0, 0), (1, 1), (2, 2)] {
+            assert_eq!(order_book.bids[index].iter().map(|&idx| order_book.order_ledger[idx].order_id).collect::<Vec<_>>(), vec![OrderId(order_id)]);
+        }
+        assert_eq!(order_book.asks[40].len(), 1);
+
+        assert_eq!(order_book.best_bid(), Some(150));
+        assert_eq!(order_book.best_ask(), Some(1100));
+        assert_eq!(order_book.depth(3).bids, vec![
+            DepthLevel { price: 150, quantity: 30, order_count: 1 },
+            DepthLevel { price: 125, quantity: 20, order_count: 1 },
+            DepthLevel { price: 100, quantity: 10, order_count: 1 }
+        ]);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 125), 20);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 130), 0);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Buy, 110), 50);
+
+        assert_eq!(order_book.add_order(Order::limit_buy(OrderId(4), UserId(0), 130, 10)).err(), Some(OrderBookError::InvalidTick(25)));
+        assert_eq!(order_book.add_order(Order::limit_buy(OrderId(5), UserId(0), 1125, 10)).err(), Some(OrderBookError::PriceOutOfRange));
+
+        let fills = order_book.add_order(Order::limit_sell(OrderId(6), UserId(1), 125, 40)).unwrap();
+
+        assert_eq!(fills.iter().map(|fill| (fill.price, fill.quantity)).collect::<Vec<_>>(), vec![(150, 30), (125, 10)]);
+        assert_eq!(order_book.best_bid(), Some(125));
+        assert_eq!(order_book.cancel_range(OrderSide::Buy, 90, 130), vec![OrderId(0), OrderId(1)]);
+        assert_eq!(order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_order_count_correctly_tracks_adds_fills_and_cancels() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
+        assert_eq!(order_book.order_count(), 0);
 
-        let price_index = order.price as usize;
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 4990, 100)).is_ok());
+        assert!(order_book.add_order(Order::limit_buy(OrderId(1), UserId(0), 4995, 100)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(2), UserId(0), 5010, 100)).is_ok());
 
-        let add_order_result = order_book.add_order(order.clone());
+        let stop_sell = OrderBuilder::new(OrderId(3), OrderSide::Sell)
+            .order_type(OrderType::Stop)
+            .quantity(50)
+            .stop_price(4900)
+            .build();
+        assert!(order_book.add_order(stop_sell).is_ok());
 
-        order.order_status = OrderStatus::Active;
+        let midpoint_buy = OrderBuilder::new(OrderId(4), OrderSide::Buy)
+            .order_type(OrderType::MidpointPeg)
+            .quantity(50)
+            .build();
+        assert!(order_book.add_order(midpoint_buy).is_ok());
 
-        let order_index = order_book.index_mappings[&order.order_id];
+        assert_eq!(order_book.order_count(), 5);
+        assert_eq!(order_book.resting_order_count(), 3);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        // Fully fills the best bid, which leaves the book
+        assert!(order_book.add_order(Order::market_sell(OrderId(5), UserId(1), 100)).is_ok());
+        assert_eq!(order_book.order_count(), 4);
 
-        let cancel_order_result = order_book.cancel_order(99);
+        for order_id in [0, 3, 4] {
+            assert!(order_book.cancel_order(OrderId(order_id)).is_ok());
+        }
 
-        assert!(cancel_order_result.is_err());
-        assert_eq!(cancel_order_result.err().unwrap(), OrderBookError::OrderNotFound);
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        assert_eq!(order_book.order_count(), 1);
+        assert!(order_book.cancel_order(OrderId(0)).is_err());
+        assert_eq!(order_book.order_count(), 1);
     }
 
     #[test]
-    fn test_cancel_order_errors_price_out_of_range() {
+    fn test_on_trade_price_correctly_triggers_market_if_touched_opposite_to_stops() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::Active,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10100,
-            quantity: 300
-        };
-
-        let price_index = order.price as usize;
-
-        
-        let order_index = order_book.order_ledger.insert(order.clone());
-        order_book.asks.extend([const { VecDeque::new() }; 10000]);
-        order_book.asks[price_index].push_back(order_index);
-
-        let cancel_order_result = order_book.cancel_order(99);
-
-        assert!(cancel_order_result.is_err());
-        assert_eq!(cancel_order_result.err().unwrap(), OrderBookError::OrderNotFound);
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        assert!(order_book.add_order(Order::limit_sell(OrderId(0), UserId(0), 5060, 100)).is_ok());
+
+        let stop_buy = OrderBuilder::new(OrderId(1), OrderSide::Buy)
+            .order_type(OrderType::Stop)
+            .user_id(UserId(1))
+            .quantity(10)
+            .stop_price(5050)
+            .build();
+        let market_if_touched_buy = OrderBuilder::new(OrderId(2), OrderSide::Buy)
+            .order_type(OrderType::MarketIfTouched)
+            .user_id(UserId(1))
+            .quantity(20)
+            .stop_price(4950)
+            .build();
+
+        assert_eq!(stop_buy.trigger_direction(), Some(TriggerDirection::Rising));
+        assert_eq!(market_if_touched_buy.trigger_direction(), Some(TriggerDirection::Falling));
+
+        assert!(order_book.add_order(stop_buy).is_ok());
+        assert!(order_book.add_order(market_if_touched_buy).is_ok());
+
+        // A rise to 5050 only reaches the buy stop
+        assert_eq!(order_book.on_trade_price(5050), vec![OrderId(1)]);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5060), 90);
+        assert!(order_book.contains_order(OrderId(2)));
+
+        assert!(order_book.on_trade_price(5000).is_empty());
+
+        // A fall to 4950 reaches the buy MIT, which then buys at market
+        assert_eq!(order_book.on_trade_price(4950), vec![OrderId(2)]);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5060), 70);
+        assert!(order_book.stop_orders.is_empty());
     }
 
     #[test]
-    fn test_modify_order_correctly_modifies_resting_limit_order() {
+    fn test_on_trade_price_correctly_rests_triggered_limit_if_touched_order() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::Active,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
+        let limit_if_touched_sell = OrderBuilder::new(OrderId(0), OrderSide::Sell)
+            .order_type(OrderType::LimitIfTouched)
+            .price(5100)
+            .quantity(50)
+            .stop_price(5080)
+            .build();
 
-        let price_index = order.price as usize;
+        assert_eq!(limit_if_touched_sell.trigger_direction(), Some(TriggerDirection::Rising));
+        assert!(order_book.add_order(limit_if_touched_sell).is_ok());
 
-        let add_order_result = order_book.add_order(order.clone());
+        assert!(order_book.on_trade_price(5070).is_empty());
+        assert_eq!(order_book.best_ask(), None);
 
-        order.order_status = OrderStatus::Active;
+        assert_eq!(order_book.on_trade_price(5080), vec![OrderId(0)]);
+        assert_eq!(order_book.best_ask(), Some(5100));
+        assert_eq!(order_book.best_ask_order().map(|order| order.order_type.clone()), Some(OrderType::Limit));
+    }
 
-        let order_index = order_book.index_mappings[&order.order_id];
+    #[test]
+    fn test_replay_correctly_rebuilds_book_from_recorded_events() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 1000,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::with_clock(config.clone(), Box::new(MockClock::new(100)));
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], order_index);
+        for (order_id, price) in [(0, 4990), (1, 4995), (2, 5000)] {
+            assert!(order_book.add_order(Order::limit_buy(OrderId(order_id), UserId(0), price, 100)).is_ok());
+        }
+        for (order_id, price) in [(3, 5010), (4, 5020)] {
+            assert!(order_book.add_order(Order::limit_sell(OrderId(order_id), UserId(1), price, 100)).is_ok());
+        }
 
-        let mut modified_order = order.clone();
-        modified_order.quantity = 500;
+        // Partial fill, in-place amend, a price amend that cancels and re-adds, a cancel and a rejection
+        assert!(order_book.add_order(Order::market_sell(OrderId(5), UserId(2), 150)).is_ok());
+        assert!(order_book.modify_order(OrderId(3), Order::limit_sell(OrderId(3), UserId(1), 5010, 60)).is_ok());
+        assert!(order_book.modify_order(OrderId(4), Order::limit_sell(OrderId(4), UserId(1), 5015, 100)).is_ok());
+        assert!(order_book.cancel_order(OrderId(0)).is_ok());
+        assert!(order_book.add_order(Order::limit_buy(OrderId(1), UserId(2), 4000, 10)).is_err());
+
+        let day_order = OrderBuilder::new(OrderId(7), OrderSide::Sell)
+            .price(5050)
+            .quantity(10)
+            .time_in_force(TimeInForce::Day)
+            .build();
+        assert!(order_book.add_order(day_order).is_ok());
+        assert_eq!(order_book.expire_orders(true), vec![OrderId(7)]);
+
+        assert!(order_book.add_order(Order::limit_sell(OrderId(8), UserId(1), 5030, 40)).is_ok());
+
+        let replayed = OrderBook::replay(config, order_book.events.make_contiguous()).unwrap();
+
+        assert_eq!(replayed.checksum(), order_book.checksum());
+        assert_eq!(replayed.depth(10), order_book.depth(10));
+        assert_eq!(replayed.order_count(), order_book.order_count());
+        assert_eq!(replayed.trade_history.len(), order_book.trade_history.len());
+    }
 
-        let modify_order_result = order_book.modify_order(order.order_id, modified_order.clone());
+    #[test]
+    fn test_replay_errors_order_not_found_for_incomplete_log() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 1000,
+            ..Default::default()
+        };
 
-        let buy_order_index = order_book.index_mappings[&order.order_id];
+        let events = [OrderBookEvent::OrderCanceled { order_id: OrderId(0), remaining_quantity: 100, timestamp: 0 }];
 
-        assert!(modify_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[buy_order_index], modified_order);
+        assert!(matches!(OrderBook::replay(config, &events), Err(OrderBookError::OrderNotFound)));
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_fills_limit_order_no_remaining_quantity() {
+    fn test_micro_price_correctly_skews_towards_the_thinner_side() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
-
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
-        };
-
-        let price_index = sell_order.price as usize;
-
-        let add_order_result = order_book.add_order(sell_order.clone());
-
-        sell_order.order_status = OrderStatus::Active;
+        assert_eq!(order_book.micro_price(), None);
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 4990, 100)).is_ok());
+        assert_eq!(order_book.micro_price(), None);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        // Heavier ask: (49.90 * 300 + 50.10 * 100) / 400 = 49.95, below the 50.00 mid
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(0), 5010, 300)).is_ok());
+        assert_eq!(order_book.micro_price(), Some(Decimal::new(4995, 2)));
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        // Heavier bid: (49.90 * 300 + 50.10 * 500) / 800 = 50.025, above the mid
+        assert!(order_book.add_order(Order::limit_buy(OrderId(2), UserId(0), 4990, 400)).is_ok());
+        assert_eq!(order_book.micro_price(), Some(Decimal::new(50025, 3)));
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert!(order_book.asks[price_index].is_empty());
-        assert!(order_book.bids[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        // Balanced top of book sits on the mid
+        assert!(order_book.add_order(Order::limit_sell(OrderId(3), UserId(0), 5010, 200)).is_ok());
+        assert_eq!(order_book.micro_price(), Some(Decimal::new(5000, 2)));
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_fills_limit_order_with_remaining_quantity() {
+    fn test_fill_order_correctly_keeps_quantities_consistent_across_partial_fills() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
+        assert!(order_book.add_order(Order::limit_sell(OrderId(0), UserId(0), 5000, 100)).is_ok());
+
+        let sell_order_index = order_book.index_mappings[&OrderId(0)];
+        let mut queue = std::mem::take(&mut order_book.asks[5000]);
+        let mut fills = Vec::new();
+
+        // Resting > aggressive, then resting == aggressive: every fill subtracts the smaller quantity from the larger
+        for (order_id, quantity, resting_remaining) in [(1, 30, 70), (2, 70, 0)] {
+            let mut buy_order = Order::market_buy(OrderId(order_id), UserId(1), quantity);
+
+            assert_eq!(order_book.fill_order(&mut queue, &mut buy_order, sell_order_index, &mut fills), Ok(true));
+            assert_eq!(buy_order.quantity, 0);
+            assert_eq!(buy_order.filled_quantity, quantity);
+            assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 5000), resting_remaining);
+        }
+
+        assert!(queue.is_empty());
+        assert!(!order_book.order_ledger.contains(sell_order_index));
+        assert_eq!(fills.iter().map(|fill| fill.quantity).sum::<u64>(), 100);
+    }
+
+    #[test]
+    fn test_add_order_stop_validates_only_stop_price() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            ..Default::default()
         };
+        let mut order_book = OrderBook::new(config);
 
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Limit,
+        let buy_stop_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Stop,
             order_status: OrderStatus::PendingNew,
             order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 600
+            user_id: UserId(0),
+            price: 0,
+            quantity: 100,
+            stop_price: Some(5005),
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
         };
+        let off_grid_stop_order = Order { order_id: OrderId(1), stop_price: Some(5003), ..buy_stop_order.clone() };
+        let out_of_range_stop_order = Order { order_id: OrderId(2), order_type: OrderType::MarketIfTouched, stop_price: Some(95), ..buy_stop_order.clone() };
 
-        let price_index = sell_order.price as usize;
-
-        let add_order_result = order_book.add_order(sell_order.clone());
-
-        sell_order.order_status = OrderStatus::Active;
+        assert!(order_book.add_order(buy_stop_order).is_ok());
+        assert_eq!(order_book.add_order(off_grid_stop_order).err().unwrap(), OrderBookError::InvalidTick(5));
+        assert_eq!(order_book.add_order(out_of_range_stop_order).err().unwrap(), OrderBookError::PriceOutOfRange);
+        assert_eq!(order_book.stop_orders.len(), 1);
+    }
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+    #[test]
+    fn test_add_order_at_price_correctly_applies_min_price_and_tick_size() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            price_scale: 2,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        assert!(order_book.add_order_at_price(Order::limit_buy(OrderId(0), UserId(0), 0, 100), Decimal::new(5025, 2)).is_ok());
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        assert_eq!(order_book.best_bid(), Some(5025));
+        assert_eq!(order_book.best_bid_index(), Some(985));
+    }
 
-        let buy_order_index = order_book.index_mappings[&buy_order.order_id];
+    #[test]
+    fn test_fill_or_kill_order_errors_cannot_fill_completely_against_larger_all_or_none_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert_eq!(order_book.bids[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[buy_order_index].quantity, 300);
-        assert!(order_book.asks[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        let all_or_none_sell = Order::builder(OrderId(0), OrderSide::Sell)
+            .order_type(OrderType::AllOrNone)
+            .price(100)
+            .quantity(100)
+            .build();
+        let fill_or_kill_buy = Order::builder(OrderId(1), OrderSide::Buy)
+            .user_id(UserId(1))
+            .price(100)
+            .quantity(50)
+            .time_in_force(TimeInForce::Fok)
+            .build();
+
+        assert!(order_book.add_order(all_or_none_sell).is_ok());
+
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_buy), 0);
+        assert_eq!(order_book.preview(&fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.add_order(fill_or_kill_buy.clone()).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 100);
+
+        // A normal order behind the All or None order can still fill it
+        assert!(order_book.add_order(Order::limit_sell(OrderId(2), UserId(0), 100, 60)).is_ok());
+
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_buy), 50);
+        assert_eq!(order_book.add_order(fill_or_kill_buy).unwrap().iter().map(|fill| fill.resting_order_id).collect::<Vec<_>>(), vec![OrderId(2)]);
+        assert_eq!(order_book.cumulative_quantity(OrderSide::Sell, 100), 110);
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_fills_market_order() {
+    fn test_add_order_errors_order_size_out_of_range_for_notional_order_sized_below_min() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            price_scale: 2,
+            min_order_qty: 10,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 600
-        };
+        assert!(order_book.add_order(Order::limit_sell(OrderId(0), UserId(0), 5000, 100)).is_ok());
 
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Market,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
-        };
+        // $150 buys 3 at $50.00, under the minimum of 10
+        let buy_order = Order::builder(OrderId(1), OrderSide::Buy)
+            .order_type(OrderType::MarketNotional(Decimal::new(150, 0)))
+            .user_id(UserId(1))
+            .quantity(0)
+            .build();
+        let expected_error = OrderBookError::OrderSizeOutOfRange { min: 10, max: u64::MAX, got: 3 };
 
-        let price_index = sell_order.price as usize;
+        assert_eq!(order_book.preview(&buy_order).err(), Some(expected_error.clone()));
+        assert_eq!(order_book.add_order(buy_order).err(), Some(expected_error));
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 100);
+    }
 
-        let add_order_result = order_book.add_order(sell_order.clone());
+    #[test]
+    fn test_add_order_returns_fills_and_drops_remainder_when_level_order_limit_reached() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 100,
+            max_orders_per_level: 1,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        sell_order.order_status = OrderStatus::Active;
+        let all_or_none_sell = Order::builder(OrderId(0), OrderSide::Sell)
+            .order_type(OrderType::AllOrNone)
+            .price(100)
+            .quantity(100)
+            .build();
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        assert!(order_book.add_order(all_or_none_sell).is_ok());
+        assert!(order_book.add_order(Order::limit_buy(OrderId(1), UserId(1), 101, 10)).is_ok());
+        order_book.drain_events();
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        let fills = order_book.add_order(Order::limit_buy(OrderId(2), UserId(2), 101, 150)).unwrap();
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        assert_eq!(fills.iter().map(|fill| fill.quantity).sum::<u64>(), 100);
+        assert!(!order_book.contains_order(OrderId(2)));
+        assert_eq!(order_book.stats().orders_rejected, 0);
+        assert!(!order_book.drain_events().iter().any(|event| matches!(event, OrderBookEvent::OrderRejected { .. })));
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
-        assert!(order_book.bids[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        // With nothing traded the order is still rejected outright
+        assert_eq!(order_book.add_order(Order::limit_buy(OrderId(3), UserId(2), 101, 10)).err(), Some(OrderBookError::LevelOrderLimitReached(1)));
+    }
+
+    #[test]
+    fn test_modify_order_checks_price_band_without_the_original_order() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            max_price_deviation_bps: 1000,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 1000, 10)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(1), 1100, 10)).is_ok());
+
+        // Without the bid the reference is the ask alone, and 960 sits more than 10% under it
+        assert_eq!(
+            order_book.modify_order(OrderId(0), Order::limit_buy(OrderId(0), UserId(0), 960, 10)).err(),
+            Some(OrderBookError::PriceBandViolation { price: 960, max_deviation_bps: 1000 })
+        );
+        assert!(order_book.contains_order(OrderId(0)));
+        assert_eq!(order_book.best_bid(), Some(1000));
+
+        assert!(order_book.modify_order(OrderId(0), Order::limit_buy(OrderId(0), UserId(0), 1050, 10)).is_ok());
+        assert_eq!(order_book.best_bid(), Some(1050));
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_fills_part_of_market_order_and_errors_insufficient_liquidity() {
+    fn test_modify_order_correctly_reduces_in_place_when_only_the_original_keeps_it_in_band() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            max_price_deviation_bps: 1000,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 1000, 10)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(1), 1100, 10)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(2), UserId(1), 1150, 10)).is_ok());
+        assert!(order_book.cancel_order(OrderId(1)).is_ok());
 
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::Market,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 600
+        // 1000 is within 10% of the 1075 mid, but not of the 1150 ask on its own
+        assert!(order_book.modify_order(OrderId(0), Order::limit_buy(OrderId(0), UserId(0), 1000, 5)).is_ok());
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 1000), 5);
+    }
+
+    #[test]
+    fn test_replay_correctly_restores_positions_and_next_order_id() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            event_log_capacity: 1000,
+            ..Default::default()
         };
+        let mut order_book = OrderBook::with_clock(config.clone(), Box::new(MockClock::new(100)));
 
-        let price_index = sell_order.price as usize;
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 5000, 100)).is_ok());
+        order_book.set_position(UserId(1), 40);
 
-        let add_order_result = order_book.add_order(sell_order.clone());
+        let reduce_only_sell = Order::builder(OrderId(1), OrderSide::Sell)
+            .user_id(UserId(1))
+            .price(5000)
+            .quantity(40)
+            .reduce_only(true)
+            .build();
 
-        sell_order.order_status = OrderStatus::Active;
+        assert_eq!(order_book.add_order(reduce_only_sell).unwrap().len(), 1);
+        assert_eq!(order_book.submit_auto_id(Order::limit_sell(OrderId(0), UserId(2), 5100, 10)).0, OrderId(1));
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        let mut replayed = OrderBook::replay(config, order_book.events.make_contiguous()).unwrap();
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        assert_eq!(replayed.trade_history.len(), 1);
+        assert_eq!(replayed.checksum(), order_book.checksum());
+        assert_eq!(replayed.next_order_id, order_book.next_order_id);
+        assert_eq!(replayed.submit_auto_id(Order::limit_sell(OrderId(0), UserId(2), 5100, 10)).0, OrderId(2));
+    }
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+    #[test]
+    fn test_add_order_errors_for_stop_limit_and_limit_if_touched_triggers_off_the_grid() {
+        let config = OrderBookConfig {
+            min_price: 100,
+            max_price: 10000,
+            tick_size: 5,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
 
-        assert!(execute_fill_by_order_type_result.is_err());
-        assert_eq!(execute_fill_by_order_type_result.err().unwrap(), OrderBookError::InsufficientLiquidity);
-        assert!(order_book.asks[price_index].is_empty());
-        assert!(order_book.bids[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        let stop_limit_order = Order::builder(OrderId(0), OrderSide::Buy)
+            .order_type(OrderType::StopLimit)
+            .price(5010)
+            .quantity(100)
+            .stop_price(5005)
+            .build();
+        let off_grid_order = Order { order_id: OrderId(1), stop_price: Some(5003), ..stop_limit_order.clone() };
+        let below_range_order = Order { order_id: OrderId(2), order_type: OrderType::LimitIfTouched, stop_price: Some(95), ..stop_limit_order.clone() };
+
+        assert!(order_book.add_order(stop_limit_order).is_ok());
+        assert_eq!(order_book.add_order(off_grid_order).err(), Some(OrderBookError::InvalidTick(5)));
+        assert_eq!(order_book.add_order(below_range_order).err(), Some(OrderBookError::PriceOutOfRange));
+        assert_eq!(order_book.stop_orders.len(), 1);
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_fills_immediate_or_cancel_order() {
+    fn test_add_order_kills_market_fill_or_kill_order_the_book_cannot_fill() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 600
-        };
-
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::ImmediateOrCancel,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
-        };
-
-        let price_index = sell_order.price as usize;
-
-        let add_order_result = order_book.add_order(sell_order.clone());
-
-        sell_order.order_status = OrderStatus::Active;
-
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
-
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 100, 200)).is_ok());
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        let fill_or_kill_sell = Order::builder(OrderId(1), OrderSide::Sell)
+            .order_type(OrderType::Market)
+            .user_id(UserId(1))
+            .quantity(500)
+            .time_in_force(TimeInForce::Fok)
+            .build();
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
-        assert!(order_book.bids[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_sell), 0);
+        assert_eq!(order_book.preview(&fill_or_kill_sell).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.add_order(fill_or_kill_sell).err(), Some(OrderBookError::CannotFillCompletely));
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 200);
+
+        // Within the book's depth it fills in full
+        let fill_or_kill_sell = Order::builder(OrderId(2), OrderSide::Sell)
+            .order_type(OrderType::Market)
+            .user_id(UserId(1))
+            .quantity(150)
+            .time_in_force(TimeInForce::Fok)
+            .build();
+
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_sell), 150);
+        assert_eq!(order_book.add_order(fill_or_kill_sell).unwrap().iter().map(|fill| fill.quantity).sum::<u64>(), 150);
+        assert_eq!(order_book.total_quantity(OrderSide::Buy), 50);
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_cancels_immediate_or_cancel_order_if_no_resting_order_exists() {
+    fn test_add_order_kills_notional_fill_or_kill_order_the_slippage_limit_cuts_short() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            max_slippage_ticks: 1,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::ImmediateOrCancel,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
-        };
-
-        let price_index = buy_order.price as usize;
+        assert!(order_book.add_order(Order::limit_sell(OrderId(0), UserId(0), 100, 10)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(0), 105, 10)).is_ok());
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        // $20 sizes to 19 across $1.00 and $1.05, but the sweep stops at $1.01 with only 10 reachable
+        let fill_or_kill_buy = Order::builder(OrderId(2), OrderSide::Buy)
+            .order_type(OrderType::MarketNotional(Decimal::new(20, 0)))
+            .user_id(UserId(1))
+            .quantity(0)
+            .time_in_force(TimeInForce::Fok)
+            .build();
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert!(order_book.asks[price_index].is_empty());
-        assert!(order_book.bids[price_index].is_empty());
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_buy), 0);
+        assert_eq!(order_book.preview(&fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.add_order(fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
         assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.total_quantity(OrderSide::Sell), 20);
+
+        // $8 sizes to 8, all of it at $1.00
+        let fill_or_kill_buy = Order::builder(OrderId(3), OrderSide::Buy)
+            .order_type(OrderType::MarketNotional(Decimal::new(8, 0)))
+            .user_id(UserId(1))
+            .quantity(0)
+            .time_in_force(TimeInForce::Fok)
+            .build();
+
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_buy), 8);
+        assert_eq!(order_book.add_order(fill_or_kill_buy).unwrap().iter().map(|fill| fill.quantity).sum::<u64>(), 8);
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_correctly_fills_fill_or_kill_order() {
+    fn test_add_order_kills_midpoint_fill_or_kill_order_short_of_contra_midpoint_quantity() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 600
-        };
-
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::FillOrKill,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 300
-        };
+        assert!(order_book.add_order(Order::limit_buy(OrderId(0), UserId(0), 4990, 100)).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(0), 5010, 100)).is_ok());
 
-        let price_index = sell_order.price as usize;
+        let midpoint_sell = Order::builder(OrderId(2), OrderSide::Sell)
+            .order_type(OrderType::MidpointPeg)
+            .user_id(UserId(1))
+            .quantity(30)
+            .build();
 
-        let add_order_result = order_book.add_order(sell_order.clone());
+        assert!(order_book.add_order(midpoint_sell).unwrap().is_empty());
 
-        sell_order.order_status = OrderStatus::Active;
+        let fill_or_kill_buy = Order::builder(OrderId(3), OrderSide::Buy)
+            .order_type(OrderType::MidpointPeg)
+            .user_id(UserId(2))
+            .quantity(100)
+            .time_in_force(TimeInForce::Fok)
+            .build();
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        assert_eq!(order_book.preview(&fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.add_order(fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert!(order_book.trade_history.is_empty());
+        assert_eq!(order_book.midpoint_orders.len(), 1);
+        assert_eq!(order_book.midpoint_orders[0].quantity, 30);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        let fill_or_kill_buy = Order::builder(OrderId(4), OrderSide::Buy)
+            .order_type(OrderType::MidpointPeg)
+            .user_id(UserId(2))
+            .quantity(30)
+            .time_in_force(TimeInForce::Fok)
+            .build();
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        let fills = order_book.add_order(fill_or_kill_buy).unwrap();
 
-        assert!(execute_fill_by_order_type_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
-        assert!(order_book.bids[price_index].is_empty());
-        assert_eq!(order_book.trade_history.len(), 1);
-        assert_eq!(order_book.trade_history[0].aggressive_order_id, buy_order.order_id);
-        assert_eq!(order_book.trade_history[0].resting_order_id, sell_order.order_id);
-        assert_eq!(order_book.trade_history[0].quantity, 300);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 5000);
+        assert_eq!(fills[0].quantity, 30);
+        assert!(order_book.midpoint_orders.is_empty());
     }
 
     #[test]
-    fn test_execute_fill_by_order_type_errors_cannot_fill_completely() {
+    fn test_pro_rata_matching_correctly_hands_all_or_none_share_to_the_rest_of_the_level() {
         let config = OrderBookConfig {
             min_price: 0,
             max_price: 10000,
             tick_size: 1,
-            queue_size: 100
+            queue_size: 100,
+            matching_policy: MatchingPolicy::ProRata { min_lot: 1 },
+            ..Default::default()
         };
         let mut order_book = OrderBook::new(config);
 
-        let mut sell_order = Order {
-            order_id: 0,
-            order_type: OrderType::Limit,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Sell,
-            user_id: 0,
-            price: 10000,
-            quantity: 300
-        };
-
-        let buy_order = Order {
-            order_id: 1,
-            order_type: OrderType::FillOrKill,
-            order_status: OrderStatus::PendingNew,
-            order_side: OrderSide::Buy,
-            user_id: 1,
-            price: 10000,
-            quantity: 600
-        };
-
-        let price_index = sell_order.price as usize;
-
-        let add_order_result = order_book.add_order(sell_order.clone());
-
-        sell_order.order_status = OrderStatus::Active;
+        let all_or_none_sell = Order::builder(OrderId(0), OrderSide::Sell)
+            .order_type(OrderType::AllOrNone)
+            .price(100)
+            .quantity(5)
+            .build();
+
+        assert!(order_book.add_order(all_or_none_sell).is_ok());
+        assert!(order_book.add_order(Order::limit_sell(OrderId(1), UserId(0), 100, 5)).is_ok());
+
+        // An even split gives each order 4, which the All or None order can't take, so only the limit order's 5
+        // is reachable
+        let fill_or_kill_buy = Order::builder(OrderId(2), OrderSide::Buy)
+            .user_id(UserId(1))
+            .price(100)
+            .quantity(8)
+            .time_in_force(TimeInForce::Fok)
+            .build();
+
+        assert_eq!(order_book.marketable_quantity(&fill_or_kill_buy), 0);
+        assert_eq!(order_book.preview(&fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert_eq!(order_book.add_order(fill_or_kill_buy).err(), Some(OrderBookError::CannotFillCompletely));
+        assert!(order_book.trade_history.is_empty());
 
-        let sell_order_index = order_book.index_mappings[&sell_order.order_id];
+        let limit_buy = Order::limit_buy(OrderId(3), UserId(1), 100, 8);
 
-        assert!(add_order_result.is_ok());
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.asks[price_index][0], sell_order_index);
+        assert_eq!(order_book.marketable_quantity(&limit_buy), 5);
 
-        let execute_fill_by_order_type_result = order_book.execute_fill_by_order_type(buy_order.clone());
+        let previewed_fills = order_book.preview(&limit_buy).unwrap();
+        let fills = order_book.add_order(limit_buy).unwrap();
 
-        assert!(execute_fill_by_order_type_result.is_err());
-        assert_eq!(execute_fill_by_order_type_result.err().unwrap(), OrderBookError::CannotFillCompletely);
-        assert_eq!(order_book.asks[price_index].len(), 1);
-        assert_eq!(order_book.order_ledger[sell_order_index].quantity, 300);
-        assert!(order_book.bids[price_index].is_empty());
-        assert!(order_book.trade_history.is_empty());
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].resting_order_id, OrderId(1));
+        assert_eq!(fills[0].quantity, 5);
+        assert_eq!(previewed_fills.iter().map(|fill| (fill.resting_order_id, fill.quantity)).collect::<Vec<_>>(), vec![(OrderId(1), 5)]);
+
+        // The remainder rests opposite an All or None order it can't fill, which isn't a cross
+        assert_eq!(order_book.total_quantity_at(OrderSide::Buy, 100), 3);
+        assert_eq!(order_book.total_quantity_at(OrderSide::Sell, 100), 5);
+        assert_eq!(order_book.validate(), Ok(()));
+        order_book.debug_assert_book_not_crossed();
     }
 
     #[test]
-    fn test_fill_limit_order_correctly_fills_buy_limit_order() {
+    fn benchmark() {
+        
 
     }
+}
 
-    #[test]
-    fn test_fill_limit_order_correctly_fills_sell_limit_order() {
+#[cfg(test)]
+mod invariant_tests {
 
-    }
+    use rand::{Rng, SeedableRng, rngs::StdRng};
 
-    #[test]
-    fn test_fill_market_order_correctly_fills_buy_market_order() {
+    use super::*;
 
-    }
+    fn random_order(rng: &mut StdRng, order_id: OrderId) -> Order {
+        let order_side = if rng.random_bool(0.5) {
+            OrderSide::Buy
+        }
+        else {
+            OrderSide::Sell
+        };
 
-    #[test]
-    fn test_fill_market_order_correctly_fills_sell_market_order() {
+        let order_type = match rng.random_range(0..20) {
+            0 | 1 => OrderType::Market,
+            2 | 3 => OrderType::AllOrNone,
+            4 => OrderType::MidpointPeg,
+            5 => OrderType::Stop,
+            6 => OrderType::StopLimit,
+            7 => OrderType::MarketIfTouched,
+            8 => OrderType::LimitIfTouched,
+            _ => OrderType::Limit
+        };
 
-    }
+        let stop_price = matches!(order_type, OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched)
+            .then(|| rng.random_range(80..120));
 
-    #[test]
-    fn test_fill_immediate_or_cancel_order_correctly_fills_immediate_or_cancel_order() {
+        let time_in_force = match rng.random_range(0..9) {
+            0 => TimeInForce::Ioc,
+            1 => TimeInForce::Fok,
+            _ => TimeInForce::Gtc
+        };
 
+        Order {
+            order_id,
+            order_type,
+            order_status: OrderStatus::PendingNew,
+            order_side,
+            user_id: UserId(rng.random_range(0..10)),
+            price: rng.random_range(80..120),
+            quantity: rng.random_range(1..500),
+            stop_price,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: rng.random_bool(0.1),
+            time_in_force
+        }
     }
 
-    #[test]
-    fn test_fill_fill_or_kill_order_correctly_fills_fill_or_kill_order() {
-
+    fn random_matching_policy(rng: &mut StdRng) -> MatchingPolicy {
+        match rng.random_range(0..3) {
+            0 => MatchingPolicy::PriceTime,
+            1 => MatchingPolicy::ProRata { min_lot: 1 },
+            _ => MatchingPolicy::ProRata { min_lot: 10 }
+        }
     }
 
-    #[test]
-    fn test_fill_fill_or_kill_order_errors_cannot_fill_completely() {
+    fn random_live_order_id(rng: &mut StdRng, order_book: &OrderBook) -> Option<OrderId> {
+        let mut live_order_ids: Vec<OrderId> = order_book.index_mappings.keys().copied().collect();
+        live_order_ids.sort_unstable();
 
+        if live_order_ids.is_empty() {
+            None
+        }
+        else {
+            Some(live_order_ids[rng.random_range(0..live_order_ids.len())])
+        }
     }
 
-    #[test]
-    fn test_match_order_against_book_correctly_matches_and_fills_buy_order() {
+    fn assert_book_invariants(order_book: &OrderBook) {
+        let actual_best_bid = (0..order_book.bids.len()).rev().find(|&i| !order_book.bids[i].is_empty());
+        let actual_best_ask = (0..order_book.asks.len()).find(|&i| !order_book.asks[i].is_empty());
 
-    }
+        assert_eq!(order_book.best_bid_index(), actual_best_bid, "occupied bid levels are stale");
+        assert_eq!(order_book.best_ask_index(), actual_best_ask, "occupied ask levels are stale");
+        assert_eq!(order_book.validate(), Ok(()));
 
-    #[test]
-    fn test_match_order_against_book_correctly_matches_and_fills_buy_order_excess_quantity() {
+        // Resting All or None orders can lock or cross the displayed prices, but no two levels that could trade
+        // with each other may overlap
+        let is_matchable = |queue: &VecDeque<usize>| queue.iter().any(|&idx| order_book.order_ledger[idx].order_type != OrderType::AllOrNone);
+        let best_matchable_bid = (0..order_book.bids.len()).rev().find(|&i| is_matchable(&order_book.bids[i]));
+        let best_matchable_ask = (0..order_book.asks.len()).find(|&i| is_matchable(&order_book.asks[i]));
 
-    }
+        if let (Some(best_bid), Some(best_ask)) = (best_matchable_bid, best_matchable_ask) {
+            assert!(best_bid < best_ask, "book is crossed: bid {best_bid} >= ask {best_ask}");
+        }
 
-    #[test]
-    fn test_match_order_against_book_correctly_matches_and_fills_sell_order() {
+        if order_book.order_ledger.iter().all(|(_, order)| order.order_type != OrderType::AllOrNone) {
+            assert!(!order_book.is_crossed() && !order_book.is_locked());
+        }
 
-    }
+        assert_eq!(order_book.index_mappings.len(), order_book.order_ledger.len());
+        assert_eq!(order_book.iter_orders().count(), order_book.order_ledger.len());
+        assert_eq!(order_book.user_orders.values().map(HashSet::len).sum::<usize>(), order_book.order_ledger.len());
+        for (user_id, order_ids) in &order_book.user_orders {
+            for order_id in order_ids {
+                assert_eq!(order_book.order_ledger[order_book.index_mappings[order_id]].user_id, *user_id);
+            }
+        }
+        for (order_id, &ledger_index) in &order_book.index_mappings {
+            let order = order_book.order_ledger.get(ledger_index).expect("mapping points at a dead ledger slot");
+            assert_eq!(order.order_id, *order_id);
+        }
 
-    #[test]
-    fn test_match_order_against_book_correctly_matches_and_fills_sell_order_excess_quantity() {
+        for (levels, level_quantities, hidden_quantities, total_quantity, side) in [
+            (&order_book.bids, &order_book.bid_quantities, &order_book.bid_hidden_quantities, order_book.total_bid_quantity, OrderSide::Buy),
+            (&order_book.asks, &order_book.ask_quantities, &order_book.ask_hidden_quantities, order_book.total_ask_quantity, OrderSide::Sell)
+        ] {
+            let mut side_quantity = 0u64;
+
+            for (price, queue) in levels.iter().enumerate() {
+                let mut level_quantity = 0u64;
+                let mut hidden_quantity = 0u64;
+                let mut seen_hidden = false;
+
+                for &ledger_index in queue {
+                    let order = order_book.order_ledger.get(ledger_index).expect("queue holds a dead ledger slot");
+                    assert_eq!(order.price as usize, price);
+                    assert_eq!(order.order_side, side);
+                    assert!(order.quantity > 0);
+                    assert!(order.hidden || !seen_hidden, "visible order queued behind a hidden one");
+                    seen_hidden |= order.hidden;
+                    level_quantity += order.quantity;
+
+                    if order.hidden {
+                        hidden_quantity += order.quantity;
+                    }
+                }
 
-    }
+                assert_eq!(level_quantities[price], level_quantity);
+                assert_eq!(hidden_quantities[price], hidden_quantity);
+                side_quantity += level_quantity;
+            }
 
-    #[test]
-    fn test_rest_remaining_limit_order_correctly_rests_buy_limit_order() {
+            assert_eq!(total_quantity, side_quantity);
+            assert_eq!(order_book.level_count(side), levels.iter().filter(|queue| !queue.is_empty()).count());
+        }
+    }
 
+    fn fill_keys(fills: &[OrderFill]) -> Vec<(OrderId, OrderId, u32, u64)> {
+        fills.iter()
+            .map(|fill| (fill.aggressive_order_id, fill.resting_order_id, fill.price, fill.quantity))
+            .collect()
     }
 
-    #[test]
-    fn test_rest_remaining_limit_order_correctly_rests_sell_limit_order() {
+    fn run_random_session(seed: u64, operations: usize) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 200,
+            tick_size: 1,
+            queue_size: 4,
+            event_log_capacity: 64,
+            matching_policy: random_matching_policy(&mut rng),
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::new(config);
+        let mut next_order_id = 0u64;
+
+        for _ in 0..operations {
+            let trades_before = order_book.trade_history.len();
+            let bids_before = order_book.total_quantity(OrderSide::Buy);
+            let asks_before = order_book.total_quantity(OrderSide::Sell);
+            let stop_orders_before = order_book.stop_orders.len();
+
+            match rng.random_range(0..10) {
+                0 | 1 => {
+                    if let Some(order_id) = random_live_order_id(&mut rng, &order_book) {
+                        assert!(order_book.cancel_order(order_id).is_ok());
+                    }
+                },
+                2 => {
+                    if let Some(order_id) = random_live_order_id(&mut rng, &order_book) {
+                        let mut replacement = random_order(&mut rng, order_id);
+                        replacement.order_type = OrderType::Limit;
+                        replacement.stop_price = None;
+                        replacement.order_side = order_book.order_ledger[order_book.index_mappings[&order_id]].order_side.clone();
+                        let _ = order_book.modify_order(order_id, replacement);
+                    }
+                },
+                _ => {
+                    let order = random_order(&mut rng, OrderId(next_order_id));
+                    next_order_id += 1;
 
-    }
+                    let order_id = order.order_id;
+                    let order_side = order.order_side.clone();
+                    let order_quantity = order.quantity;
+                    let is_midpoint = order.order_type == OrderType::MidpointPeg;
+                    let preview_result = order_book.preview(&order);
+                    let add_order_result = order_book.add_order(order);
+
+                    // The dry run predicts exactly what the real submission does
+                    match (preview_result, add_order_result) {
+                        (Ok(previewed_fills), Ok(fills)) => assert_eq!(fill_keys(&previewed_fills), fill_keys(&fills)),
+                        (Err(preview_err), Err(err)) => assert_eq!(preview_err, err),
+                        (preview_result, add_order_result) => panic!("preview {preview_result:?} disagrees with add_order {add_order_result:?}")
+                    }
 
-    #[test]
-    fn test_rest_remaining_limit_order_errors_non_limit_order_rest_attempt() {
+                    let new_trades = &order_book.trade_history[trades_before..];
+                    let filled_quantity: u64 = new_trades.iter()
+                        .filter(|fill| fill.aggressive_order_id == order_id)
+                        .map(|fill| fill.quantity)
+                        .sum();
 
-    }
+                    assert!(filled_quantity <= order_quantity);
 
-    #[test]
-    fn test_can_fill_completely_correctly_returns_true_for_buy_order_that_can_be_filled_completely() {
+                    // Stops the trades set off, and resting midpoint orders crossing once a mid appears, trade or
+                    // rest on their own account, so the book's totals only tie back to this order when it acted alone
+                    let stop_orders_fired = order_book.stop_orders.len() < stop_orders_before;
+                    let traded_alone = !stop_orders_fired && new_trades.iter().all(|fill| fill.aggressive_order_id == order_id);
+
+                    if traded_alone && is_midpoint {
+                        // Midpoint orders trade in the dark and never touch the lit levels
+                        assert_eq!(order_book.total_quantity(OrderSide::Buy), bids_before);
+                        assert_eq!(order_book.total_quantity(OrderSide::Sell), asks_before);
+                    }
+                    else if traded_alone {
+                        // Every unit filled by the aggressor was consumed from the opposing side
+                        let (own_before, own_after, opposing_before, opposing_after) = match order_side {
+                            OrderSide::Buy => (bids_before, order_book.total_quantity(OrderSide::Buy), asks_before, order_book.total_quantity(OrderSide::Sell)),
+                            OrderSide::Sell => (asks_before, order_book.total_quantity(OrderSide::Sell), bids_before, order_book.total_quantity(OrderSide::Buy))
+                        };
+
+                        assert_eq!(opposing_before - opposing_after, filled_quantity);
+                        assert!(own_after - own_before <= order_quantity - filled_quantity);
+                    }
+                }
+            }
 
+            assert_book_invariants(&order_book);
+        }
     }
 
     #[test]
-    fn test_can_fill_completely_correctly_returns_false_for_buy_order_with_remaining_quantity() {
+    fn test_replay_correctly_rebuilds_random_sessions() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 200,
+            tick_size: 1,
+            queue_size: 4,
+            event_log_capacity: 50_000,
+            ..Default::default()
+        };
 
-    }
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let config = OrderBookConfig { matching_policy: random_matching_policy(&mut rng), ..config.clone() };
+            let mut order_book = OrderBook::new(config.clone());
+
+            for next_order_id in 0..2000 {
+                match rng.random_range(0..10) {
+                    0 | 1 => {
+                        if let Some(order_id) = random_live_order_id(&mut rng, &order_book) {
+                            assert!(order_book.cancel_order(order_id).is_ok());
+                        }
+                    },
+                    2 => {
+                        if let Some(order_id) = random_live_order_id(&mut rng, &order_book) {
+                            let mut replacement = random_order(&mut rng, order_id);
+                            replacement.order_type = OrderType::Limit;
+                            replacement.stop_price = None;
+                            replacement.order_side = order_book.order_ledger[order_book.index_mappings[&order_id]].order_side.clone();
+                            let _ = order_book.modify_order(order_id, replacement);
+                        }
+                    },
+                    _ => {
+                        let _ = order_book.add_order(random_order(&mut rng, OrderId(next_order_id)));
+                    }
+                }
+            }
 
-    #[test]
-    fn test_can_fill_completely_correctly_returns_true_for_sell_order_that_can_be_filled_completely() {
+            let replayed = OrderBook::replay(config.clone(), order_book.events.make_contiguous()).unwrap();
 
+            assert_eq!(replayed.checksum(), order_book.checksum());
+            assert_eq!(replayed.depth(usize::MAX), order_book.depth(usize::MAX));
+            assert_eq!(fill_keys(&replayed.trade_history), fill_keys(&order_book.trade_history));
+            assert_eq!(replayed.order_count(), order_book.order_count());
+        }
     }
 
-    #[test]
-    fn test_can_fill_completely_correctly_returns_false_for_sell_order_with_remaining_quantity() {
-
+    // Operations per random session, e.g. ORDER_BOOK_FUZZ_OPERATIONS=100000 cargo test for a longer local run
+    fn random_session_operations() -> usize {
+        std::env::var("ORDER_BOOK_FUZZ_OPERATIONS").ok()
+            .and_then(|operations| operations.parse().ok())
+            .unwrap_or(2000)
     }
 
     #[test]
-    fn benchmark() {
-        
+    fn test_random_sessions_preserve_book_invariants() {
+        let operations = random_session_operations();
 
+        for seed in 0..20 {
+            run_random_session(seed, operations);
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,13 +1,36 @@
+use std::{fmt::Display, hash::Hash, sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard}};
+
 use dashmap::DashMap;
 
-use crate::{enums::{order_book_errors::OrderBookError, symbol::Symbol}, models::{order::Order, order_book_config::OrderBookConfig}, order_book::OrderBook};
+use crate::{enums::{order_book_errors::OrderBookError, symbol::Symbol}, models::{book_snapshot::{BestQuote, BookSnapshot}, order::Order, order_book_config::OrderBookConfig, order_fill::OrderFill, order_id::OrderId}, order_book::OrderBook};
+
+// Manager over the built-in `Symbol` enum universe.
+pub type SymbolOrderBookManager = OrderBookManager<Symbol>;
 
-pub struct OrderBookManager {
-    pub books: DashMap<Symbol, OrderBook>,
-    pub order_id_symbol_mapping: DashMap<u64, Symbol>
+// Locking model: the DashMap shard lock is only ever taken shared (get) to look a book up, and each book sits
+// behind its own RwLock. Writers to one symbol never block readers or writers on another, even within a shard,
+// and BBO/depth reads on a symbol only wait for writers on that same symbol.
+pub struct OrderBookManager<S>
+where
+    S: Eq + Hash + Clone + Display
+{
+    pub books: DashMap<S, RwLock<OrderBook>>,
+    pub order_id_symbol_mapping: DashMap<OrderId, S>
+}
+
+impl<S> Default for OrderBookManager<S>
+where
+    S: Eq + Hash + Clone + Display
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl OrderBookManager {
+impl<S> OrderBookManager<S>
+where
+    S: Eq + Hash + Clone + Display
+{
     pub fn new() -> Self {
         Self {
             books: DashMap::new(),
@@ -15,41 +38,794 @@ impl OrderBookManager {
         }
     }
 
-    pub fn add_symbol(&mut self, symbol: Symbol, config: OrderBookConfig) {
-        self.books.insert(symbol, OrderBook::new(config));
+    pub fn add_symbol(&mut self, symbol: S, config: OrderBookConfig) {
+        self.books.insert(symbol, RwLock::new(OrderBook::new(config)));
     }
 
-    pub fn add_order(&mut self, symbol: Symbol, order: Order) -> Result<(), OrderBookError> {
-        let mut book = self.books.get_mut(&symbol)
-            .ok_or(OrderBookError::SymbolNotFound(symbol.clone()))?;
+    // Tears the book down and forgets every order routed to it, handing the book back for inspection
+    pub fn remove_symbol(&self, symbol: S) -> Option<OrderBook> {
+        let (_, book) = self.books.remove(&symbol)?;
 
-        self.order_id_symbol_mapping.insert(order.order_id, symbol);
+        self.order_id_symbol_mapping.retain(|_, order_symbol| *order_symbol != symbol);
 
-        book.add_order(order)
+        Some(book.into_inner().unwrap_or_else(PoisonError::into_inner))
     }
 
-    pub fn cancel_order(&mut self, order_id: u64) -> Result<(), OrderBookError> {
+    pub fn add_order(&self, symbol: S, order: Order) -> Result<Vec<OrderFill>, OrderBookError> {
+        let entry = self.books.get(&symbol)
+            .ok_or(OrderBookError::SymbolNotFound(symbol.to_string()))?;
+
+        let order_id = order.order_id;
+
+        // The mapping is updated under the book's write lock, so a cancel can't route by it while it disagrees
+        // with the book
+        let mut book = write_book(&entry);
+        let result = book.add_order(order);
+
+        if let Ok(fills) = &result {
+            // Ids are only unique within a book, so leave the mapping alone if it now routes to another symbol
+            for fill in fills {
+                if !book.contains_order(fill.resting_order_id) {
+                    self.order_id_symbol_mapping.remove_if(&fill.resting_order_id, |_, order_symbol| *order_symbol == symbol);
+                }
+            }
+
+            // Only orders left resting can be canceled, so fully filled and IOC remainders aren't mapped
+            if book.contains_order(order_id) {
+                self.order_id_symbol_mapping.insert(order_id, symbol);
+            }
+        }
+
+        result
+    }
+
+    pub fn cancel_order(&self, order_id: OrderId) -> Result<(), OrderBookError> {
+        // Clone out of the mapping so no shard guard is held across the remove below
         let symbol = self.order_id_symbol_mapping.get(&order_id)
+            .map(|symbol| symbol.clone())
             .ok_or(OrderBookError::OrderNotFound)?;
 
-        let mut book = self.books.get_mut(&*symbol)
-            .ok_or(OrderBookError::SymbolNotFound(symbol.to_owned()))?;
+        let entry = self.books.get(&symbol)
+            .ok_or(OrderBookError::SymbolNotFound(symbol.to_string()))?;
+
+        let result = write_book(&entry).cancel_order(order_id);
+
+        // Resting orders filled by a triggered stop leave a mapping behind, so drop it once the book disowns the id
+        if matches!(result, Ok(()) | Err(OrderBookError::OrderNotFound)) {
+            self.order_id_symbol_mapping.remove(&order_id);
+        }
+
+        result
+    }
+
+    pub fn get_bbo(&self, symbol: S) -> Option<(Option<u32>, Option<u32>)> {
+        self.books.get(&symbol).map(|entry| {
+            let book = read_book(&entry);
+            (book.best_bid(), book.best_ask())
+        })
+    }
+
+    // None if the symbol is unknown or either side of its book is empty
+    pub fn get_bbo_with_size(&self, symbol: S) -> Option<(BestQuote, BestQuote)> {
+        let entry = self.books.get(&symbol)?;
+        let mut top_of_book = read_book(&entry).depth(1);
+
+        match (top_of_book.bids.pop(), top_of_book.asks.pop()) {
+            (Some(best_bid), Some(best_ask)) => Some((best_bid, best_ask)),
+            _ => None
+        }
+    }
+
+    pub fn depth(&self, symbol: S, levels: usize) -> Option<BookSnapshot> {
+        self.books.get(&symbol).map(|entry| read_book(&entry).depth(levels))
+    }
+
+    // Every level of the book, copied out under the read lock. The lock is released before this returns, so
+    // publishers can serialize and fan the snapshot out without holding up the symbol's writers
+    pub fn snapshot(&self, symbol: S) -> Option<BookSnapshot> {
+        self.depth(symbol, usize::MAX)
+    }
+
+    // Up to the last n fills for the symbol, oldest first
+    pub fn recent_trades(&self, symbol: S, n: usize) -> Option<Vec<OrderFill>> {
+        self.books.get(&symbol).map(|entry| {
+            let book = read_book(&entry);
+            let start_index = book.trade_history.len().saturating_sub(n);
+            book.trade_history[start_index..].to_vec()
+        })
+    }
+}
+
+// A book is only left poisoned by a panic mid-operation (e.g. the debug crossed-book assertion), so keep serving it
+fn read_book(book: &RwLock<OrderBook>) -> RwLockReadGuard<'_, OrderBook> {
+    book.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write_book(book: &RwLock<OrderBook>) -> RwLockWriteGuard<'_, OrderBook> {
+    book.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{enums::{order_side::OrderSide, order_status::OrderStatus, order_type::OrderType, time_in_force::TimeInForce}, models::{book_snapshot::DepthLevel, user_id::UserId}};
+
+    use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_add_order_correctly_routes_order_to_custom_string_symbol() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = OrderBookManager::<String>::new();
+        manager.add_symbol("BTC-USD".to_string(), config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = manager.add_order("BTC-USD".to_string(), buy_order);
+
+        assert!(add_order_result.is_ok());
+        assert_eq!(manager.get_bbo("BTC-USD".to_string()), Some((Some(5000), None)));
+        assert_eq!(*manager.order_id_symbol_mapping.get(&OrderId(0)).unwrap(), "BTC-USD");
+
+        let cancel_order_result = manager.cancel_order(OrderId(0));
+
+        assert!(cancel_order_result.is_ok());
+        assert!(manager.order_id_symbol_mapping.get(&OrderId(0)).is_none());
+    }
+
+    #[test]
+    fn test_add_order_errors_symbol_not_found_for_unknown_custom_symbol() {
+        let manager = OrderBookManager::<String>::new();
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let add_order_result = manager.add_order("ETH-USD".to_string(), buy_order);
+
+        assert_eq!(add_order_result.err().unwrap(), OrderBookError::SymbolNotFound("ETH-USD".to_string()));
+    }
+
+    #[test]
+    fn test_get_bbo_correctly_returns_bbo_for_symbol_enum_alias() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5001,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(manager.add_order(Symbol::AAPL, sell_order).is_ok());
+        assert_eq!(manager.get_bbo(Symbol::AAPL), Some((None, Some(5001))));
+        assert_eq!(manager.get_bbo(Symbol::MSFT), None);
+    }
+
+    #[test]
+    fn test_add_order_correctly_returns_fills_from_symbol_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        let buy_order = Order {
+            order_id: OrderId(1),
+            order_type: OrderType::Market,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            price: 0,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(manager.add_order(Symbol::AAPL, sell_order).unwrap().is_empty());
+
+        let fills = manager.add_order(Symbol::AAPL, buy_order).unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].aggressive_order_id, OrderId(1));
+        assert_eq!(fills[0].resting_order_id, OrderId(0));
+        assert_eq!(fills[0].aggressor_side, OrderSide::Buy);
+        assert_eq!(fills[0].aggressive_user_id, UserId(1));
+        assert_eq!(fills[0].resting_user_id, UserId(0));
+        assert_eq!(fills[0].quantity, 100);
+    }
+
+    #[test]
+    fn test_get_bbo_correctly_returns_none_for_empty_book() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        assert_eq!(manager.get_bbo(Symbol::AAPL), Some((None, None)));
+    }
+
+    #[test]
+    fn test_get_bbo_with_size_correctly_aggregates_top_level_quantity_and_order_count() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        let orders = [
+            (OrderSide::Buy, 4999, 100),
+            (OrderSide::Buy, 5000, 200),
+            (OrderSide::Buy, 5000, 300),
+            (OrderSide::Buy, 5000, 50),
+            (OrderSide::Sell, 5001, 400),
+            (OrderSide::Sell, 5001, 100),
+            (OrderSide::Sell, 5002, 900)
+        ];
+
+        for (order_id, (order_side, price, quantity)) in orders.into_iter().enumerate() {
+            let order = Order {
+                order_id: OrderId(order_id as u64),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(manager.add_order(Symbol::AAPL, order).is_ok());
+        }
+
+        let (best_bid, best_ask) = manager.get_bbo_with_size(Symbol::AAPL).unwrap();
+
+        assert_eq!(best_bid, BestQuote { price: 5000, quantity: 550, order_count: 3 });
+        assert_eq!(best_ask, BestQuote { price: 5001, quantity: 500, order_count: 2 });
+        assert_eq!(manager.books.get(&Symbol::AAPL).unwrap().read().unwrap().depth(1).bids[0], best_bid);
+    }
+
+    #[test]
+    fn test_get_bbo_with_size_returns_none_for_one_sided_book_and_unknown_symbol() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        let buy_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
 
-        book.cancel_order(order_id)?;
-        self.order_id_symbol_mapping.remove(&order_id);
+        assert!(manager.add_order(Symbol::AAPL, buy_order).is_ok());
+        assert_eq!(manager.get_bbo_with_size(Symbol::AAPL), None);
+        assert_eq!(manager.get_bbo_with_size(Symbol::MSFT), None);
+    }
+
+    #[test]
+    fn test_depth_and_recent_trades_correctly_isolate_books_per_symbol() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config.clone());
+        manager.add_symbol(Symbol::MSFT, config);
+
+        let orders = [
+            (Symbol::AAPL, 0, OrderType::Limit, OrderSide::Sell, 5000, 100),
+            (Symbol::AAPL, 1, OrderType::Limit, OrderSide::Sell, 5001, 200),
+            (Symbol::AAPL, 2, OrderType::Market, OrderSide::Buy, 0, 60),
+            (Symbol::AAPL, 3, OrderType::Market, OrderSide::Buy, 0, 10),
+            (Symbol::MSFT, 4, OrderType::Limit, OrderSide::Buy, 3000, 50),
+            (Symbol::MSFT, 5, OrderType::Limit, OrderSide::Sell, 3000, 20)
+        ];
+
+        for (symbol, order_id, order_type, order_side, price, quantity) in orders {
+            let order = Order {
+                order_id: OrderId(order_id),
+                order_type,
+                order_status: OrderStatus::PendingNew,
+                order_side,
+                user_id: UserId(0),
+                price,
+                quantity,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(manager.add_order(symbol, order).is_ok());
+        }
+
+        let aapl_depth = manager.depth(Symbol::AAPL, 5).unwrap();
+        let msft_depth = manager.depth(Symbol::MSFT, 5).unwrap();
+
+        assert!(aapl_depth.bids.is_empty());
+        assert_eq!(aapl_depth.asks, vec![
+            DepthLevel { price: 5000, quantity: 30, order_count: 1 },
+            DepthLevel { price: 5001, quantity: 200, order_count: 1 }
+        ]);
+        assert_eq!(msft_depth.bids, vec![DepthLevel { price: 3000, quantity: 30, order_count: 1 }]);
+        assert!(msft_depth.asks.is_empty());
+
+        let aapl_trades = manager.recent_trades(Symbol::AAPL, 1).unwrap();
+        let msft_trades = manager.recent_trades(Symbol::MSFT, 10).unwrap();
+
+        assert_eq!(aapl_trades.len(), 1);
+        assert_eq!(aapl_trades[0].aggressive_order_id, OrderId(3));
+        assert_eq!(manager.recent_trades(Symbol::AAPL, 10).unwrap().len(), 2);
+        assert_eq!(msft_trades.len(), 1);
+        assert_eq!(msft_trades[0].aggressive_order_id, OrderId(5));
+        assert_eq!(msft_trades[0].price, 3000);
 
-        Ok(())
+        assert!(manager.depth(Symbol::GOOGL, 5).is_none());
+        assert!(manager.recent_trades(Symbol::GOOGL, 5).is_none());
     }
 
-    pub fn get_bbo(&self, symbol: Symbol) -> Option<(Option<u32>, Option<u32>)> {
-        self.books.get(&symbol).map(|book| (
-            match book.best_bid_index {
-                Some(best_bid) => Some(best_bid as u32),
-                None => None
-            }, 
-            match book.best_ask_index {
-                Some(best_ask) => Some(best_ask as u32),
-                None => None
-            }))
+    #[test]
+    fn test_add_order_correctly_accepts_concurrent_orders_across_symbols() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let symbols = [Symbol::AAPL, Symbol::MSFT, Symbol::GOOGL, Symbol::AMZN];
+
+        let mut manager = SymbolOrderBookManager::new();
+        for symbol in &symbols {
+            manager.add_symbol(symbol.clone(), config.clone());
+        }
+        let manager = Arc::new(manager);
+
+        let handles: Vec<_> = symbols.iter().cloned().enumerate()
+            .map(|(thread_index, symbol)| {
+                let manager = Arc::clone(&manager);
+
+                thread::spawn(move || {
+                    for i in 0..100u64 {
+                        let order = Order {
+                            order_id: OrderId(thread_index as u64 * 1000 + i),
+                            order_type: OrderType::Limit,
+                            order_status: OrderStatus::PendingNew,
+                            order_side: OrderSide::Buy,
+                            user_id: UserId(thread_index as u32),
+                            price: 1000 + i as u32,
+                            quantity: 10,
+                            stop_price: None,
+                            reduce_only: false,
+                            filled_quantity: 0,
+                            hidden: false,
+                            time_in_force: TimeInForce::Gtc
+                        };
+
+                        manager.add_order(symbol.clone(), order).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(manager.order_id_symbol_mapping.len(), 400);
+
+        for (thread_index, symbol) in symbols.iter().enumerate() {
+            let entry = manager.books.get(symbol).unwrap();
+            let book = entry.read().unwrap();
+
+            assert_eq!(book.index_mappings.len(), 100);
+            assert_eq!(book.total_quantity(OrderSide::Buy), 1000);
+            assert_eq!(book.best_bid(), Some(1099));
+            assert!(*manager.order_id_symbol_mapping.get(&OrderId(thread_index as u64 * 1000)).unwrap() == *symbol);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_books_correctly_serve_concurrent_readers_and_writers_across_symbols() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let symbols = [Symbol::AAPL, Symbol::MSFT, Symbol::GOOGL, Symbol::AMZN];
+
+        let mut manager = SymbolOrderBookManager::new();
+        for symbol in &symbols {
+            manager.add_symbol(symbol.clone(), config.clone());
+        }
+        let manager = Arc::new(manager);
+        let writers_done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let manager = Arc::clone(&manager);
+                let writers_done = Arc::clone(&writers_done);
+                let symbols = symbols.clone();
+
+                thread::spawn(move || {
+                    while !writers_done.load(Ordering::Acquire) {
+                        for symbol in &symbols {
+                            if let Some((Some(best_bid), Some(best_ask))) = manager.get_bbo(symbol.clone()) {
+                                assert!(best_bid < best_ask);
+                            }
+
+                            let snapshot = manager.depth(symbol.clone(), 5).unwrap();
+                            assert!(snapshot.bids.windows(2).all(|levels| levels[0].price > levels[1].price));
+                            assert!(snapshot.asks.windows(2).all(|levels| levels[0].price < levels[1].price));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writers: Vec<_> = symbols.iter().cloned().enumerate()
+            .map(|(thread_index, symbol)| {
+                let manager = Arc::clone(&manager);
+
+                thread::spawn(move || {
+                    for i in 0..500u64 {
+                        let order_id = thread_index as u64 * 10_000 + i;
+                        let order_side = if i % 2 == 0 { OrderSide::Buy } else { OrderSide::Sell };
+
+                        let order = Order {
+                            order_id: OrderId(order_id),
+                            order_type: OrderType::Limit,
+                            order_status: OrderStatus::PendingNew,
+                            order_side,
+                            user_id: UserId(thread_index as u32),
+                            price: 1000 + (i % 7) as u32,
+                            quantity: 10,
+                            stop_price: None,
+                            reduce_only: false,
+                            filled_quantity: 0,
+                            hidden: false,
+                            time_in_force: TimeInForce::Gtc
+                        };
+
+                        manager.add_order(symbol.clone(), order).unwrap();
+
+                        if i % 5 == 0 {
+                            let _ = manager.cancel_order(OrderId(order_id));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        writers_done.store(true, Ordering::Release);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        for symbol in &symbols {
+            let entry = manager.books.get(symbol).unwrap();
+            let book = entry.read().unwrap();
+
+            assert_eq!(book.trade_history.iter().filter(|fill| fill.aggressive_order_id.0 / 10_000 != fill.resting_order_id.0 / 10_000).count(), 0);
+            assert_eq!(book.index_mappings.len(), book.order_ledger.len());
+        }
+    }
+
+    #[test]
+    fn test_remove_symbol_correctly_removes_book_and_its_order_mappings() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config.clone());
+        manager.add_symbol(Symbol::MSFT, config);
+
+        for (order_id, symbol) in [(0, Symbol::AAPL), (1, Symbol::AAPL), (2, Symbol::MSFT)] {
+            let buy_order = Order {
+                order_id: OrderId(order_id),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 5000,
+                quantity: 100,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            assert!(manager.add_order(symbol, buy_order).is_ok());
+        }
+
+        let removed_book = manager.remove_symbol(Symbol::AAPL).unwrap();
+
+        assert_eq!(removed_book.total_quantity(OrderSide::Buy), 200);
+        assert!(manager.remove_symbol(Symbol::AAPL).is_none());
+        assert_eq!(manager.order_id_symbol_mapping.len(), 1);
+
+        let buy_order = Order {
+            order_id: OrderId(3),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Buy,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert_eq!(manager.add_order(Symbol::AAPL, buy_order).err().unwrap(), OrderBookError::SymbolNotFound(Symbol::AAPL.to_string()));
+        assert_eq!(manager.cancel_order(OrderId(0)).err().unwrap(), OrderBookError::OrderNotFound);
+        assert!(manager.cancel_order(OrderId(2)).is_ok());
+    }
+
+    #[test]
+    fn test_snapshot_correctly_serves_consistent_snapshots_while_writer_adds_orders() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+        let manager = Arc::new(manager);
+        let writer_done = Arc::new(AtomicBool::new(false));
+
+        let reader = {
+            let manager = Arc::clone(&manager);
+            let writer_done = Arc::clone(&writer_done);
+
+            thread::spawn(move || {
+                let mut last_quantity = 0;
+                let mut last_order_count = 0;
+
+                loop {
+                    let finished = writer_done.load(Ordering::Acquire);
+                    let snapshot = manager.snapshot(Symbol::AAPL).unwrap();
+
+                    // Only resting bids are ever added, so each snapshot holds at least what the previous one did
+                    let quantity: u64 = snapshot.bids.iter().map(|level| level.quantity).sum();
+                    let order_count: usize = snapshot.bids.iter().map(|level| level.order_count).sum();
+
+                    assert!(snapshot.asks.is_empty());
+                    assert!(snapshot.bids.windows(2).all(|levels| levels[0].price > levels[1].price));
+                    assert_eq!(quantity, order_count as u64 * 10);
+                    assert!(quantity >= last_quantity);
+                    assert!(order_count >= last_order_count);
+
+                    last_quantity = quantity;
+                    last_order_count = order_count;
+
+                    if finished {
+                        return last_order_count;
+                    }
+                }
+            })
+        };
+
+        for i in 0..1000u64 {
+            let order = Order {
+                order_id: OrderId(i),
+                order_type: OrderType::Limit,
+                order_status: OrderStatus::PendingNew,
+                order_side: OrderSide::Buy,
+                user_id: UserId(0),
+                price: 1000 + (i % 11) as u32,
+                quantity: 10,
+                stop_price: None,
+                reduce_only: false,
+                filled_quantity: 0,
+                hidden: false,
+                time_in_force: TimeInForce::Gtc
+            };
+
+            manager.add_order(Symbol::AAPL, order).unwrap();
+        }
+        writer_done.store(true, Ordering::Release);
+
+        assert_eq!(reader.join().unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_add_order_correctly_maps_only_orders_left_resting() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config);
+
+        let sell_order = Order {
+            order_id: OrderId(0),
+            order_type: OrderType::Limit,
+            order_status: OrderStatus::PendingNew,
+            order_side: OrderSide::Sell,
+            user_id: UserId(0),
+            price: 5000,
+            quantity: 100,
+            stop_price: None,
+            reduce_only: false,
+            filled_quantity: 0,
+            hidden: false,
+            time_in_force: TimeInForce::Gtc
+        };
+
+        assert!(manager.add_order(Symbol::AAPL, sell_order.clone()).is_ok());
+        assert!(manager.add_order(Symbol::AAPL, Order { order_id: OrderId(1), quantity: 50, ..sell_order.clone() }).is_ok());
+        assert_eq!(manager.order_id_symbol_mapping.len(), 2);
+
+        // Fully fills itself and order 0, partially fills order 1
+        let market_order = Order {
+            order_id: OrderId(2),
+            order_type: OrderType::Market,
+            order_side: OrderSide::Buy,
+            user_id: UserId(1),
+            quantity: 120,
+            ..sell_order.clone()
+        };
+
+        assert_eq!(manager.add_order(Symbol::AAPL, market_order).unwrap().len(), 2);
+        assert!(manager.order_id_symbol_mapping.get(&OrderId(0)).is_none());
+        assert!(manager.order_id_symbol_mapping.get(&OrderId(2)).is_none());
+        assert!(*manager.order_id_symbol_mapping.get(&OrderId(1)).unwrap() == Symbol::AAPL);
+        assert_eq!(manager.cancel_order(OrderId(2)), Err(OrderBookError::OrderNotFound));
+
+        // Neither an IOC remainder nor a rejected order is mapped
+        let ioc_order = Order {
+            order_id: OrderId(3),
+            order_side: OrderSide::Buy,
+            quantity: 100,
+            time_in_force: TimeInForce::Ioc,
+            ..sell_order.clone()
+        };
+
+        assert_eq!(manager.add_order(Symbol::AAPL, ioc_order).unwrap().len(), 1);
+        assert!(manager.add_order(Symbol::AAPL, Order { order_id: OrderId(4), quantity: 0, ..sell_order }).is_err());
+        assert!(manager.order_id_symbol_mapping.is_empty());
+    }
+
+    #[test]
+    fn test_add_order_keeps_other_symbols_mapping_when_filling_a_shared_order_id() {
+        let config = OrderBookConfig {
+            min_price: 0,
+            max_price: 10000,
+            tick_size: 1,
+            queue_size: 100,
+            ..Default::default()
+        };
+        let mut manager = SymbolOrderBookManager::new();
+        manager.add_symbol(Symbol::AAPL, config.clone());
+        manager.add_symbol(Symbol::MSFT, config);
+
+        assert!(manager.add_order(Symbol::MSFT, Order::limit_sell(OrderId(5), UserId(0), 5000, 100)).is_ok());
+        assert!(manager.add_order(Symbol::AAPL, Order::limit_sell(OrderId(5), UserId(0), 5000, 100)).is_ok());
+
+        // Fills MSFT's order 5 while the mapping routes id 5 to AAPL
+        assert_eq!(manager.add_order(Symbol::MSFT, Order::limit_buy(OrderId(6), UserId(1), 5000, 100)).unwrap().len(), 1);
+
+        assert!(*manager.order_id_symbol_mapping.get(&OrderId(5)).unwrap() == Symbol::AAPL);
+        assert!(manager.cancel_order(OrderId(5)).is_ok());
+        assert_eq!(manager.get_bbo(Symbol::AAPL), Some((None, None)));
+    }
+}
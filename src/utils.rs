@@ -1,8 +1,63 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{SystemTime, UNIX_EPOCH}};
 
 pub fn get_timestamp() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
         .as_nanos()
-}
\ No newline at end of file
+}
+
+// Time source for fill and event timestamps, in nanoseconds since the epoch
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u128;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        get_timestamp()
+    }
+}
+
+// Manually driven clock for tests. Clones share the same time, so a test can keep a handle after giving one to the book
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<AtomicU64>
+}
+
+impl MockClock {
+    pub fn new(start: u64) -> Self {
+        MockClock { now: Arc::new(AtomicU64::new(start)) }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, nanos: u64) {
+        self.now.fetch_add(nanos, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u128 {
+        self.now.load(Ordering::SeqCst) as u128
+    }
+}
+
+// Copies a fixed-size field out of a buffer the caller has already length-checked
+pub(crate) fn read_field<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut field = [0; N];
+    field.copy_from_slice(&bytes[offset..offset + N]);
+    field
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// 64-bit FNV-1a, chosen over std's hashers because its output is fixed by spec and can be reproduced in another process
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}